@@ -3,11 +3,13 @@ pub mod parser;
 pub mod token;
 
 use lexer::tokenize;
-use parser::parse;
+use parser::parse_program;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::{fmt, fs};
+use crate::opcodes;
 
 #[derive(Debug)]
 pub enum AssemblerError {
@@ -44,16 +46,59 @@ impl fmt::Display for AssemblerError {
 
 impl Error for AssemblerError {}
 
-#[allow(dead_code, unused_variables)]
-pub fn assemble_program (input_path: &str, output_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Assembled program together with what is known about its source
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub origin: u16,
+    /// Source line (from 1) of the instruction starting at each address
+    pub lines: BTreeMap<u16, usize>,
+    pub labels: HashMap<String, u16>,
+}
+
+impl Program {
+    pub fn line_at(&self, addr: u16) -> Option<usize> {
+        self.lines.get(&addr).copied()
+    }
+
+    /// Address of every instruction byte, operands included
+    pub fn code_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.lines.keys().flat_map(|addr| {
+            let opcode = self.bytes[addr.wrapping_sub(self.origin) as usize];
+            let length = opcodes::get(opcode).map_or(1, |info| info.length) as u16;
+            (0..length).map(move |i| addr.wrapping_add(i))
+        })
+    }
+
+    /// Label defined at the address, the first in alphabetical order if there are several
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.labels.iter()
+            .filter(|(_, a)| **a == addr)
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+}
+
+/// Assembles a source file in memory
+pub fn assemble_source (input_path: &str) -> Result<Program, Box<dyn std::error::Error>> {
     let mut input = File::open(input_path)?;
     let mut contents = String::new();
     input.read_to_string(&mut contents)?;
-    let tokens = tokenize(&contents)?;
-    let machine_code = parse(&tokens)?;
+    assemble_text(&contents)
+}
+
+/// Assembles source text held in memory
+pub fn assemble_text (source: &str) -> Result<Program, Box<dyn std::error::Error>> {
+    let tokens = tokenize(source)?;
+    Ok(parse_program(&tokens)?)
+}
+
+#[allow(dead_code, unused_variables)]
+pub fn assemble_program (input_path: &str, output_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = assemble_source(input_path)?;
     fs::create_dir_all("bin/")?;
     let mut output = File::create(format!("bin/{output_name}.bin"))?;
-    output.write_all(machine_code.as_slice())?;
+    output.write_all(program.bytes.as_slice())?;
     Ok(())
 }
 
@@ -1,7 +1,8 @@
 use super::token::*;
-use crate::assembler::AssemblerError;
+use crate::assembler::{AssemblerError, Program};
+use crate::opcodes::{self, Operand};
 use core::slice::Iter;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::iter::Peekable;
 
 /// Represents the parser's current expectation for the next token
@@ -17,8 +18,12 @@ enum State {
     SrcReg,
     /// Expecting a destination register (encoded in bits 3-5)
     DestReg,
-    /// Expecting a register pair (encoded in bits 2-4)
+    /// Expecting a register pair (encoded in bits 4-5)
     RegPair,
+    /// Expecting a register pair or PSW (encoded in bits 4-5)
+    RegPairPsw,
+    /// Expecting register pair B or D (encoded in bit 4)
+    RegPairBD,
     /// Expecting an 8-bit immediate value
     Imm8,
     /// Expecting an 16-bit immediate value
@@ -44,6 +49,10 @@ struct Parser<'a> {
     unresolved_labels: Vec<(usize, String)>,
     /// Flag to indicate the need of allocating bytes for a label in the buffer
     alloc_lable: bool,
+    /// Source line of the instruction being assembled
+    inst_line: usize,
+    /// Source line of every instruction, by address
+    lines: BTreeMap<u16, usize>,
 }
 
 impl<'a> Parser<'a> {
@@ -58,13 +67,20 @@ impl<'a> Parser<'a> {
             labels: HashMap::new(),
             unresolved_labels: Vec::new(),
             alloc_lable: false,
+            inst_line: 0,
+            lines: BTreeMap::new(),
         }
     }
 
-    fn parse(mut self) -> Result<Vec<u8>, AssemblerError> {
+    fn parse(mut self) -> Result<Program, AssemblerError> {
         self.first_pass()?;
         self.second_pass()?;
-        Ok(self.buffer)
+        Ok(Program {
+            bytes: self.buffer,
+            origin: 0xC000,
+            lines: self.lines,
+            labels: self.labels.into_iter().map(|(name, addr)| (name, addr as u16)).collect(),
+        })
     }
 
     fn first_pass(&mut self) -> Result<(), AssemblerError> {
@@ -130,6 +146,8 @@ impl<'a> Parser<'a> {
             State::SrcReg => self.handle_register_arg(token, 0, &encode_arg3)?,
             State::DestReg => self.handle_register_arg(token, 3, &encode_arg3)?,
             State::RegPair => self.handle_register_arg(token, 4, &encode_arg2)?,
+            State::RegPairPsw => self.handle_register_arg(token, 4, &encode_arg2_psw)?,
+            State::RegPairBD => self.handle_register_arg(token, 4, &encode_arg1)?,
             State::Imm8 => self.handle_immediate(token, State::Imm8)?,
             State::Imm16 => self.handle_immediate(token, State::Imm16)?,
             State::RstImm => self.handle_register_arg(token, 3, &parse_arg)?,
@@ -156,6 +174,7 @@ impl<'a> Parser<'a> {
                     }
                     self.state_queue.extend(states);
                     self.next_bytes = op as u32;
+                    self.inst_line = token.line();
                 } else {
                     self.labels.insert(token.lexeme().to_string(), self.address);
                     self.state_queue.push_back(State::Colon);
@@ -289,6 +308,7 @@ impl<'a> Parser<'a> {
         }
 
         let old_len = self.buffer.len();
+        self.lines.insert(self.address as u16, self.inst_line);
 
         self.buffer.push(self.next_bytes as u8);
         self.next_bytes >>= 8;
@@ -310,6 +330,11 @@ impl<'a> Parser<'a> {
 }
 
 pub fn parse(tokens: &[Token]) -> Result<Vec<u8>, AssemblerError> {
+    Ok(Parser::new(tokens).parse()?.bytes)
+}
+
+/// Assembles the tokens keeping the source line of every instruction and the label addresses
+pub fn parse_program(tokens: &[Token]) -> Result<Program, AssemblerError> {
     Parser::new(tokens).parse()
 }
 
@@ -365,89 +390,51 @@ fn encode_arg2(token: &Token) -> Result<u8, AssemblerError> {
     }
 }
 
+fn encode_arg2_psw(token: &Token) -> Result<u8, AssemblerError> {
+    match token.lexeme().to_lowercase().as_str() {
+        "b" => Ok(0),
+        "d" => Ok(1),
+        "h" => Ok(2),
+        "psw" => Ok(3),
+        _ => Err(AssemblerError::SemanticError(
+            format!("unknown register \"{}\"", token.lexeme()),
+            Some(token.line()),
+            Some(token.column()),
+        )),
+    }
+}
+
+fn encode_arg1(token: &Token) -> Result<u8, AssemblerError> {
+    match token.lexeme().to_lowercase().as_str() {
+        "b" => Ok(0),
+        "d" => Ok(1),
+        _ => Err(AssemblerError::SemanticError(
+            format!("unknown register \"{}\"", token.lexeme()),
+            Some(token.line()),
+            Some(token.column()),
+        )),
+    }
+}
+
+/// Builds the base opcode and the expected operands of a mnemonic from the opcode table
 fn encode_inst(inst: &str) -> Option<(u8, Vec<State>)> {
-    use State::{Append, Comma, DestReg, Imm8, Imm16, RegPair, RstImm, SrcReg};
-    match inst.to_lowercase().as_str() {
-        "mov" => Some((0x40, vec![DestReg, Comma, SrcReg, Append(1)])),
-        "mvi" => Some((0x06, vec![DestReg, Comma, Imm8, Append(2)])),
-        "lxi" => Some((0x01, vec![RegPair, Comma, Imm16, Append(3)])),
-        "stax" => Some((0x02, vec![RegPair, Append(1)])),
-        "ldax" => Some((0x0A, vec![RegPair, Append(1)])),
-        "sta" => Some((0x32, vec![Imm16, Append(3)])),
-        "lda" => Some((0x3A, vec![Imm16, Append(3)])),
-        "shld" => Some((0x22, vec![Imm16, Append(3)])),
-        "lhld" => Some((0x2A, vec![Imm16, Append(3)])),
-        "xchg" => Some((0xEB, vec![Append(1)])),
-        "push" => Some((0xC5, vec![RegPair, Append(1)])),
-        "pop" => Some((0xC1, vec![RegPair, Append(1)])),
-        "xthl" => Some((0xE3, vec![Append(1)])),
-        "sphl" => Some((0xF9, vec![Append(1)])),
-        "inx" => Some((0x03, vec![RegPair, Append(1)])),
-        "dcx" => Some((0x0B, vec![RegPair, Append(1)])),
-        "jmp" => Some((0xC3, vec![Imm16, Append(3)])),
-        "jc" => Some((0xDA, vec![Imm16, Append(3)])),
-        "jnc" => Some((0xD2, vec![Imm16, Append(3)])),
-        "jz" => Some((0xCA, vec![Imm16, Append(3)])),
-        "jnz" => Some((0xC2, vec![Imm16, Append(3)])),
-        "jp" => Some((0xF2, vec![Imm16, Append(3)])),
-        "jm" => Some((0xFA, vec![Imm16, Append(3)])),
-        "jpe" => Some((0xEA, vec![Imm16, Append(3)])),
-        "jpo" => Some((0xE2, vec![Imm16, Append(3)])),
-        "pchl" => Some((0xE9, vec![Append(1)])),
-        "call" => Some((0xCD, vec![Imm16, Append(3)])),
-        "cc" => Some((0xDC, vec![Imm16, Append(3)])),
-        "cnc" => Some((0xD4, vec![Imm16, Append(3)])),
-        "cz" => Some((0xCC, vec![Imm16, Append(3)])),
-        "cnz" => Some((0xC4, vec![Imm16, Append(3)])),
-        "cp" => Some((0xF4, vec![Imm16, Append(3)])),
-        "cm" => Some((0xFC, vec![Imm16, Append(3)])),
-        "cpe" => Some((0xEC, vec![Imm16, Append(3)])),
-        "cpo" => Some((0xE4, vec![Imm16, Append(3)])),
-        "ret" => Some((0xC9, vec![Append(1)])),
-        "rc" => Some((0xD8, vec![Append(1)])),
-        "rnc" => Some((0xD0, vec![Append(1)])),
-        "rz" => Some((0xC8, vec![Append(1)])),
-        "rnz" => Some((0xC0, vec![Append(1)])),
-        "rp" => Some((0xF0, vec![Append(1)])),
-        "rm" => Some((0xF8, vec![Append(1)])),
-        "rpe" => Some((0xE8, vec![Append(1)])),
-        "rpo" => Some((0xE0, vec![Append(1)])),
-        "rst" => Some((0xC7, vec![RstImm, Append(1)])),
-        "in" => Some((0xDB, vec![Imm8, Append(2)])),
-        "out" => Some((0xD3, vec![Imm8, Append(2)])),
-        "inr" => Some((0x04, vec![DestReg, Append(1)])),
-        "dcr" => Some((0x05, vec![DestReg, Append(1)])),
-        "add" => Some((0x80, vec![SrcReg, Append(1)])),
-        "adc" => Some((0x88, vec![SrcReg, Append(1)])),
-        "adi" => Some((0xC6, vec![Imm8, Append(2)])),
-        "aci" => Some((0xCE, vec![Imm8, Append(2)])),
-        "dad" => Some((0x09, vec![RegPair, Append(1)])),
-        "sub" => Some((0x90, vec![SrcReg, Append(1)])),
-        "sbb" => Some((0x98, vec![SrcReg, Append(1)])),
-        "sui" => Some((0xD6, vec![Imm8, Append(2)])),
-        "sbi" => Some((0xDE, vec![Imm8, Append(2)])),
-        "ana" => Some((0xA0, vec![SrcReg, Append(1)])),
-        "xra" => Some((0xA8, vec![SrcReg, Append(1)])),
-        "ora" => Some((0xB0, vec![SrcReg, Append(1)])),
-        "cmp" => Some((0xB8, vec![SrcReg, Append(1)])),
-        "ani" => Some((0xE6, vec![Imm8, Append(2)])),
-        "xri" => Some((0xEE, vec![Imm8, Append(2)])),
-        "ori" => Some((0xF6, vec![Imm8, Append(2)])),
-        "cpi" => Some((0xFE, vec![Imm8, Append(2)])),
-        "rlc" => Some((0x07, vec![Append(1)])),
-        "rrc" => Some((0x0F, vec![Append(1)])),
-        "ral" => Some((0x17, vec![Append(1)])),
-        "rar" => Some((0x1F, vec![Append(1)])),
-        "cma" => Some((0x2F, vec![Append(1)])),
-        "stc" => Some((0x37, vec![Append(1)])),
-        "cmc" => Some((0x3F, vec![Append(1)])),
-        "daa" => Some((0x27, vec![Append(1)])),
-        "ei" => Some((0xFB, vec![Append(1)])),
-        "di" => Some((0xF3, vec![Append(1)])),
-        "nop" => Some((0x00, vec![Append(1)])),
-        "hlt" => Some((0x76, vec![Append(1)])),
-        "rim" => Some((0x20, vec![Append(1)])),
-        "sim" => Some((0x30, vec![Append(1)])),
-        _ => None,
+    let (opcode, info) = opcodes::find(inst)?;
+    let mut states = vec![];
+    for (i, operand) in info.operands.iter().enumerate() {
+        if i > 0 {
+            states.push(State::Comma);
+        }
+        states.push(match operand {
+            Operand::Dst => State::DestReg,
+            Operand::Src => State::SrcReg,
+            Operand::Pair => State::RegPair,
+            Operand::PairPsw => State::RegPairPsw,
+            Operand::PairBD => State::RegPairBD,
+            Operand::Rst => State::RstImm,
+            Operand::Imm8 => State::Imm8,
+            Operand::Imm16 => State::Imm16,
+        });
     }
+    states.push(State::Append(info.length));
+    Some((opcode, states))
 }
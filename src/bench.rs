@@ -0,0 +1,96 @@
+use std::{
+    error::Error,
+    hash::{DefaultHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Simulator,
+    StepOutcome,
+    assembler::{Program, assemble_text},
+    cpu::ExecMode,
+};
+
+// A program timed from start to HLT
+#[derive(Debug, Clone, Copy)]
+pub struct Benchmark {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+pub const BENCHMARKS: &[Benchmark] = &[
+    Benchmark { name: "loops", source: include_str!("../test/bench/loops.asm") },
+    Benchmark { name: "memcpy", source: include_str!("../test/bench/memcpy.asm") },
+    Benchmark { name: "checksum", source: include_str!("../test/bench/checksum.asm") },
+    Benchmark { name: "calls", source: include_str!("../test/bench/calls.asm") },
+    Benchmark { name: "patch", source: include_str!("../test/bench/patch.asm") },
+];
+
+pub fn find(name: &str) -> Option<&'static Benchmark> {
+    BENCHMARKS.iter().find(|bench| bench.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub mode: ExecMode,
+    pub runs: u32,
+    pub steps: u64,        // Per run
+    pub cycles: u64,       // Per run
+    pub elapsed: Duration, // Of all runs
+    pub digest: u64,       // Of the machine state at the end
+}
+
+impl BenchResult {
+    // Millions of instructions per second
+    pub fn mips(&self) -> f64 {
+        (self.steps * self.runs as u64) as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE) / 1e6
+    }
+}
+
+impl Benchmark {
+    // Runs the program over and over for at least `min_time`
+    pub fn run(&self, mode: ExecMode, min_time: Duration) -> Result<BenchResult, Box<dyn Error>> {
+        let program = assemble_text(self.source)?;
+        let mut result = BenchResult {
+            name: self.name,
+            mode,
+            runs: 0,
+            steps: 0,
+            cycles: 0,
+            elapsed: Duration::ZERO,
+            digest: 0,
+        };
+        while result.runs == 0 || result.elapsed < min_time {
+            let mut sim = machine(&program, mode);
+            let start = Instant::now();
+            let outcome = sim.run(u64::MAX);
+            result.elapsed += start.elapsed();
+            if outcome != StepOutcome::Finished {
+                return Err(format!("Benchmark {} stopped before finishing: {outcome:?}", self.name).into());
+            }
+            result.runs += 1;
+            result.steps = sim.get_step_count();
+            result.cycles = sim.get_cycles();
+            result.digest = digest(&sim);
+        }
+        Ok(result)
+    }
+}
+
+// A simulator set up as for a plain run, without the step-back history
+fn machine(program: &Program, mode: ExecMode) -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_history_depth(0);
+    sim.set_exec_mode(mode);
+    sim.load_program(program.clone());
+    sim
+}
+
+// Hash of everything a snapshot keeps, so both modes can be checked to end
+// in the same state
+fn digest(sim: &Simulator) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&sim.snapshot().to_bytes());
+    hasher.finish()
+}
@@ -1,13 +1,50 @@
 pub mod mem;
 pub mod io;
+pub mod intc;
+pub mod cycles;
+pub mod code;
+pub mod map;
+pub mod device;
+pub mod bus8085;
+
+pub use bus8085::Bus8085;
+use std::cell::RefCell;
+
 use crate::bus::io::Io;
 use crate::bus::mem::Memory;
+use crate::bus::intc::{InterruptController, FixedOpcode};
+use crate::bus::cycles::{BusCycle, CycleLog, MachineCycle};
+use crate::bus::code::{CachedCode, CodeWatch, CodeWrite};
+use crate::bus::map::{Access, MemFault, MemFaultKind, MemoryMap, OPEN_BUS};
+use crate::bus::device::{DeviceSlot, IoDevice, Space};
+use crate::cpu::Interrupts;
+use crate::snapshot::{Reader, SnapshotError, Writer};
 
 #[allow(dead_code, unused_variables)]
 #[derive(Debug)]
 pub struct Bus {
     mem: Memory,
-    io: Io,
+    io: Io,                            // Ports no device answers on
+    devices: Vec<DeviceSlot>,          // Later ones win where ranges overlap
+    mem_devices: bool,                 // Whether any are in memory space
+    intc: Box<dyn InterruptController>,
+    journal: Option<Journal>,
+    cycles: RefCell<Option<CycleLog>>, // Machine cycles, while recording
+    code: RefCell<Option<CodeWatch>>,  // Writes to code, while watching
+    cached: Option<CachedCode>,        // Opcodes in the block cache, while caching
+    map: Option<MemoryMap>,            // Flat RAM when None
+    mem_faults: RefCell<Vec<MemFault>>, // Accesses the map stops on, made during steps
+    in_step: bool,
+}
+
+// Previous values of the locations written while journaling, in write order,
+// and the state of each device before the step first reached it, by its
+// index on the bus
+#[derive(Debug, Default)]
+pub struct Journal {
+    pub memory: Vec<(u16, u8)>,
+    pub io: Vec<(u8, u8)>,
+    pub devices: Vec<(usize, Vec<u8>)>,
 }
 
 impl Default for Bus {
@@ -25,43 +62,206 @@ impl Bus {
             Ok(()) => (),
             Err(err) => panic!("{}", err),
         }
-        Bus { mem, io: Io::default() }
+        Bus { mem, ..Bus::new() }
     }
 
     pub fn new() -> Bus {
-        Bus { mem: Memory::default(), io: Io::default() }
+        Bus {
+            mem: Memory::default(),
+            io: Io::default(),
+            devices: Vec::new(),
+            mem_devices: false,
+            intc: Box::new(FixedOpcode::default()),
+            journal: None,
+            cycles: RefCell::new(None),
+            code: RefCell::new(None),
+            cached: None,
+            map: None,
+            mem_faults: RefCell::new(Vec::new()),
+            in_step: false,
+        }
+    }
+
+    fn log_cycle(&self, kind: MachineCycle, addr: u16, data: u8) {
+        if let Some(log) = self.cycles.borrow_mut().as_mut() {
+            log.push(kind, addr, data);
+        }
+    }
+
+    fn mem_fault(&self, addr: u16, kind: MemFaultKind) {
+        if self.in_step {
+            self.mem_faults.borrow_mut().push(MemFault { pc: 0, addr, kind });
+        }
+    }
+
+    fn slot(&self, space: Space, addr: u16) -> Option<&DeviceSlot> {
+        self.devices.iter().rev().find(|slot| slot.contains(space, addr))
+    }
+
+    // The device at an address, for an access that may change it
+    fn slot_mut(&mut self, space: Space, addr: u16) -> Option<&mut DeviceSlot> {
+        let index = self.devices.iter().rposition(|slot| slot.contains(space, addr))?;
+        self.journal_device(index);
+        Some(&mut self.devices[index])
+    }
+
+    // Saves the state of a device the first time a journaled step reaches it
+    fn journal_device(&mut self, index: usize) {
+        if let Some(journal) = &mut self.journal
+            && !journal.devices.iter().any(|(saved, _)| *saved == index)
+        {
+            let mut w = Writer::default();
+            self.devices[index].device.save_state(&mut w);
+            journal.devices.push((index, w.into_bytes()));
+        }
+    }
+
+    // Device in memory space at an address, checked before the memory map
+    fn mem_slot(&self, pos: u16) -> Option<&DeviceSlot> {
+        if !self.mem_devices {
+            return None;
+        }
+        self.slot(Space::Memory, pos)
     }
 
-    pub fn mem_get8(&self, pos:u16) -> u8 {
-        self.mem.get8(pos)
+    fn mem_slot_mut(&mut self, pos: u16) -> Option<&mut DeviceSlot> {
+        if !self.mem_devices {
+            return None;
+        }
+        self.slot_mut(Space::Memory, pos)
+    }
+
+    // Reads through the devices and the memory map
+    fn mem_read(&mut self, pos: u16) -> u8 {
+        if let Some(slot) = self.mem_slot_mut(pos) {
+            return slot.device.read(pos - slot.lower);
+        }
+        let Some(map) = &self.map else { return self.mem.get8(pos) };
+        match map.resolve(pos) {
+            (addr, Access::Ram | Access::Rom { .. }) => self.mem.get8(addr),
+            (_, Access::Unmapped { fault }) => {
+                if fault {
+                    self.mem_fault(pos, MemFaultKind::UnmappedRead);
+                }
+                OPEN_BUS
+            }
+        }
     }
 
-    pub fn mem_get16(&self, pos:u16) -> u16 {
-        self.mem.get16(pos)
+    // First machine cycle of an instruction
+    pub fn fetch_opcode(&mut self, pos:u16) -> u8 {
+        let value = self.mem_read(pos);
+        self.log_cycle(MachineCycle::OpcodeFetch, pos, value);
+        if let Some(code) = self.code.borrow_mut().as_mut() {
+            code.fetch(pos);
+        }
+        value
     }
 
-    pub fn mem_get16_reverse(&self, pos:u16) -> u16 {
-        self.mem.get16_reverse(pos)
+    pub fn mem_get8(&mut self, pos:u16) -> u8 {
+        let value = self.mem_read(pos);
+        self.log_cycle(MachineCycle::MemRead, pos, value);
+        value
+    }
+
+    // Reads what the CPU would, without a bus cycle, a fault or disturbing
+    // a device
+    pub fn mem_peek8(&self, pos:u16) -> u8 {
+        if let Some(slot) = self.mem_slot(pos) {
+            return slot.device.peek(pos - slot.lower);
+        }
+        match self.map.as_ref().map(|map| map.resolve(pos)) {
+            None => self.mem.get8(pos),
+            Some((addr, Access::Ram | Access::Rom { .. })) => self.mem.get8(addr),
+            Some((_, Access::Unmapped { .. })) => OPEN_BUS,
+        }
+    }
+
+    pub fn mem_get16(&mut self, pos:u16) -> u16 {
+        (self.mem_get8(pos) as u16) << 8 | self.mem_get8(pos.wrapping_add(1)) as u16
+    }
+
+    pub fn mem_get16_reverse(&mut self, pos:u16) -> u16 {
+        self.mem_get8(pos) as u16 | (self.mem_get8(pos.wrapping_add(1)) as u16) << 8
     }
 
     pub fn mem_set8(&mut self, pos:u16, value:u8) {
-        self.mem.set8(pos, value);
+        self.log_cycle(MachineCycle::MemWrite, pos, value);
+        if let Some(slot) = self.mem_slot_mut(pos) {
+            slot.device.write(pos - slot.lower, value);
+            // Code run from a device is taken to change only when written
+            if let Some(cached) = &mut self.cached {
+                cached.write(pos);
+            }
+            return;
+        }
+        let addr = match self.map.as_ref().map(|map| map.resolve(pos)) {
+            None => pos,
+            Some((addr, Access::Ram)) => addr,
+            Some((_, Access::Rom { fault })) => {
+                if fault {
+                    self.mem_fault(pos, MemFaultKind::RomWrite { value });
+                }
+                return;
+            }
+            Some((_, Access::Unmapped { fault })) => {
+                if fault {
+                    self.mem_fault(pos, MemFaultKind::UnmappedWrite { value });
+                }
+                return;
+            }
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.memory.push((pos, self.mem.get8(addr)));
+        }
+        if let Some(code) = self.code.get_mut() {
+            code.write(pos, self.mem.get8(addr), value);
+        }
+        // Writing the same byte leaves the decoded code as it was
+        if let Some(cached) = self.cached.as_mut().filter(|_| self.mem.get8(addr) != value) {
+            cached.write(addr);
+        }
+        self.mem.set8(addr, value);
+    }
+
+    // Puts a program in memory, ROM included, as a programmer would
+    pub fn mem_load(&mut self, origin:u16, bytes:&[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let pos = origin.wrapping_add(i as u16);
+            if let Some(slot) = self.mem_slot_mut(pos) {
+                slot.device.write(pos - slot.lower, *byte);
+                if let Some(cached) = &mut self.cached {
+                    cached.write(pos);
+                }
+                continue;
+            }
+            let addr = self.map.as_ref().map_or(pos, |map| map.resolve(pos).0);
+            if let Some(cached) = &mut self.cached {
+                cached.write(addr);
+            }
+            self.mem.set8(addr, *byte);
+        }
     }
 
     pub fn mem_set16(&mut self, pos:u16, value:u16) {
-        self.mem.set16(pos, value);
+        self.mem_set8(pos, (value >> 8) as u8);
+        self.mem_set8(pos.wrapping_add(1), value as u8);
     }
 
     pub fn mem_set16_reverse(&mut self, pos:u16, value:u16) {
-        self.mem.set16_reverse(pos, value);
+        self.mem_set8(pos, value as u8);
+        self.mem_set8(pos.wrapping_add(1), (value >> 8) as u8);
     }
 
     pub fn mem_clone(&self) -> Memory {
         self.mem.clone()
     }
 
-    pub fn mem_diff(&self, other:Memory) -> Vec<(u16, u8)> {
-        self.mem.diff(other)
+    pub fn mem_restore(&mut self, mem: Memory) {
+        self.mem = mem;
+        if let Some(cached) = &mut self.cached {
+            cached.write_all();
+        }
     }
 
     pub fn mem_dump(&self, filename:&str) -> std::io::Result<()> {
@@ -71,6 +271,9 @@ impl Bus {
 
     pub fn mem_read_dump(&mut self, filename:&str) -> std::io::Result<()> {
         self.mem.read_dump(filename)?;
+        if let Some(cached) = &mut self.cached {
+            cached.write_all();
+        }
         Ok(())
     }
 
@@ -99,12 +302,25 @@ impl Bus {
         self.io.clone()
     }
 
-    pub fn io_diff(&self, other: Io) -> Vec<(u8, u8)> {
-        self.io.diff(other)
+    pub fn io_restore(&mut self, io: Io) {
+        self.io = io;
+    }
+
+    pub fn io_get8(&mut self, pos:u8) -> u8 {
+        let value = match self.slot_mut(Space::Io, pos as u16) {
+            Some(slot) => slot.device.read(pos as u16 - slot.lower),
+            None => self.io.get8(pos),
+        };
+        self.log_cycle(MachineCycle::IoRead, u16::from_le_bytes([pos, pos]), value);
+        value
     }
 
-    pub fn io_get8(&self, pos:u8) -> u8 {
-        self.io.get8(pos)
+    // What IN would read, without disturbing a device
+    pub fn io_peek8(&self, pos:u8) -> u8 {
+        match self.slot(Space::Io, pos as u16) {
+            Some(slot) => slot.device.peek(pos as u16 - slot.lower),
+            None => self.io.get8(pos),
+        }
     }
 
     pub fn io_get16(&self, pos:u8) -> u16 {
@@ -116,15 +332,32 @@ impl Bus {
     }
 
     pub fn io_set8(&mut self, pos:u8, value:u8) {
-        self.io.set8(pos, value);
+        if let Some(journal) = &mut self.journal {
+            journal.io.push((pos, self.io.get8(pos)));
+        }
+        self.log_cycle(MachineCycle::IoWrite, u16::from_le_bytes([pos, pos]), value);
+        match self.slot_mut(Space::Io, pos as u16) {
+            Some(slot) => slot.device.write(pos as u16 - slot.lower, value),
+            None => self.io.set8(pos, value),
+        }
+    }
+
+    // Puts back a port from the journal. Devices are restored from their
+    // saved state instead.
+    pub fn io_undo8(&mut self, pos:u8, value:u8) {
+        if self.slot(Space::Io, pos as u16).is_none() {
+            self.io.set8(pos, value);
+        }
     }
 
     pub fn io_set16(&mut self, pos:u8, value:u16) {
-        self.io.set16(pos, value);
+        self.io_set8(pos, (value >> 8) as u8);
+        self.io_set8(pos.wrapping_add(1), value as u8);
     }
 
     pub fn io_set16_reverse(&mut self, pos:u8, value:u16) {
-        self.io.set16_reverse(pos, value);
+        self.io_set8(pos, value as u8);
+        self.io_set8(pos.wrapping_add(1), (value >> 8) as u8);
     }
 
     pub fn io_write_file(&self, filename:&str) -> std::io::Result<()> {
@@ -132,4 +365,291 @@ impl Bus {
         Ok(())
     }
 
+    pub fn set_interrupt_controller(&mut self, intc: Box<dyn InterruptController>) {
+        self.intc = intc;
+    }
+
+    // Starts recording the old value of every location written
+    pub fn begin_journal(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    // Stops recording and hands back what was written since begin_journal
+    pub fn end_journal(&mut self) -> Journal {
+        self.journal.take().unwrap_or_default()
+    }
+
+    // One INTA machine cycle, the interrupt controller drives the data bus
+    pub fn inta(&mut self) -> u8 {
+        let value = self.intc.inta();
+        self.log_cycle(MachineCycle::Inta, 0, value);
+        value
+    }
+
+    // Starts or stops recording machine cycles, dropping any not yet taken
+    pub fn record_cycles(&mut self, enable: bool) {
+        *self.cycles.get_mut() = enable.then(CycleLog::default);
+    }
+
+    pub fn is_recording_cycles(&self) -> bool {
+        self.cycles.borrow().is_some()
+    }
+
+    // Machine cycles recorded since the last call
+    pub fn take_cycles(&mut self) -> Vec<BusCycle> {
+        self.cycles.get_mut().as_mut().map(CycleLog::take).unwrap_or_default()
+    }
+
+    // Called by the CPU around each step so the accesses in between can be
+    // timed against the T-states it took
+    pub fn begin_step(&mut self) {
+        self.in_step = true;
+        if let Some(log) = self.cycles.get_mut() {
+            log.begin_step();
+        }
+        if let Some(code) = self.code.get_mut() {
+            code.begin_step();
+        }
+    }
+
+    pub fn end_step(&mut self, start: u64, end: u64) {
+        self.in_step = false;
+        if let Some(log) = self.cycles.get_mut() {
+            log.end_step(start, end);
+        }
+        if let Some(code) = self.code.get_mut() {
+            code.end_step();
+        }
+    }
+
+    // Starts or stops watching for writes to code
+    pub fn set_code_watch(&mut self, watch: Option<CodeWatch>) {
+        *self.code.get_mut() = watch;
+    }
+
+    pub fn is_watching_code(&self) -> bool {
+        self.code.borrow().is_some()
+    }
+
+    pub fn stops_on_code_writes(&self) -> bool {
+        self.code.borrow().as_ref().is_some_and(CodeWatch::is_stop)
+    }
+
+    pub fn code_watch(&mut self) -> Option<&mut CodeWatch> {
+        self.code.get_mut().as_mut()
+    }
+
+    // Writes to code since the last call, made by the instruction at pc
+    pub fn take_code_writes(&mut self, pc: u16) -> Vec<CodeWrite> {
+        self.code.get_mut().as_mut().map(|code| code.take(pc)).unwrap_or_default()
+    }
+
+    // Starts or stops tracking the opcodes in the block cache
+    pub fn track_cached_code(&mut self, enable: bool) {
+        self.cached = enable.then(CachedCode::new);
+    }
+
+    // Marks the memory behind an address as holding a cached opcode, and
+    // returns its address
+    pub fn cache_code(&mut self, pos: u16) -> u16 {
+        let addr = match self.map.as_ref() {
+            Some(map) if self.mem_slot(pos).is_none() => map.resolve(pos).0,
+            _ => pos,
+        };
+        if let Some(cached) = &mut self.cached {
+            cached.cache(addr);
+        }
+        addr
+    }
+
+    pub fn has_written_code(&self) -> bool {
+        self.cached.as_ref().is_some_and(CachedCode::has_writes)
+    }
+
+    // Cached opcodes changed since the last call
+    pub fn take_written_code(&mut self) -> Vec<u16> {
+        self.cached.as_mut().map(CachedCode::take).unwrap_or_default()
+    }
+
+    // Replaces the memory map, None for flat RAM
+    pub fn set_memory_map(&mut self, map: Option<MemoryMap>) {
+        self.map = map;
+        // Any address may now reach different memory
+        if let Some(cached) = &mut self.cached {
+            cached.write_all();
+        }
+    }
+
+    pub fn memory_map(&self) -> Option<&MemoryMap> {
+        self.map.as_ref()
+    }
+
+    pub fn has_mem_faults(&self) -> bool {
+        !self.mem_faults.borrow().is_empty()
+    }
+
+    // Faulting accesses since the last call, made by the instruction at pc
+    pub fn take_mem_faults(&mut self, pc: u16) -> Vec<MemFault> {
+        let mut faults = std::mem::take(self.mem_faults.get_mut());
+        for fault in &mut faults {
+            fault.pc = pc;
+        }
+        faults
+    }
+
+    // Connects a device to the ports lower..=upper
+    pub fn add_io_device(&mut self, lower: u8, upper: u8, device: Box<dyn IoDevice>) {
+        self.devices.push(DeviceSlot { space: Space::Io, lower: lower as u16, upper: upper as u16, device });
+    }
+
+    // Connects a device to the memory addresses lower..=upper, in place of
+    // whatever the memory map puts there
+    pub fn add_mem_device(&mut self, lower: u16, upper: u16, device: Box<dyn IoDevice>) {
+        self.devices.push(DeviceSlot { space: Space::Memory, lower, upper, device });
+        self.mem_devices = true;
+        // Code cached from the memory underneath is no longer reachable
+        if let Some(cached) = &mut self.cached {
+            cached.write_all();
+        }
+    }
+
+    pub fn has_devices(&self) -> bool {
+        !self.devices.is_empty()
+    }
+
+    // Lets the devices run for the T-states a step took
+    pub fn tick_devices(&mut self, cycles: u64) {
+        for index in 0..self.devices.len() {
+            if !self.devices[index].device.is_idle() {
+                self.journal_device(index);
+            }
+            self.devices[index].device.tick(cycles);
+        }
+    }
+
+    // Interrupt inputs driven by the devices
+    pub fn irq_lines(&self) -> Interrupts {
+        self.devices.iter().fold(Interrupts::default(), |lines, slot| lines.or(slot.device.irq()))
+    }
+
+    // State of every device, in the order they were added. Empty without
+    // devices.
+    pub fn devices_state(&self) -> Vec<u8> {
+        if self.devices.is_empty() {
+            return Vec::new();
+        }
+        let mut w = Writer::default();
+        w.u32(self.devices.len() as u32);
+        for slot in &self.devices {
+            let mut state = Writer::default();
+            slot.device.save_state(&mut state);
+            w.bytes(&state.into_bytes());
+        }
+        w.into_bytes()
+    }
+
+    pub fn devices_restore(&mut self, state: &[u8]) -> Result<(), SnapshotError> {
+        let mut r = Reader::new(state);
+        let count = if state.is_empty() { 0 } else { r.u32()? as usize };
+        if count != self.devices.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "state saved for {count} devices, the bus has {}", self.devices.len())));
+        }
+        for slot in &mut self.devices {
+            slot.device.load_state(&mut Reader::new(r.bytes()?))?;
+        }
+        Ok(())
+    }
+
+    // Puts back the devices a step reached, from the journal
+    pub fn devices_undo(&mut self, states: &[(usize, Vec<u8>)]) -> Result<(), SnapshotError> {
+        for (index, state) in states {
+            let slot = self.devices.get_mut(*index)
+                .ok_or_else(|| SnapshotError::Invalid(format!("no device {index} on the bus")))?;
+            slot.device.load_state(&mut Reader::new(state))?;
+        }
+        Ok(())
+    }
+}
+
+impl Bus8085 for Bus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem_get8(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.mem_set8(addr, value);
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.io_get8(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.io_set8(port, value);
+    }
+
+    fn inta(&mut self) -> u8 {
+        Bus::inta(self)
+    }
+
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.fetch_opcode(addr)
+    }
+
+    fn begin_step(&mut self) {
+        Bus::begin_step(self);
+    }
+
+    fn end_step(&mut self, start: u64, end: u64) {
+        Bus::end_step(self, start, end);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.tick_devices(cycles);
+    }
+
+    fn irq_lines(&self) -> Interrupts {
+        Bus::irq_lines(self)
+    }
+}
+
+// The bus as the cached mode sees it when memory is flat RAM and nothing
+// records or watches it: memory is reached directly, the rest through the bus
+pub(crate) struct FlatRam<'a>(pub(crate) &'a mut Bus);
+
+impl Bus {
+    pub(crate) fn is_flat_ram(&self) -> bool {
+        self.map.is_none()
+            && !self.mem_devices
+            && self.journal.is_none()
+            && self.code.borrow().is_none()
+            && self.cycles.borrow().is_none()
+    }
+}
+
+impl Bus8085 for FlatRam<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.mem.get8(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let bus = &mut *self.0;
+        if let Some(cached) = bus.cached.as_mut().filter(|_| bus.mem.get8(addr) != value) {
+            cached.write(addr);
+        }
+        bus.mem.set8(addr, value);
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.0.io_get8(port)
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.0.io_set8(port, value);
+    }
+
+    fn inta(&mut self) -> u8 {
+        self.0.inta()
+    }
 }
@@ -0,0 +1,38 @@
+use crate::cpu::Interrupts;
+
+// Everything the CPU does outside itself. `Bus` is the simulator's own;
+// any other, such as a test fixture, a logging wrapper or a proxy to real
+// hardware, can drive the core instead. Only the first five are needed, the
+// rest have defaults for a bus that doesn't care.
+pub trait Bus8085 {
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, value: u8);
+
+    fn io_read(&mut self, port: u8) -> u8;
+
+    fn io_write(&mut self, port: u8, value: u8);
+
+    // One INTA machine cycle after an accepted INTR, the byte placed on the
+    // data bus
+    fn inta(&mut self) -> u8;
+
+    // Opcode fetch, the first machine cycle of an instruction
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    // Called around each step with the cycle count before and after
+    fn begin_step(&mut self) {}
+
+    fn end_step(&mut self, _start: u64, _end: u64) {}
+
+    // Called after each step with the T-states it took, before end_step
+    fn tick(&mut self, _cycles: u64) {}
+
+    // Interrupt inputs driven from the bus side, sampled once per step
+    // along with the CPU's own pins
+    fn irq_lines(&self) -> Interrupts {
+        Interrupts::default()
+    }
+}
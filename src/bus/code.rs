@@ -0,0 +1,174 @@
+use std::fmt;
+
+// Why a written address counts as part of the instruction stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeOrigin {
+    Assembled, // The assembler emitted an instruction byte there
+    Fetched,   // It was fetched as an opcode
+}
+
+// A write into the instruction stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub pc: u16,   // Instruction that wrote it
+    pub addr: u16,
+    pub old: u8,
+    pub value: u8,
+    pub origin: CodeOrigin,
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origin = match self.origin {
+            CodeOrigin::Assembled => "assembled as code",
+            CodeOrigin::Fetched => "executed as an opcode",
+        };
+        write!(f, "Write to code: instruction at {:04X} changed {:04X} from {:02X} to {:02X} ({origin})",
+            self.pc, self.addr, self.old, self.value)
+    }
+}
+
+// Called with every write to code, boxed so the simulator can hold any closure
+pub struct CodeHook(pub Box<dyn FnMut(&CodeWrite)>);
+
+impl fmt::Debug for CodeHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CodeHook")
+    }
+}
+
+// Marks the addresses holding code and collects the writes made to them
+// during steps. Restoring memory outside a step is not reported.
+#[derive(Debug, Clone)]
+pub struct CodeWatch {
+    assembled: Vec<bool>,
+    fetched: Vec<bool>,
+    writes: Vec<CodeWrite>,
+    stop: bool,    // Stop the program on a write instead of only reporting it
+    in_step: bool,
+}
+
+impl Default for CodeWatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeWatch {
+    pub fn new() -> CodeWatch {
+        CodeWatch {
+            assembled: vec![false; 0x10000],
+            fetched: vec![false; 0x10000],
+            writes: Vec::new(),
+            stop: false,
+            in_step: false,
+        }
+    }
+
+    pub fn with_stop(mut self, stop: bool) -> CodeWatch {
+        self.stop = stop;
+        self
+    }
+
+    pub fn is_stop(&self) -> bool {
+        self.stop
+    }
+
+    // Forgets the assembled code, for a new program
+    pub fn clear_assembled(&mut self) {
+        self.assembled.fill(false);
+    }
+
+    pub fn mark_assembled(&mut self, addr: u16) {
+        self.assembled[addr as usize] = true;
+    }
+
+    pub fn fetch(&mut self, addr: u16) {
+        self.fetched[addr as usize] = true;
+    }
+
+    pub fn origin(&self, addr: u16) -> Option<CodeOrigin> {
+        if self.assembled[addr as usize] {
+            Some(CodeOrigin::Assembled)
+        } else if self.fetched[addr as usize] {
+            Some(CodeOrigin::Fetched)
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, old: u8, value: u8) {
+        if !self.in_step {
+            return;
+        }
+        if let Some(origin) = self.origin(addr) {
+            self.writes.push(CodeWrite { pc: 0, addr, old, value, origin });
+        }
+    }
+
+    pub fn begin_step(&mut self) {
+        self.in_step = true;
+    }
+
+    pub fn end_step(&mut self) {
+        self.in_step = false;
+    }
+
+    // Writes since the last call, made by the instruction at pc
+    pub fn take(&mut self, pc: u16) -> Vec<CodeWrite> {
+        let mut writes = std::mem::take(&mut self.writes);
+        for write in &mut writes {
+            write.pc = pc;
+        }
+        writes
+    }
+}
+
+// Opcodes of cached instructions, for the block cache, by the address of
+// the memory behind them once mirrors are resolved. Operands are read when
+// an instruction runs, so writes to them don't matter. A write that changes
+// a cached opcode is kept until the cache takes it and drops its blocks.
+#[derive(Debug, Clone)]
+pub struct CachedCode {
+    cached: Vec<bool>,
+    written: Vec<u16>,
+}
+
+impl Default for CachedCode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CachedCode {
+    pub fn new() -> CachedCode {
+        CachedCode { cached: vec![false; 0x10000], written: Vec::new() }
+    }
+
+    pub fn cache(&mut self, addr: u16) {
+        self.cached[addr as usize] = true;
+    }
+
+    // Address of the memory written, not the one the CPU used
+    pub fn write(&mut self, addr: u16) {
+        if self.cached[addr as usize] {
+            self.cached[addr as usize] = false;
+            self.written.push(addr);
+        }
+    }
+
+    // Every cached opcode, for when all of memory is replaced
+    pub fn write_all(&mut self) {
+        for addr in 0..=0xFFFF {
+            self.write(addr);
+        }
+    }
+
+    pub fn has_writes(&self) -> bool {
+        !self.written.is_empty()
+    }
+
+    pub fn take(&mut self) -> Vec<u16> {
+        std::mem::take(&mut self.written)
+    }
+}
@@ -0,0 +1,125 @@
+// Machine cycles the CPU runs on the bus. Each instruction starts with an
+// opcode fetch (or INTA cycles when an interrupt controller supplies it),
+// followed by one 3 T-state cycle per byte read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineCycle {
+    OpcodeFetch,
+    MemRead,
+    MemWrite,
+    IoRead,
+    IoWrite,
+    Inta,
+    BusIdle, // Internal work (DAD, interrupt entry, halted) with no transfer
+}
+
+impl MachineCycle {
+    // Status lines as (IO/M, S1, S0)
+    pub fn status(&self) -> (bool, bool, bool) {
+        match self {
+            MachineCycle::OpcodeFetch => (false, true, true),
+            MachineCycle::MemRead => (false, true, false),
+            MachineCycle::MemWrite => (false, false, true),
+            MachineCycle::IoRead => (true, true, false),
+            MachineCycle::IoWrite => (true, false, true),
+            MachineCycle::Inta => (true, true, true),
+            MachineCycle::BusIdle => (false, false, false),
+        }
+    }
+
+    pub fn is_read(&self) -> bool {
+        matches!(self, MachineCycle::OpcodeFetch | MachineCycle::MemRead | MachineCycle::IoRead | MachineCycle::Inta)
+    }
+
+    pub fn is_write(&self) -> bool {
+        matches!(self, MachineCycle::MemWrite | MachineCycle::IoWrite)
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            MachineCycle::OpcodeFetch => "OF",
+            MachineCycle::MemRead => "MR",
+            MachineCycle::MemWrite => "MW",
+            MachineCycle::IoRead => "IOR",
+            MachineCycle::IoWrite => "IOW",
+            MachineCycle::Inta => "INA",
+            MachineCycle::BusIdle => "BI",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusCycle {
+    pub kind: MachineCycle,
+    pub addr: u16,    // IO cycles carry the port on both address halves
+    pub data: u8,
+    pub start: u64,   // T-state at which the cycle begins (T1)
+    pub t_states: u8,
+}
+
+// Cycles recorded while the bus is being watched. Accesses made by the CPU
+// during a step are logged as they happen and get their timing once the CPU
+// knows how long the step took. Reads from outside a step (displays,
+// debuggers) are not bus cycles and are ignored.
+#[derive(Debug, Default)]
+pub struct CycleLog {
+    cycles: Vec<BusCycle>,
+    timed: usize,  // cycles[..timed] already have their timing
+    in_step: bool,
+}
+
+impl CycleLog {
+    pub fn push(&mut self, kind: MachineCycle, addr: u16, data: u8) {
+        if self.in_step {
+            self.cycles.push(BusCycle { kind, addr, data, start: 0, t_states: 0 });
+        }
+    }
+
+    pub fn begin_step(&mut self) {
+        self.in_step = true;
+    }
+
+    // Spreads the T-states of a step over the cycles logged during it. Every
+    // transfer takes 3 T-states and the first fetch gets the rest, up to 6.
+    // Anything beyond that is internal work, shown as bus idle cycles.
+    pub fn end_step(&mut self, start: u64, end: u64) {
+        self.in_step = false;
+        let mut step = self.cycles.split_off(self.timed);
+        let lead = step.first()
+            .is_some_and(|c| matches!(c.kind, MachineCycle::OpcodeFetch | MachineCycle::Inta)) as usize;
+        let transfers = (step.len() - lead) as u64 * 3;
+        let mut rest = (end - start).saturating_sub(transfers);
+
+        if lead == 1 {
+            let t_states = if rest <= 6 { rest } else { 4 };
+            step[0].t_states = t_states as u8;
+            rest -= t_states;
+        }
+        for cycle in step.iter_mut().skip(lead) {
+            cycle.t_states = 3;
+        }
+
+        let mut idle = Vec::new();
+        let addr = step.first().map_or(0, |c| c.addr);
+        while rest > 0 {
+            let t_states = if rest < 6 { rest } else { 3 };
+            idle.push(BusCycle { kind: MachineCycle::BusIdle, addr, data: 0, start: 0, t_states: t_states as u8 });
+            rest -= t_states;
+        }
+        step.splice(lead..lead, idle);
+
+        let mut t = start;
+        for cycle in step.iter_mut() {
+            cycle.start = t;
+            t += cycle.t_states as u64;
+        }
+        self.cycles.append(&mut step);
+        self.timed = self.cycles.len();
+    }
+
+    // Hands over the timed cycles, keeping any of a step still in progress
+    pub fn take(&mut self) -> Vec<BusCycle> {
+        let rest = self.cycles.split_off(self.timed);
+        self.timed = 0;
+        std::mem::replace(&mut self.cycles, rest)
+    }
+}
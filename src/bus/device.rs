@@ -0,0 +1,61 @@
+use std::fmt::Debug;
+
+use crate::cpu::Interrupts;
+use crate::snapshot::{Reader, SnapshotError, Writer};
+
+// A peripheral on a range of IO ports or memory addresses. Addresses are
+// counted from the start of the range, so the same device can be placed at
+// any base address, in either space.
+pub trait IoDevice: Debug {
+    // IN, or a memory read, which may change the device's state
+    fn read(&mut self, addr: u16) -> u8;
+
+    // What `read` would return, without disturbing the device, for views
+    fn peek(&self, addr: u16) -> u8;
+
+    // OUT, or a memory write
+    fn write(&mut self, addr: u16, value: u8);
+
+    // Called after every step with the T-states it took
+    fn tick(&mut self, _cycles: u64) {}
+
+    // Whether `tick` would leave the state as it is, so a step that doesn't
+    // otherwise reach the device needn't save it for undo
+    fn is_idle(&self) -> bool {
+        false
+    }
+
+    // Interrupt inputs the device is driving high
+    fn irq(&self) -> Interrupts {
+        Interrupts::default()
+    }
+
+    // State kept in snapshots, checkpoints and the step-back history
+    fn save_state(&self, _w: &mut Writer) {}
+
+    fn load_state(&mut self, _r: &mut Reader) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+// Which addresses a device answers on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Io,
+    Memory,
+}
+
+// A device and the addresses it answers on
+#[derive(Debug)]
+pub(super) struct DeviceSlot {
+    pub space: Space,
+    pub lower: u16,
+    pub upper: u16,
+    pub device: Box<dyn IoDevice>,
+}
+
+impl DeviceSlot {
+    pub fn contains(&self, space: Space, addr: u16) -> bool {
+        self.space == space && (self.lower..=self.upper).contains(&addr)
+    }
+}
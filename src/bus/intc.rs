@@ -0,0 +1,51 @@
+use std::fmt::Debug;
+
+// Device answering the INTA machine cycles that follow an accepted INTR.
+// The 8085 expects either an RST opcode (one INTA cycle) or a CALL opcode
+// followed by the two address bytes (three INTA cycles).
+pub trait InterruptController: Debug {
+    // Byte placed on the data bus during one INTA cycle
+    fn inta(&mut self) -> u8;
+}
+
+// Always answers with the same opcode. With nothing driving the data bus the
+// pull-ups read as 0xFF, which is RST 7.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedOpcode(pub u8);
+
+impl Default for FixedOpcode {
+    fn default() -> Self {
+        FixedOpcode(0xFF)
+    }
+}
+
+impl InterruptController for FixedOpcode {
+    fn inta(&mut self) -> u8 {
+        self.0
+    }
+}
+
+// Answers with CALL addr, spread over three INTA cycles
+#[derive(Debug, Clone, Copy)]
+pub struct CallVector {
+    addr: u16,
+    cycle: u8,
+}
+
+impl CallVector {
+    pub fn new(addr: u16) -> CallVector {
+        CallVector { addr, cycle: 0 }
+    }
+}
+
+impl InterruptController for CallVector {
+    fn inta(&mut self) -> u8 {
+        let byte = match self.cycle {
+            0 => 0xCD,
+            1 => self.addr as u8,
+            _ => (self.addr >> 8) as u8,
+        };
+        self.cycle = (self.cycle + 1) % 3;
+        byte
+    }
+}
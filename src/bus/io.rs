@@ -1,8 +1,10 @@
 use std::fs::File;
 use std::io::prelude::*;
 
+use crate::bus::device::IoDevice;
+
 #[allow(dead_code, unused_variables)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Io{ 
     arr: Vec<u8>,
 }
@@ -27,21 +29,16 @@ impl Io {
         }
     }
 
-    pub fn clone(&self) -> Io {
-        Io { arr: self.arr.clone() }
+    // The 256 ports, for snapshots
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.arr[..0xFF+1]
     }
 
-    pub fn diff(&self, other:Io) -> Vec<(u8, u8)> {
-        let mut changes: Vec<(u8, u8)> = vec![];
-        let mut j : u8 = 0;
-
-        for i in &self.arr {
-            if *i != other.arr[j as usize] {
-                changes.push((j, other.arr[j as usize]));
-            }
-            j = j.wrapping_add(1);
-        }
-        changes
+    pub fn from_bytes(bytes: &[u8]) -> Io {
+        let mut io = Io::new();
+        let len = bytes.len().min(0xFF+1);
+        io.arr[..len].copy_from_slice(&bytes[..len]);
+        io
     }
 
     pub fn get8(&self, pos:u8) -> u8 {
@@ -92,3 +89,18 @@ impl Io {
     }
 
 }
+
+// Ports no device answers on latch the last value written
+impl IoDevice for Io {
+    fn read(&mut self, port: u16) -> u8 {
+        self.get8(port as u8)
+    }
+
+    fn peek(&self, port: u16) -> u8 {
+        self.get8(port as u8)
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        self.set8(port as u8, value);
+    }
+}
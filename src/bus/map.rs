@@ -0,0 +1,169 @@
+use std::{error::Error, fmt, fs, str::FromStr};
+
+// Value read from an address nothing answers, the data bus pulled high
+pub const OPEN_BUS: u8 = 0xFF;
+
+// What an address is connected to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Ram,
+    Rom { fault: bool },      // Writes are ignored, or stop the program
+    Unmapped { fault: bool }, // Reads give FF and writes are ignored, or either stops the program
+}
+
+// An address after mirroring: the byte of memory behind it, and how it is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    addr: u16,
+    access: Access,
+}
+
+// How the 64K address space is decoded. Regions are laid over each other in
+// the order they are given, so later ones win, and a mirror repeats whatever
+// its source was when it was added. Addresses no region covers are RAM.
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    slots: Vec<Slot>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            slots: (0..=0xFFFF).map(|addr| Slot { addr, access: Access::Ram }).collect(),
+        }
+    }
+
+    fn with_access(mut self, lower: u16, upper: u16, access: Access) -> MemoryMap {
+        for addr in lower..=upper {
+            self.slots[addr as usize] = Slot { addr, access };
+        }
+        self
+    }
+
+    pub fn with_ram(self, lower: u16, upper: u16) -> MemoryMap {
+        self.with_access(lower, upper, Access::Ram)
+    }
+
+    pub fn with_rom(self, lower: u16, upper: u16, fault: bool) -> MemoryMap {
+        self.with_access(lower, upper, Access::Rom { fault })
+    }
+
+    pub fn with_unmapped(self, lower: u16, upper: u16, fault: bool) -> MemoryMap {
+        self.with_access(lower, upper, Access::Unmapped { fault })
+    }
+
+    // Makes lower..=upper repeat source_lower..=source_upper, over and over
+    // if the source is the smaller of the two
+    pub fn with_mirror(mut self, lower: u16, upper: u16, source_lower: u16, source_upper: u16) -> MemoryMap {
+        let len = source_upper as usize - source_lower as usize + 1;
+        for (i, addr) in (lower..=upper).enumerate() {
+            self.slots[addr as usize] = self.slots[source_lower as usize + i % len];
+        }
+        self
+    }
+
+    // Byte of memory behind an address, and how it is connected
+    pub fn resolve(&self, addr: u16) -> (u16, Access) {
+        let slot = self.slots[addr as usize];
+        (slot.addr, slot.access)
+    }
+
+    pub fn get_access(&self, addr: u16) -> Access {
+        self.slots[addr as usize].access
+    }
+
+    pub fn from_file(path: &str) -> Result<MemoryMap, Box<dyn Error>> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    // Adds the region on one line of a map file
+    fn with_line(self, words: &[&str]) -> Result<MemoryMap, String> {
+        let [kind, range, rest @ ..] = words else {
+            return Err("expected a region type and an address range".to_string());
+        };
+        let (lower, upper) = parse_range(range)?;
+        match (kind.to_lowercase().as_str(), rest) {
+            ("ram", []) => Ok(self.with_ram(lower, upper)),
+            ("rom", [] | ["ignore"]) => Ok(self.with_rom(lower, upper, false)),
+            ("rom", ["fault"]) => Ok(self.with_rom(lower, upper, true)),
+            ("unmapped", [] | ["ignore"]) => Ok(self.with_unmapped(lower, upper, false)),
+            ("unmapped", ["fault"]) => Ok(self.with_unmapped(lower, upper, true)),
+            ("mirror", [source]) => {
+                let (source_lower, source_upper) = parse_range(source)?;
+                Ok(self.with_mirror(lower, upper, source_lower, source_upper))
+            }
+            ("mirror", []) => Err("mirror needs a source range".to_string()),
+            ("ram" | "rom" | "unmapped" | "mirror", rest) => Err(format!("unexpected \"{}\"", rest.join(" "))),
+            (other, _) => Err(format!("unknown region type: {other} (expected ram, rom, unmapped or mirror)")),
+        }
+    }
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x").trim_end_matches(['h', 'H']), 16)
+        .map_err(|_| format!("not a hex address: {s}"))
+}
+
+fn parse_range(s: &str) -> Result<(u16, u16), String> {
+    let (lower, upper) = s.split_once('-').ok_or(format!("not an address range: {s} (expected LOWER-UPPER)"))?;
+    let (lower, upper) = (parse_addr(lower)?, parse_addr(upper)?);
+    if lower > upper {
+        return Err(format!("range {s} ends before it starts"));
+    }
+    Ok((lower, upper))
+}
+
+// One region per line, '#' starts a comment:
+//
+//   ram      2000-20FF
+//   rom      0000-07FF [ignore|fault]
+//   unmapped 3000-FFFF [ignore|fault]
+//   mirror   2100-27FF 2000-20FF
+impl FromStr for MemoryMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = MemoryMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let words = line.split('#').next().unwrap_or_default().split_whitespace().collect::<Vec<_>>();
+            if !words.is_empty() {
+                map = map.with_line(&words).map_err(|err| format!("Memory map line {}: {err}", i + 1))?;
+            }
+        }
+        Ok(map)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemFaultKind {
+    RomWrite { value: u8 },
+    UnmappedRead,
+    UnmappedWrite { value: u8 },
+}
+
+// An access the memory map was set to stop the program on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemFault {
+    pub pc: u16,   // Instruction that made it
+    pub addr: u16,
+    pub kind: MemFaultKind,
+}
+
+impl fmt::Display for MemFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MemFaultKind::RomWrite { value } => write!(f,
+                "Write to ROM: instruction at {:04X} wrote {value:02X} to {:04X}", self.pc, self.addr),
+            MemFaultKind::UnmappedRead => write!(f,
+                "Unmapped read: instruction at {:04X} read {:04X}", self.pc, self.addr),
+            MemFaultKind::UnmappedWrite { value } => write!(f,
+                "Unmapped write: instruction at {:04X} wrote {value:02X} to {:04X}", self.pc, self.addr),
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::fs::File;
 use std::io::prelude::*;
 
 #[allow(dead_code, unused_variables)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Memory { 
     arr: Vec<u8>,
 }
@@ -16,26 +16,7 @@ impl Default for Memory {
 #[allow(dead_code, unused_variables)]
 impl Memory {
     pub fn new() -> Memory {
-        Memory { arr: vec![0; 0xFFFF+2] }
-    }
-
-    pub fn clone(&self) -> Memory {
-        Memory { arr: self.arr.clone() }
-    }
-    
-    pub fn diff(&self, other:Memory) -> Vec<(u16, u8)> {
-        
-        let mut changes: Vec<(u16, u8)> = vec![];
-        let mut j : u16 = 0;
-
-        for i in &self.arr {
-            if *i != other.arr[j as usize]
-            {
-                changes.push((j, other.arr[j as usize]));
-            }
-            j = j.wrapping_add(1);
-        }
-        changes
+        Memory { arr: vec![0; 0xFFFF+1] }
     }
 
     pub fn print(&self) {
@@ -88,24 +69,37 @@ impl Memory {
         let len = file.metadata().unwrap().len() as usize;
 
         if len == 0xFFFF + 1 {
-            file.read(&mut self.arr[0x0000..0xFFFF+1])?;
+            file.read_exact(&mut self.arr[0x0000..0xFFFF+1])?;
         }
         else {
-            file.read(&mut self.arr[0xC000..0xC000+len])?;
+            file.read_exact(&mut self.arr[0xC000..0xC000+len])?;
         }
         Ok(())
     }
 
+    // The 64K of memory, for snapshots
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.arr
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Memory {
+        let mut mem = Memory::new();
+        let len = bytes.len().min(0xFFFF+1);
+        mem.arr[..len].copy_from_slice(&bytes[..len]);
+        mem
+    }
+
     pub fn get8(&self, pos:u16) -> u8 {
         self.arr[pos as usize]
     }
 
+    // 16-bit accesses wrap around from FFFF to 0000
     pub fn get16(&self, pos:u16) -> u16 {
-        (self.arr[pos as usize] as u16) << 8 | self.arr[pos as usize + 1] as u16
+        (self.get8(pos) as u16) << 8 | self.get8(pos.wrapping_add(1)) as u16
     }
 
     pub fn get16_reverse(&self, pos:u16) -> u16 {
-        (self.arr[pos as usize + 1] as u16) << 8 | self.arr[pos as usize] as u16
+        (self.get8(pos.wrapping_add(1)) as u16) << 8 | self.get8(pos) as u16
     }
 
     pub fn set8(&mut self, pos:u16, value:u8) {
@@ -113,19 +107,13 @@ impl Memory {
     }
 
     pub fn set16(&mut self, pos:u16, value:u16) {
-        let hi:u8 = (value & 0x00FF) as u8;
-        let lo:u8 = (value >> 8) as u8;
-        self.arr[pos as usize] = lo;
-        self.arr[pos as usize + 1] = hi;
-        self.arr[0xFFFF+1] = 0;
+        self.set8(pos, (value >> 8) as u8);
+        self.set8(pos.wrapping_add(1), value as u8);
     }
 
     pub fn set16_reverse(&mut self, pos:u16, value:u16) {
-        let lo:u8 = (value & 0x00FF) as u8;
-        let hi:u8 = (value >> 8) as u8;
-        self.arr[pos as usize] = lo;
-        self.arr[pos as usize + 1] = hi;
-        self.arr[0xFFFF+1] = 0;
+        self.set8(pos, value as u8);
+        self.set8(pos.wrapping_add(1), (value >> 8) as u8);
     }
 
 
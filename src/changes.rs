@@ -1,3 +1,5 @@
+use crate::{cpu::CPU, serial::UartLine};
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Regs {
 
@@ -18,9 +20,15 @@ pub struct Regs {
 
 }
 
+// Undo record for one step: the CPU as it was before the step, plus the
+// previous value of every memory and IO location the step wrote, in write
+// order, the devices it reached as they were, by index on the bus, and the
+// serial line as it was
 #[derive(Default, Debug, Clone)]
 pub struct Changes {
-    pub cpu : Regs,
+    pub cpu : CPU,
     pub memory : Vec<(u16, u8)>,
     pub io: Vec<(u8, u8)>,
+    pub devices: Vec<(usize, Vec<u8>)>,
+    pub serial: Option<UartLine>,
 }
@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::{assembler::Program, opcodes, profile::StepRecord};
+
+// Conditional jumps, calls and returns, the instructions with two directions
+pub fn is_conditional(opcode: u8) -> bool {
+    matches!(opcode & 0xC7, 0xC0 | 0xC2 | 0xC4)
+}
+
+// How often each branch direction was followed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+// Execution counts per instruction address and taken/not-taken counts per
+// conditional branch. Reports map them to source lines through the program.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    executed: BTreeMap<u16, u64>,
+    branches: BTreeMap<u16, BranchCount>,
+}
+
+// Coverage of one source line
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LineCoverage {
+    pub count: u64,
+    pub branch: Option<BranchCount>, // For conditional instructions
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn record(&mut self, step: &StepRecord) {
+        if !step.executed {
+            return;
+        }
+        *self.executed.entry(step.pc).or_default() += 1;
+        if is_conditional(step.opcode) {
+            let length = opcodes::get(step.opcode).map_or(1, |info| info.length);
+            let branch = self.branches.entry(step.pc).or_default();
+            if step.next_pc == step.pc.wrapping_add(length as u16) {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+            }
+        }
+    }
+
+    pub fn count_at(&self, addr: u16) -> u64 {
+        self.executed.get(&addr).copied().unwrap_or(0)
+    }
+
+    // Coverage of every line holding an instruction, in line order
+    pub fn lines(&self, program: &Program) -> BTreeMap<usize, LineCoverage> {
+        let mut lines = BTreeMap::new();
+        for (addr, line) in &program.lines {
+            let opcode = program.bytes[addr.wrapping_sub(program.origin) as usize];
+            let coverage = LineCoverage {
+                count: self.count_at(*addr),
+                branch: is_conditional(opcode).then(|| self.branches.get(addr).copied().unwrap_or_default()),
+            };
+            lines.insert(*line, coverage);
+        }
+        lines
+    }
+
+    // Writes the coverage as an lcov tracefile for one source file
+    pub fn write_lcov<W: Write>(&self, mut out: W, program: &Program, source_path: &str) -> io::Result<()> {
+        let lines = self.lines(program);
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{source_path}")?;
+
+        let (mut branches, mut branches_hit) = (0, 0);
+        for (line, coverage) in &lines {
+            let Some(branch) = coverage.branch else { continue };
+            for (direction, count) in [branch.taken, branch.not_taken].iter().enumerate() {
+                // lcov uses "-" for branches whose line never ran
+                let count = if coverage.count == 0 { "-".to_string() } else { count.to_string() };
+                writeln!(out, "BRDA:{line},0,{direction},{count}")?;
+            }
+            branches += 2;
+            branches_hit += (branch.taken > 0) as u32 + (branch.not_taken > 0) as u32;
+        }
+        writeln!(out, "BRF:{branches}")?;
+        writeln!(out, "BRH:{branches_hit}")?;
+
+        for (line, coverage) in &lines {
+            writeln!(out, "DA:{line},{}", coverage.count)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.values().filter(|c| c.count > 0).count())?;
+        writeln!(out, "end_of_record")
+    }
+
+    // Writes a standalone HTML page with the source annotated with execution
+    // counts and branch directions
+    pub fn write_html<W: Write>(&self, mut out: W, program: &Program, source: &str, title: &str) -> io::Result<()> {
+        let lines = self.lines(program);
+        let hit = lines.values().filter(|c| c.count > 0).count();
+        let branches = lines.values().filter_map(|c| c.branch).collect::<Vec<_>>();
+        let branches_hit = branches.iter().map(|b| (b.taken > 0) as usize + (b.not_taken > 0) as usize).sum::<usize>();
+        let percent = |part: usize, whole: usize| if whole == 0 { 100.0 } else { part as f64 * 100.0 / whole as f64 };
+
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html><head><meta charset=\"utf-8\"><title>Coverage - {}</title>", escape(title))?;
+        writeln!(out, "<style>")?;
+        writeln!(out, "body {{ font-family: sans-serif; }}")?;
+        writeln!(out, "table {{ border-collapse: collapse; font-family: monospace; }}")?;
+        writeln!(out, "td {{ padding: 0 8px; white-space: pre; }}")?;
+        writeln!(out, ".num {{ text-align: right; color: #777; }}")?;
+        writeln!(out, ".hit {{ background: #d4f7d4; }}")?;
+        writeln!(out, ".miss {{ background: #f7d4d4; }}")?;
+        writeln!(out, ".partial {{ background: #f7efc4; }}")?;
+        writeln!(out, "</style></head><body>")?;
+        writeln!(out, "<h1>{}</h1>", escape(title))?;
+        writeln!(out, "<p>Instructions: {hit} of {} ({:.1}%)<br>Branch directions: {branches_hit} of {} ({:.1}%)</p>",
+            lines.len(), percent(hit, lines.len()), branches.len() * 2, percent(branches_hit, branches.len() * 2))?;
+        writeln!(out, "<table>")?;
+        writeln!(out, "<tr><th>Line</th><th>Count</th><th>Branch</th><th>Source</th></tr>")?;
+
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            let (class, count, branch) = match lines.get(&line) {
+                None => ("", String::new(), String::new()),
+                Some(coverage) => {
+                    let branch = coverage.branch.map_or(String::new(), |b| format!("taken {} / not {}", b.taken, b.not_taken));
+                    let class = match coverage.branch {
+                        _ if coverage.count == 0 => "miss",
+                        Some(b) if b.taken == 0 || b.not_taken == 0 => "partial",
+                        _ => "hit",
+                    };
+                    (class, coverage.count.to_string(), branch)
+                }
+            };
+            writeln!(out, "<tr class=\"{class}\"><td class=\"num\">{line}</td><td class=\"num\">{count}</td><td>{branch}</td><td>{}</td></tr>",
+                escape(text))?;
+        }
+        writeln!(out, "</table></body></html>")
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,8 +1,19 @@
+mod blocks;
+mod callstack;
 mod instructions;
+mod interrupts;
+mod snapshot;
 
-use crate::bus::Bus;
+pub use blocks::{BlockCache, BlockExit, BlockLimits, ExecMode};
+pub use callstack::{Frame, InterruptSource, StackWarning};
+
+use std::fmt;
+
+use crate::bus::{Bus, Bus8085};
+use crate::opcodes;
 use crate::changes::Changes;
 use crate::changes::Regs;
+use crate::snapshot::SnapshotError;
 
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Interrupts {
@@ -14,9 +25,66 @@ pub struct Interrupts {
     pub intr: bool, // Interrupt request
 }
 
+impl Interrupts {
+    pub fn any(&self) -> bool {
+        self.trap || self.rst7_5 || self.rst6_5 || self.rst5_5 || self.intr
+    }
+
+    // Lines high in either, as when several sources drive the same pins
+    pub fn or(self, other: Interrupts) -> Interrupts {
+        Interrupts {
+            trap: self.trap || other.trap,
+            rst7_5: self.rst7_5 || other.rst7_5,
+            rst6_5: self.rst6_5 || other.rst6_5,
+            rst5_5: self.rst5_5 || other.rst5_5,
+            intr: self.intr || other.intr,
+        }
+    }
+}
+
+// A single line high
+impl From<InterruptSource> for Interrupts {
+    fn from(source: InterruptSource) -> Self {
+        let mut ints = Interrupts::default();
+        match source {
+            InterruptSource::Trap => ints.trap = true,
+            InterruptSource::Rst7_5 => ints.rst7_5 = true,
+            InterruptSource::Rst6_5 => ints.rst6_5 = true,
+            InterruptSource::Rst5_5 => ints.rst5_5 = true,
+            InterruptSource::Intr => ints.intr = true,
+        }
+        ints
+    }
+}
+
+// An opcode the CPU has no instruction for, reached at pc. PC stays on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndefinedOpcode {
+    pub pc: u16,
+    pub opcode: u8,
+}
+
+impl fmt::Display for UndefinedOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Undefined opcode {:02X} at {:04X}", self.opcode, self.pc)
+    }
+}
+
+// Chip being emulated. The 8080 has no RIM/SIM (they run as NOP), no TRAP or
+// RST n.5 inputs, its own cycle timings, and differs in the PSW layout and
+// in how logical instructions set AC.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuModel {
+    #[default]
+    I8085,
+    I8080,
+}
+
 #[derive(Default, Debug, Clone)]
 #[allow(dead_code, unused_variables, clippy::upper_case_acronyms)]
 pub struct CPU {
+    model: CpuModel,
+
     a: u8, // Accumulator
     b: u8, // Pair BC
     c: u8,
@@ -39,14 +107,43 @@ pub struct CPU {
     pub sod: bool, // Serial Output Data       --                 ==
 
     // Interrupts
-    pub pending_int: Interrupts, // Pending interrupts      -- Public to simulate a hardware pin
-    masked_int: Interrupts,      // Disabled interrupts   (trap should't be masked)
-    int: bool,                   // Interrupt flip-flop
-    inta: bool,                  // Interrupt accept flag (used with intr only)
+    pub pins: Interrupts,            // Interrupt input lines    -- Public to simulate a hardware pin
+    last_pins: Interrupts,           // Pin levels at the previous step, for edge detection
+    pending_int: Interrupts,         // Pending interrupts
+    masked_int: Interrupts,          // Disabled interrupts   (trap should't be masked)
+    int: bool,                       // Interrupt flip-flop
+    int_delay: bool,                 // Set by EI, holds off interrupts for one more instruction
+    ie_before_trap: Option<bool>,    // Interrupt flip-flop before the last TRAP, read back by RIM
+    inta: bool,                      // Interrupt accept flag (used with intr only)
+    interrupted: bool,               // The last step entered an interrupt instead of executing
+
+    halted: bool, // Set by HLT, cleared when an interrupt is accepted
+    undefined: Option<UndefinedOpcode>, // Fetched by the last step instead of an instruction
+    cycles: u64,  // T-states elapsed since reset
+
+    // Shadow call stack
+    inst_pc: u16,                        // Address of the instruction being executed
+    call_stack: Vec<Frame>,              // Innermost last
+    stack_warning: Option<StackWarning>, // Mismatched return in the last step
 }
 
+// T-states spent per step while the CPU sits in the HALT state
+const HALT_T_STATES: u8 = 4;
+
 #[allow(dead_code, unused_variables)]
 impl CPU {
+    pub fn with_model(model: CpuModel) -> CPU {
+        CPU { model, ..CPU::default() }
+    }
+
+    pub fn get_model(&self) -> CpuModel {
+        self.model
+    }
+
+    pub fn set_model(&mut self, model: CpuModel) {
+        self.model = model;
+    }
+
     #[rustfmt::skip]
     pub fn print_state(&self) {
         println!("📥A  => {:02X} - {:08b}    |    🚩S  => {}", self.a, self.a, self.s);
@@ -58,6 +155,7 @@ impl CPU {
         println!("📥L  => {:02X} - {:08b}", self.l, self.l);
         println!("📥SP => {:04X} - {:016b}", self.sp, self.sp);
         println!("📥PC => {:04X} - {:016b}", self.pc, self.pc);
+        println!("⏱️ Cycles => {}{}", self.cycles, if self.halted { "    (halted)" } else { "" });
     }
 
     pub fn set_pc(&mut self, val: u16) {
@@ -72,6 +170,10 @@ impl CPU {
         self.sp
     }
 
+    pub fn get_pending_int(&self) -> Interrupts {
+        self.pending_int
+    }
+
     pub fn get_masked_int(&self) -> Interrupts {
         self.masked_int
     }
@@ -84,17 +186,106 @@ impl CPU {
         self.inta
     }
 
-    fn fetch8(&mut self, bus: &Bus) -> u8 {
-        self.pc += 1;
-        bus.mem_get8(self.pc - 1)
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn get_undefined_opcode(&self) -> Option<UndefinedOpcode> {
+        self.undefined
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn fetch8(&mut self, bus: &mut impl Bus8085) -> u8 {
+        let value = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16(&mut self, bus: &mut impl Bus8085) -> u16 {
+        let lo = self.fetch8(bus) as u16;
+        let hi = self.fetch8(bus) as u16;
+        hi << 8 | lo
+    }
+
+    // Little endian, wrapping around from FFFF to 0000
+    fn read16(&self, bus: &mut impl Bus8085, addr: u16) -> u16 {
+        bus.read(addr) as u16 | (bus.read(addr.wrapping_add(1)) as u16) << 8
+    }
+
+    fn write16(&self, bus: &mut impl Bus8085, addr: u16, value: u16) {
+        bus.write(addr, value as u8);
+        bus.write(addr.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // Operand of a conditional jump or call. The 8085 does not read the high
+    // byte of a branch it won't take, the 8080 always reads both.
+    fn fetch_target(&mut self, bus: &mut impl Bus8085, taken: bool) -> u16 {
+        if taken || self.model == CpuModel::I8080 {
+            self.fetch16(bus)
+        } else {
+            let lo = self.fetch8(bus) as u16;
+            self.pc = self.pc.wrapping_add(1);
+            lo
+        }
+    }
+
+    // Pushes PC and jumps, shared by CALL, RST and interrupts
+    fn call_to(&mut self, bus: &mut impl Bus8085, addr: u16, interrupt: Option<InterruptSource>) {
+        // High byte first, as the 8085 pushes
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, (self.pc >> 8) as u8);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, self.pc as u8);
+        self.push_frame(addr, interrupt);
+        self.pc = addr;
+    }
+
+    // Charges the extra T-states of a taken conditional branch
+    fn branch_taken(&mut self, inst: u8) {
+        if let Some(info) = opcodes::get(inst) {
+            let (base, taken) = info.timing(self.model);
+            self.cycles += (taken - base) as u64;
+        }
+    }
+
+    // Evaluates the condition encoded in bits 3-5 of conditional jumps, calls and returns
+    fn condition(&self, inst: u8) -> bool {
+        match (inst >> 3) & 0x07 {
+            0 => !self.z,  // NZ
+            1 => self.z,   // Z
+            2 => !self.cy, // NC
+            3 => self.cy,  // C
+            4 => !self.p,  // PO
+            5 => self.p,   // PE
+            6 => !self.s,  // P
+            7 => self.s,   // M
+            _ => unreachable!(),
+        }
     }
 
-    fn fetch16(&mut self, bus: &Bus) -> u16 {
-        self.pc += 2;
-        bus.mem_get16_reverse(self.pc - 2)
+    pub fn get_reg(&self, bus: &mut impl Bus8085, target: u8) -> u8 {
+        match target {
+            6 => bus.read(self.get_reg_pair(2)),
+            _ => self.register(target),
+        }
+    }
+
+    // As get_reg, without a bus cycle or disturbing a device at HL
+    pub fn peek_reg(&self, bus: &Bus, target: u8) -> u8 {
+        match target {
+            6 => bus.mem_peek8(self.get_reg_pair(2)),
+            _ => self.register(target),
+        }
     }
 
-    pub fn get_reg(&self, bus: &Bus, target: u8) -> u8 {
+    fn register(&self, target: u8) -> u8 {
         match target {
             0 => self.b,
             1 => self.c,
@@ -102,7 +293,6 @@ impl CPU {
             3 => self.e,
             4 => self.h,
             5 => self.l,
-            6 => bus.mem_get8(self.get_reg_pair(2)),
             7 => self.a,
             _ => panic!("Unknown target"),
         }
@@ -131,7 +321,7 @@ impl CPU {
         value
     }
 
-    pub fn set_reg(&mut self, bus: &mut Bus, target: u8, value: u8) {
+    pub fn set_reg(&mut self, bus: &mut impl Bus8085, target: u8, value: u8) {
         match target {
             0 => self.b = value,
             1 => self.c = value,
@@ -139,7 +329,7 @@ impl CPU {
             3 => self.e = value,
             4 => self.h = value,
             5 => self.l = value,
-            6 => bus.mem_set8(self.get_reg_pair(2), value),
+            6 => bus.write(self.get_reg_pair(2), value),
             7 => self.a = value,
             _ => panic!("Unknown target"),
         }
@@ -197,81 +387,84 @@ impl CPU {
         }
     }
 
-    pub fn diff(&self, other: CPU) -> Regs {
+    pub fn regs(&self) -> Regs {
         Regs {
-            a: other.a,
-            b: other.b,
-            c: other.c,
-            d: other.d,
-            e: other.e,
-            h: other.h,
-            l: other.l,
-            z: other.z,
-            s: other.s,
-            ac: other.ac,
-            p: other.p,
-            cy: other.cy,
-            pc: other.pc,
-            sp: other.sp,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            z: self.z,
+            s: self.s,
+            ac: self.ac,
+            p: self.p,
+            cy: self.cy,
+            pc: self.pc,
+            sp: self.sp,
         }
     }
 
-    pub fn restore(&mut self, bus: &mut Bus, changes: &Changes) {
-        self.a = changes.cpu.a;
-        self.b = changes.cpu.b;
-        self.c = changes.cpu.c;
-        self.d = changes.cpu.d;
-        self.e = changes.cpu.e;
-        self.h = changes.cpu.h;
-        self.l = changes.cpu.l;
-        self.z = changes.cpu.z;
-        self.s = changes.cpu.s;
-        self.ac = changes.cpu.ac;
-        self.cy = changes.cpu.cy;
-        self.p = changes.cpu.p;
-        self.pc = changes.cpu.pc;
-        self.sp = changes.cpu.sp;
-
-        for (add, val) in &changes.memory {
+    // Undoes a step: writes back the old memory and IO values, latest first,
+    // and returns the CPU to its state before the step. Fails when a device
+    // rejects its saved state.
+    pub fn restore(&mut self, bus: &mut Bus, changes: &Changes) -> Result<(), SnapshotError> {
+        for (add, val) in changes.memory.iter().rev() {
             bus.mem_set8(*add, *val);
         }
-        
-        for (add, val) in &changes.io {
-            bus.io_set8(*add, *val);
+
+        for (add, val) in changes.io.iter().rev() {
+            bus.io_undo8(*add, *val);
         }
+        bus.devices_undo(&changes.devices)?;
+
+        *self = changes.cpu.clone();
+        Ok(())
     }
 
-    pub fn execute(&mut self, bus: &mut Bus) -> bool {
-        if self.pending_int.trap {
-            self.rst(0x24, bus);
-        }
+    pub fn execute(&mut self, bus: &mut impl Bus8085) {
+        let start = self.cycles;
+        bus.begin_step();
+        self.run_step(bus);
+        bus.tick(self.cycles - start);
+        bus.end_step(start, self.cycles);
+    }
 
-        if self.int {
-            self.int = false;
-
-            if self.pending_int.rst7_5 && !self.masked_int.rst7_5 {
-                self.rst(0x3C, bus);
-                self.pending_int.rst7_5 = false;
-            } else if self.pending_int.rst6_5 && !self.masked_int.rst6_5 {
-                self.rst(0x34, bus);
-                self.pending_int.rst6_5 = false;
-            } else if self.pending_int.rst5_5 && !self.masked_int.rst5_5 {
-                self.rst(0x2C, bus);
-                self.pending_int.rst5_5 = false;
-            } else if self.pending_int.intr {
-                let addr = self.fetch8(bus);
-                let val = bus.io_get8(addr);
-                self.rst(val, bus);
-            }
+    fn run_step(&mut self, bus: &mut impl Bus8085) {
+        self.inst_pc = self.pc;
+        self.stack_warning = None;
+        self.undefined = None;
+        self.sample_interrupts(bus.irq_lines());
+        self.inta = false;
+        self.interrupted = self.accept_interrupt(bus);
+        if self.interrupted {
+            return;
         }
 
-        if self.pc >= 0xD000 {
-            return false;
+        if self.halted {
+            self.cycles += HALT_T_STATES as u64;
+            return;
         }
-        let inst = bus.mem_get8(self.pc);
-        self.pc += 1;
+
+        self.int_delay = false;
+        let inst = bus.fetch(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        let Some(info) = opcodes::get(inst) else {
+            // Only the fetch happened
+            self.pc = self.inst_pc;
+            self.cycles += 4;
+            self.undefined = Some(UndefinedOpcode { pc: self.pc, opcode: inst });
+            return;
+        };
+        self.cycles += info.timing(self.model).0 as u64;
+        self.dispatch(bus, inst);
+    }
+
+    // Runs the instruction of an opcode already fetched
+    fn dispatch(&mut self, bus: &mut impl Bus8085, inst: u8) {
         match inst {
-            0x76 => return false,
+            0x76 => self.hlt(),
             0x40..=0x7F => self.mov(bus, inst),
             0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => self.mvi(bus, inst),
             0x01 | 0x11 | 0x21 | 0x31 => self.lxi(bus, inst),
@@ -304,14 +497,15 @@ impl CPU {
             0xC6 => self.adi(bus),
             0xCE => self.aci(bus),
             0x09 | 0x19 | 0x29 | 0x39 => self.dad(inst),
-            0x90..=0x9F => self.sub(bus, inst),
+            0x90..=0x97 => self.sub(bus, inst),
+            0x98..=0x9F => self.sbb(bus, inst),
             0xD6 => self.sui(bus),
             0xDE => self.sbi(bus),
             0xA0..=0xA7 => self.ana(bus, inst),
             0xA8..=0xAF => self.xra(bus, inst),
             0xB0..=0xB7 => self.ora(bus, inst),
             0xB8..=0xBF => self.cmp(bus, inst),
-            0xE6 => self.cpi(bus, inst),
+            0xE6 => self.ani(bus, inst),
             0xEE => self.xri(bus, inst),
             0xF6 => self.ori(bus, inst),
             0xFE => self.cpi(bus, inst),
@@ -323,10 +517,10 @@ impl CPU {
             0xFB => self.ei(),
             0xF3 => self.di(),
             0x00 => self.nop(),
+            0x20 | 0x30 if self.model == CpuModel::I8080 => self.nop(),
             0x20 => self.rim(),
             0x30 => self.sim(),
             _ => panic!("Instrução não identificada: {inst:02X}"),
         };
-        true
     }
 }
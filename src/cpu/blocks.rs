@@ -0,0 +1,222 @@
+use super::{CPU, CpuModel};
+use crate::bus::{Bus, FlatRam};
+use crate::opcodes;
+
+// Longest run of instructions decoded into one block
+const MAX_BLOCK_LEN: usize = 32;
+
+/// How the simulator runs instructions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExecMode {
+    /// Fetches and decodes every instruction as it runs. The reference for
+    /// the cached mode, and the only one used while a debugging aid is on.
+    #[default]
+    Interpreter,
+    /// Runs straight-line code from a cache of decoded blocks, dropped when
+    /// one of their opcodes is changed
+    Cached,
+}
+
+// An instruction decoded ahead of time. Operands are not kept, the
+// instruction reads them when it runs, so only a new opcode makes a block
+// stale.
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    opcode: u8,
+    t_states: u8, // When a conditional branch is not taken
+    addr: u16,    // Of the memory behind the opcode
+    stack: bool,
+}
+
+// CALL, RET, RST and their conditional forms, PUSH, POP, XTHL, SPHL and LXI
+// SP, what the stack guard looks at
+fn uses_stack(opcode: u8) -> bool {
+    match opcode {
+        0xCD | 0xC9 | 0xE3 | 0xF9 | 0x31 => true,
+        op => op & 0xC7 == 0xC4 || op & 0xC7 == 0xC0 || op & 0xC7 == 0xC7 || op & 0xCF == 0xC5 || op & 0xCF == 0xC1,
+    }
+}
+
+// Instructions that end a block: they may branch, do IO or change what
+// interrupts can do
+fn ends_block(opcode: u8) -> bool {
+    match opcode {
+        0xC3 | 0xE9 | 0x76 => true, // JMP, PCHL, HLT
+        0xCD | 0xC9 => true, // CALL, RET
+        op if op & 0xC7 == 0xC2 => true, // Conditional jumps
+        op if op & 0xC7 == 0xC4 || op & 0xC7 == 0xC0 || op & 0xC7 == 0xC7 => true, // Conditional calls and returns, RST
+        0xDB | 0xD3 | 0xFB | 0xF3 | 0x20 | 0x30 => true, // IN, OUT, EI, DI, RIM, SIM
+        _ => false,
+    }
+}
+
+// Blocks of decoded instructions by start address. The bus reports the
+// cached opcodes written since the last run, and the blocks holding them
+// are dropped.
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    model: CpuModel,
+    blocks: Vec<Option<Box<[Decoded]>>>,
+    pages: Vec<Vec<u16>>, // Start of the blocks with an opcode on each page of memory
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache {
+            model: CpuModel::default(),
+            blocks: vec![None; 0x10000],
+            pages: vec![Vec::new(); 0x100],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.fill(None);
+        self.pages.iter_mut().for_each(Vec::clear);
+    }
+
+    // Drops the blocks with an opcode at addr, and forgets the ones
+    // already dropped through another page
+    fn invalidate(&mut self, addr: u16) {
+        let blocks = &mut self.blocks;
+        self.pages[(addr >> 8) as usize].retain(|start| {
+            let block = &mut blocks[*start as usize];
+            if block.as_ref().is_some_and(|block| block.iter().any(|inst| inst.addr == addr)) {
+                *block = None;
+            }
+            block.is_some()
+        });
+    }
+
+    fn decode(&mut self, start: u16, bus: &mut Bus) {
+        let mut block = Vec::new();
+        let mut addr = start;
+        while block.len() < MAX_BLOCK_LEN {
+            let opcode = bus.mem_peek8(addr);
+            // Left to the interpreter, which stops on it
+            let Some(info) = opcodes::get(opcode) else { break };
+            let at = bus.cache_code(addr);
+            block.push(Decoded {
+                opcode,
+                t_states: info.timing(self.model).0,
+                addr: at,
+                stack: uses_stack(opcode),
+            });
+            let page = (at >> 8) as usize;
+            if !self.pages[page].contains(&start) {
+                self.pages[page].push(start);
+            }
+            addr = addr.wrapping_add(info.length as u16);
+            if ends_block(opcode) {
+                break;
+            }
+        }
+        self.blocks[start as usize] = Some(block.into_boxed_slice());
+    }
+}
+
+// When a run of blocks has to hand back to the simulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLimits {
+    pub steps: u64,
+    pub cycles: u64,       // Stop once the cycle count reaches this
+    pub pc: Option<u16>,   // Stop when PC gets here
+    pub stack: bool,       // Stop after an instruction that uses the stack
+}
+
+// What a run of blocks did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockExit {
+    pub steps: u64,
+    pub pc: u16, // Last instruction run
+    pub opcode: u8,
+    pub sp: u16, // SP before it
+}
+
+impl CPU {
+    // Whether the next step is an instruction and no interrupt can be taken
+    // before it, the state in which blocks can run
+    pub fn can_run_block(&self, bus: &Bus) -> bool {
+        !self.halted && !self.pins.any() && !self.last_pins.any() && !self.pending_int.any() && !bus.irq_lines().any()
+    }
+
+    // Runs blocks from the cache, as the same steps would run through
+    // `execute` with nothing recording the bus. Besides the limits, stops on
+    // HLT, on a stack warning, on a memory fault, when a device raises an
+    // interrupt, when a block ends on code the cache can't decode and when
+    // a cached opcode is changed.
+    pub fn run_blocks(&mut self, bus: &mut Bus, cache: &mut BlockCache, limits: BlockLimits) -> BlockExit {
+        let start = self.cycles;
+        bus.begin_step();
+        let exit = self.run_cached(bus, cache, limits);
+        bus.end_step(start, self.cycles);
+        exit
+    }
+
+    fn run_cached(&mut self, bus: &mut Bus, cache: &mut BlockCache, limits: BlockLimits) -> BlockExit {
+        if cache.model != self.model {
+            cache.clear();
+            cache.model = self.model;
+        }
+        self.inta = false;
+        self.interrupted = false;
+
+        // Neither changes during the run
+        let devices = bus.has_devices();
+        let flat = bus.is_flat_ram();
+
+        let mut exit = BlockExit { steps: 0, pc: self.pc, opcode: 0, sp: self.sp };
+        loop {
+            if bus.has_written_code() {
+                for addr in bus.take_written_code() {
+                    cache.invalidate(addr);
+                }
+            }
+            if cache.blocks[self.pc as usize].is_none() {
+                cache.decode(self.pc, bus);
+            }
+            let Some(block) = &cache.blocks[self.pc as usize] else { return exit };
+            if block.is_empty() {
+                return exit;
+            }
+            for inst in block.iter() {
+                exit = BlockExit { steps: exit.steps + 1, pc: self.pc, opcode: inst.opcode, sp: self.sp };
+                self.inst_pc = self.pc;
+                self.stack_warning = None;
+                self.int_delay = false;
+                self.pc = self.pc.wrapping_add(1);
+                let start = self.cycles;
+                self.cycles += inst.t_states as u64;
+                if flat {
+                    self.dispatch(&mut FlatRam(bus), inst.opcode);
+                } else {
+                    self.dispatch(bus, inst.opcode);
+                }
+                let raised = devices && {
+                    bus.tick_devices(self.cycles - start);
+                    bus.irq_lines().any()
+                };
+
+                if exit.steps == limits.steps
+                    || self.cycles >= limits.cycles
+                    || limits.pc == Some(self.pc)
+                    || self.stack_warning.is_some()
+                    || (limits.stack && inst.stack)
+                    || bus.has_written_code()
+                    || (!flat && bus.has_mem_faults())
+                    || raised
+                {
+                    return exit;
+                }
+            }
+            if self.halted {
+                return exit;
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fmt, str::FromStr};
+
+use super::CPU;
+
+// Frames kept before the oldest are dropped, for code that calls and never returns
+const MAX_FRAMES: usize = 256;
+
+// Interrupt that entered a subroutine instead of a CALL or RST
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptSource {
+    Trap,
+    Rst7_5,
+    Rst6_5,
+    Rst5_5,
+    Intr,
+}
+
+impl fmt::Display for InterruptSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InterruptSource::Trap => "TRAP",
+            InterruptSource::Rst7_5 => "RST 7.5",
+            InterruptSource::Rst6_5 => "RST 6.5",
+            InterruptSource::Rst5_5 => "RST 5.5",
+            InterruptSource::Intr => "INTR",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// Pin names as written on the command line: trap, rst7.5, rst6.5, rst5.5, intr
+impl FromStr for InterruptSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(' ', "").as_str() {
+            "trap" => Ok(InterruptSource::Trap),
+            "rst7.5" => Ok(InterruptSource::Rst7_5),
+            "rst6.5" => Ok(InterruptSource::Rst6_5),
+            "rst5.5" => Ok(InterruptSource::Rst5_5),
+            "intr" => Ok(InterruptSource::Intr),
+            _ => Err(format!("Unknown interrupt input: {s} (expected trap, rst7.5, rst6.5, rst5.5 or intr)")),
+        }
+    }
+}
+
+// One subroutine entered through CALL, RST or an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub caller: u16,      // Address of the calling instruction, or where the interrupt hit
+    pub target: u16,
+    pub return_addr: u16,
+    pub sp: u16,          // SP once the return address was pushed
+    pub interrupt: Option<InterruptSource>,
+}
+
+// A return that doesn't match the innermost frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackWarning {
+    pub pc: u16,              // Address of the RET
+    pub sp: u16,              // SP when it ran
+    pub expected: Option<Frame>,
+}
+
+impl fmt::Display for StackWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.expected {
+            Some(frame) => write!(f,
+                "RET at {:04X} with SP={:04X}, but the call to {:04X} left SP={:04X} (unbalanced PUSH/POP?)",
+                self.pc, self.sp, frame.target, frame.sp),
+            None => write!(f, "RET at {:04X} with no call to return from", self.pc),
+        }
+    }
+}
+
+impl CPU {
+    pub fn get_call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    pub fn get_stack_warning(&self) -> Option<StackWarning> {
+        self.stack_warning
+    }
+
+    // Called once the return address is on the stack
+    pub(super) fn push_frame(&mut self, target: u16, interrupt: Option<InterruptSource>) {
+        if self.call_stack.len() == MAX_FRAMES {
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(Frame {
+            caller: self.inst_pc,
+            target,
+            return_addr: self.pc,
+            sp: self.sp,
+            interrupt,
+        });
+    }
+
+    // Called before a taken return pops its address. The frame whose SP
+    // matches is the one returning; frames above it are abandoned.
+    pub(super) fn pop_frame(&mut self) {
+        match self.call_stack.iter().rposition(|frame| frame.sp == self.sp) {
+            Some(depth) => {
+                if depth + 1 != self.call_stack.len() {
+                    self.stack_warning = Some(StackWarning {
+                        pc: self.inst_pc,
+                        sp: self.sp,
+                        expected: self.call_stack.last().copied(),
+                    });
+                }
+                self.call_stack.truncate(depth);
+            }
+            None => {
+                self.stack_warning = Some(StackWarning {
+                    pc: self.inst_pc,
+                    sp: self.sp,
+                    expected: self.call_stack.last().copied(),
+                });
+                self.call_stack.pop();
+            }
+        }
+    }
+}
@@ -1,67 +1,67 @@
-use super::CPU;
-use crate::bus::Bus;
+use super::{CPU, CpuModel};
+use crate::bus::Bus8085;
 
 #[allow(dead_code, unused_variables)]
 impl CPU {
-    pub(super) fn mov(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn mov(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let s = inst & 0x07;
         let d = (inst >> 3) & 0x07;
         let value = self.get_reg(bus, s);
         self.set_reg(bus, d, value);
     }
 
-    pub(super) fn mvi(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn mvi(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let d = (inst >> 3) & 0x07;
         let value = self.fetch8(bus);
         self.set_reg(bus, d, value);
     }
 
-    pub(super) fn lxi(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn lxi(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let d = (inst >> 4) & 0x03;
         let value = self.fetch16(bus);
         self.set_reg_pair(d, value);
     }
 
-    pub(super) fn stax(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn stax(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let s = (inst >> 4) & 1;
         let addr: u16 = if s == 0 {
             (self.b as u16) << 8 | self.c as u16
         } else {
             (self.d as u16) << 8 | self.c as u16
         };
-        bus.mem_set8(addr, self.a);
+        bus.write(addr, self.a);
     }
 
-    pub(super) fn ldax(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn ldax(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let s = (inst >> 4) & 1;
         let addr: u16 = if s == 0 {
             (self.b as u16) << 8 | self.c as u16
         } else {
             (self.d as u16) << 8 | self.c as u16
         };
-        let value = bus.mem_get8(addr);
+        let value = bus.read(addr);
         self.a = value;
     }
 
-    pub(super) fn sta(&mut self, bus: &mut Bus) {
+    pub(super) fn sta(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch16(bus);
-        bus.mem_set8(addr, self.a);
+        bus.write(addr, self.a);
     }
 
-    pub(super) fn lda(&mut self, bus: &mut Bus) {
+    pub(super) fn lda(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch16(bus);
-        self.a = bus.mem_get8(addr);
+        self.a = bus.read(addr);
     }
 
-    pub(super) fn shld(&mut self, bus: &mut Bus) {
+    pub(super) fn shld(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch16(bus);
         let value = (self.h as u16) << 8 | self.l as u16;
-        bus.mem_set16_reverse(addr, value);
+        self.write16(bus, addr, value);
     }
 
-    pub(super) fn lhld(&mut self, bus: &mut Bus) {
+    pub(super) fn lhld(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch16(bus);
-        let value = bus.mem_get16_reverse(addr);
+        let value = self.read16(bus, addr);
         self.h = (value >> 8) as u8;
         self.l = value as u8;
     }
@@ -75,7 +75,7 @@ impl CPU {
         self.e = l;
     }
 
-    pub(super) fn inr(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn inr(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let d = (inst >> 3) & 0x07;
         let old = self.get_reg(bus, d);
         let new = old.wrapping_add(1);
@@ -95,7 +95,7 @@ impl CPU {
         }
     }
 
-    pub(super) fn dcr(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn dcr(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let d = (inst >> 3) & 0x07;
         let old = self.get_reg(bus, d);
         let new = old.wrapping_sub(1);
@@ -172,7 +172,7 @@ impl CPU {
         }
     }
 
-    pub(super) fn add(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn add(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let s = inst & 0x07;
         let value = self.get_reg(bus, s);
         let prev_a = self.a;
@@ -184,7 +184,7 @@ impl CPU {
         self.cy = self.a < prev_a;
     }
 
-    pub(super) fn adc(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn adc(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let s = inst & 0x07;
         let value = self.get_reg(bus, s);
         let prev_a = self.a;
@@ -198,7 +198,7 @@ impl CPU {
         self.cy = self.a < prev_a;
     }
 
-    pub(super) fn adi(&mut self, bus: &mut Bus) {
+    pub(super) fn adi(&mut self, bus: &mut impl Bus8085) {
         let value = self.fetch8(bus);
         let prev_a = self.a;
         self.a = prev_a.wrapping_add(value);
@@ -209,7 +209,7 @@ impl CPU {
         self.cy = self.a < prev_a;
     }
 
-    pub(super) fn aci(&mut self, bus: &mut Bus) {
+    pub(super) fn aci(&mut self, bus: &mut impl Bus8085) {
         let value = self.fetch8(bus);
         let prev_a = self.a;
         //self.a = prev_a + value + self.cy as u8;
@@ -232,8 +232,8 @@ impl CPU {
         self.cy = cur_hl < prev_hl;
     }
 
-    pub(super) fn sub(&mut self, bus: &mut Bus, inst: u8) {
-        let s = inst & 0x03;
+    pub(super) fn sub(&mut self, bus: &mut impl Bus8085, inst: u8) {
+        let s = inst & 0x07;
         let value = self.get_reg(bus, s);
         let prev_a = self.a;
         // self.a = prev_a - value;
@@ -245,8 +245,8 @@ impl CPU {
         self.cy = value > prev_a;
     }
 
-    pub(super) fn sbb(&mut self, bus: &mut Bus, inst: u8) {
-        let s = inst & 0x03;
+    pub(super) fn sbb(&mut self, bus: &mut impl Bus8085, inst: u8) {
+        let s = inst & 0x07;
         // let value = self.get_reg(bus, s) + self.cy as u8;
         let value = self.get_reg(bus, s).wrapping_add(self.cy as u8);
         let prev_a = self.a;
@@ -259,7 +259,7 @@ impl CPU {
         self.cy = value > prev_a;
     }
 
-    pub(super) fn sui(&mut self, bus: &mut Bus) {
+    pub(super) fn sui(&mut self, bus: &mut impl Bus8085) {
         let value = self.fetch8(bus);
         let prev_a = self.a;
         // self.a = prev_a - value;
@@ -271,8 +271,8 @@ impl CPU {
         self.cy = value > prev_a;
     }
 
-    pub(super) fn sbi(&mut self, bus: &mut Bus) {
-        let value = self.fetch8(bus) + self.cy as u8;
+    pub(super) fn sbi(&mut self, bus: &mut impl Bus8085) {
+        let value = self.fetch8(bus).wrapping_add(self.cy as u8);
         let prev_a = self.a;
         // self.a = prev_a - value;
         self.a = prev_a.wrapping_sub(value);
@@ -307,39 +307,37 @@ impl CPU {
         self.update_p(self.a);
     }
 
-    pub(super) fn push(&mut self, inst: u8, bus: &mut Bus) {
-        if self.sp <= 0xC000 {
-            self.sp = 0xD000;
-        }
+    pub(super) fn push(&mut self, inst: u8, bus: &mut impl Bus8085) {
         let which = (inst >> 4) & 0x03;
         match which {
             0 => {
                 // BC
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.b);
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.c);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.b);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.c);
             }
             1 => {
                 // DE
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.d);
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.e);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.d);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.e);
             }
             2 => {
                 // HL
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.h);
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.l);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.h);
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.l);
             }
             3 => {
                 // PSW - AF
-                self.sp -= 1;
-                bus.mem_set8(self.sp, self.a);
-                self.sp -= 1;
-                let mut flags: u8 = 0;
+                self.sp = self.sp.wrapping_sub(1);
+                bus.write(self.sp, self.a);
+                self.sp = self.sp.wrapping_sub(1);
+                // Bit 1 always reads as 1 on the 8080
+                let mut flags: u8 = if self.model == CpuModel::I8080 { 0x02 } else { 0 };
                 if self.cy {
                     flags += 1;
                 }
@@ -355,67 +353,61 @@ impl CPU {
                 if self.s {
                     flags += 128;
                 }
-                bus.mem_set8(self.sp, flags);
+                bus.write(self.sp, flags);
             }
             _ => panic!("Instrução não encontrada: {inst:X} / {inst:b}"),
         }
     }
 
-    pub(super) fn pop(&mut self, inst: u8, bus: &mut Bus) {
-        if self.sp == 0xCFFF {
-            self.sp = 0x0000;
-        }
+    pub(super) fn pop(&mut self, inst: u8, bus: &mut impl Bus8085) {
         let which = (inst >> 4) & 0x03;
         match which {
             0 => {
                 // BC
-                self.c = bus.mem_get8(self.sp);
-                self.sp += 1;
-                self.b = bus.mem_get8(self.sp);
-                self.sp += 1;
+                self.c = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
+                self.b = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
             }
             1 => {
                 // DE
-                self.e = bus.mem_get8(self.sp);
-                self.sp += 1;
-                self.d = bus.mem_get8(self.sp);
-                self.sp += 1;
+                self.e = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
+                self.d = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
             }
             2 => {
                 // HL
-                self.l = bus.mem_get8(self.sp);
-                self.sp += 1;
-                self.h = bus.mem_get8(self.sp);
-                self.sp += 1;
+                self.l = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
+                self.h = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
             }
             3 => {
                 // PSW - AF
-                let flags = bus.mem_get8(self.sp);
+                let flags = bus.read(self.sp);
                 self.s = (flags & 0x80) == 0x80;
                 self.z = (flags & 0x40) == 0x40;
                 self.ac = (flags & 0x10) == 0x10;
                 self.p = (flags & 0x04) == 0x04;
                 self.cy = (flags & 0x01) == 0x01;
-                self.sp += 1;
-                self.a = bus.mem_get8(self.sp);
-                self.sp += 1;
+                self.sp = self.sp.wrapping_add(1);
+                self.a = bus.read(self.sp);
+                self.sp = self.sp.wrapping_add(1);
             }
             _ => panic!("ERRO: Instrução não encontrada: {inst:X} / {inst:b}"),
         }
-        if self.sp >= 0xCFFF {
-            self.sp = 0xC000;
-        }
     }
 
     pub(super) fn sphl(&mut self) {
         self.sp = self.get_reg_pair(2);
     }
 
-    pub(super) fn xthl(&mut self, bus: &mut Bus) {
-        let tmp_l: u8 = bus.mem_get8(self.sp);
-        let tmp_h: u8 = bus.mem_get8(self.sp + 1);
-        bus.mem_set8(self.sp, self.l);
-        bus.mem_set8(self.sp + 1, self.h);
+    pub(super) fn xthl(&mut self, bus: &mut impl Bus8085) {
+        let tmp_l: u8 = bus.read(self.sp);
+        let tmp_h: u8 = bus.read(self.sp.wrapping_add(1));
+        bus.write(self.sp, self.l);
+        bus.write(self.sp.wrapping_add(1), self.h);
         self.l = tmp_l;
         self.h = tmp_h;
     }
@@ -424,242 +416,51 @@ impl CPU {
         self.pc = self.get_reg_pair(2);
     }
 
-    pub(super) fn jump(&mut self, inst: u8, bus: &Bus) {
-        match inst {
-            0xC3 => {
-                // jmp
-                self.pc = self.fetch16(bus);
-            }
-            0xDA => {
-                // jc
-                if self.cy {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xD2 => {
-                // jnc
-                if !self.cy {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xCA => {
-                // jz
-                if self.z {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xC2 => {
-                // jnz
-                if !self.z {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xF2 => {
-                // jp
-                if !self.s {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xFA => {
-                // jn
-                if self.s {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xEA => {
-                // jpe
-                if self.p {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xE2 => {
-                // jpo
-                if !self.p {
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            _ => panic!("Instrução não encontrada: {inst:X} / {inst:b}"),
+    pub(super) fn jump(&mut self, inst: u8, bus: &mut impl Bus8085) {
+        if inst == 0xC3 {
+            self.pc = self.fetch16(bus);
+        } else if self.condition(inst) {
+            self.pc = self.fetch_target(bus, true);
+            self.branch_taken(inst);
+        } else {
+            self.fetch_target(bus, false);
         }
     }
 
-    pub(super) fn call(&mut self, inst: u8, bus: &mut Bus) {
-        if self.sp <= 0xC000 {
-            self.sp = 0xD000;
-        }
-        match inst {
-            0xCD => {
-                // call
-                self.sp -= 2;
-                bus.mem_set16_reverse(self.sp, self.pc + 2);
-                self.pc = self.fetch16(bus);
-            }
-            0xDC => {
-                // cc
-                if self.cy {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xD4 => {
-                // cnc
-                if !self.cy {
-                    self.pc = self.fetch16(bus);
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                }
-            }
-            0xCC => {
-                // cz
-                if self.z {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xC4 => {
-                // cnz
-                if !self.z {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xF4 => {
-                // cp
-                if !self.s {
-                    self.pc = self.fetch16(bus);
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                }
-            }
-            0xFC => {
-                // cn
-                if self.s {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xEC => {
-                // cpe
-                if self.p {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            0xE4 => {
-                // cpo
-                if !self.p {
-                    self.sp -= 2;
-                    bus.mem_set16_reverse(self.sp, self.pc + 2);
-                    self.pc = self.fetch16(bus);
-                }
-            }
-            _ => panic!("ERR: Instrução não encontrada: {inst:X} / {inst:b}"),
+    pub(super) fn call(&mut self, inst: u8, bus: &mut impl Bus8085) {
+        let taken = inst == 0xCD || self.condition(inst);
+        let addr = self.fetch_target(bus, taken);
+        if taken {
+            self.branch_taken(inst);
+            self.call_to(bus, addr, None);
         }
     }
 
-    pub(super) fn ret(&mut self, inst: u8, bus: &Bus) {
-        if self.sp == 0xCFFF {
-            self.sp = 0x0000;
-        }
-        match inst {
-            0xC9 => {
-                // ret
-                self.pc = bus.mem_get16_reverse(self.sp);
-                self.sp += 2;
-            }
-            0xD8 => {
-                // rc
-                if self.cy {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xD0 => {
-                // rnc
-                if !self.cy {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xC8 => {
-                // rz
-                if self.z {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xC0 => {
-                // rnz
-                if !self.z {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xF0 => {
-                //rp
-                if !self.s {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xF8 => {
-                // rm
-                if self.s {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xE8 => {
-                // rpe
-                if self.p {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            0xE0 => {
-                // rpo
-                if !self.p {
-                    self.pc = bus.mem_get16_reverse(self.sp);
-                    self.sp += 2;
-                }
-            }
-            _ => panic!("ERRO: Instrução não encontrada: {inst:X} / {inst:b}"),
-        }
-        if self.sp >= 0xCFFF {
-            self.sp = 0xC000;
+    pub(super) fn ret(&mut self, inst: u8, bus: &mut impl Bus8085) {
+        if inst == 0xC9 || self.condition(inst) {
+            self.pop_frame();
+            self.branch_taken(inst);
+            self.pc = self.read16(bus, self.sp);
+            self.sp = self.sp.wrapping_add(2);
         }
     }
 
-    pub(super) fn rst(&mut self, inst: u8, bus: &mut Bus) {
-        if self.sp <= 0xC000 {
-            self.sp = 0xD000;
-        }
-        self.sp -= 2;
-        bus.mem_set16_reverse(self.sp, self.pc + 2);
-
-        if inst >= 0xC7 {
-            self.pc = (inst as u16) & 0b0011_1000;
-        }
-        else if inst >= 0x24 && inst <= 0x3C {
-            self.pc = (inst as u16) & 0b0011_1100;
-        }
+    pub(super) fn rst(&mut self, inst: u8, bus: &mut impl Bus8085) {
+        self.call_to(bus, (inst & 0x38) as u16, None);
     }
 
-    pub(super) fn ana(&mut self, bus: &mut Bus, inst: u8) {
+    pub(super) fn ana(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let which = inst & 0x07;
-        self.a &= self.get_reg(bus, which);
+        let value = self.get_reg(bus, which);
+        self.update_and_ac(value);
+        self.a &= value;
         self.update_p(self.a);
         self.update_s(self.a);
         self.update_z(self.a);
         self.cy = false;
-        self.ac = true;
     }
 
-    pub(super) fn ora(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn ora(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let which = inst & 0x07;
         self.a |= self.get_reg(bus, which);
         self.update_p(self.a);
@@ -669,7 +470,7 @@ impl CPU {
         self.ac = false;
     }
 
-    pub(super) fn xra(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn xra(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let which = inst & 0x07;
         self.a ^= self.get_reg(bus, which);
         self.update_p(self.a);
@@ -679,7 +480,7 @@ impl CPU {
         self.ac = false;
     }
 
-    pub(super) fn cmp(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn cmp(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let which = inst & 0x07;
         if self.a < self.get_reg(bus, which) {
             self.cy = true;
@@ -703,17 +504,25 @@ impl CPU {
         }
     }
 
-    pub(super) fn ani(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn ani(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let immediate = self.fetch8(bus);
+        self.update_and_ac(immediate);
         self.a &= immediate;
         self.update_p(self.a);
         self.update_s(self.a);
         self.update_z(self.a);
         self.cy = false;
-        self.ac = true;
     }
 
-    pub(super) fn ori(&mut self, bus: &Bus, inst: u8) {
+    // AND always sets AC on the 8085, the 8080 sets it to bit 3 of A | operand
+    fn update_and_ac(&mut self, value: u8) {
+        self.ac = match self.model {
+            CpuModel::I8085 => true,
+            CpuModel::I8080 => (self.a | value) & 0x08 != 0,
+        };
+    }
+
+    pub(super) fn ori(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let immediate = self.fetch8(bus);
         self.a |= immediate;
         self.update_p(self.a);
@@ -723,7 +532,7 @@ impl CPU {
         self.ac = false;
     }
 
-    pub(super) fn xri(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn xri(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let immediate = self.fetch8(bus);
         self.a ^= immediate;
         self.update_p(self.a);
@@ -733,7 +542,7 @@ impl CPU {
         self.ac = false;
     }
 
-    pub(super) fn cpi(&mut self, bus: &Bus, inst: u8) {
+    pub(super) fn cpi(&mut self, bus: &mut impl Bus8085, inst: u8) {
         let immediate = self.fetch8(bus);
         if self.a < immediate {
             self.cy = true;
@@ -762,22 +571,28 @@ impl CPU {
 
     pub(super) fn nop(&self) {}
 
-    pub(super) fn io_in(&mut self, bus: &Bus) {
+    pub(super) fn hlt(&mut self) {
+        self.halted = true;
+    }
+
+    pub(super) fn io_in(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch8(bus);
-        self.a = bus.io_get8(addr);
+        self.a = bus.io_read(addr);
     }
 
-    pub(super) fn io_out(&mut self, bus: &mut Bus) {
+    pub(super) fn io_out(&mut self, bus: &mut impl Bus8085) {
         let addr = self.fetch8(bus);
-        bus.io_set8(addr, self.a);
+        bus.io_write(addr, self.a);
     }
 
     pub(super) fn ei(&mut self) {
         self.int = true;
+        self.int_delay = true;
     }
 
     pub(super) fn di(&mut self) {
-       self.int = false;
+        self.int = false;
+        self.int_delay = false;
     }
 
     pub(super) fn rim(&mut self) {
@@ -790,7 +605,9 @@ impl CPU {
         if self.pending_int.rst6_5 { val |= 0b0010_0000; }
         if self.pending_int.rst5_5 { val |= 0b0001_0000; }
 
-        if self.int { val |= 0b0000_1000; }
+        // The first RIM after a TRAP reports the interrupt enable state from before it
+        let ie = self.ie_before_trap.take().unwrap_or(self.int);
+        if ie { val |= 0b0000_1000; }
 
         if self.masked_int.rst7_5 { val |= 0b0000_0100; }
         if self.masked_int.rst6_5 { val |= 0b0000_0010; }
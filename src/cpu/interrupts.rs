@@ -0,0 +1,117 @@
+use super::{CPU, CpuModel, InterruptSource};
+use crate::bus::Bus8085;
+use crate::opcodes;
+
+// Vectors of the hardware interrupts
+const TRAP_VECTOR: u16 = 0x0024;
+const RST7_5_VECTOR: u16 = 0x003C;
+const RST6_5_VECTOR: u16 = 0x0034;
+const RST5_5_VECTOR: u16 = 0x002C;
+
+impl CPU {
+    // Updates the pending interrupts from the input pins, once per step.
+    // Devices on the bus drive `lines` alongside whatever set the pins.
+    pub(super) fn sample_interrupts(&mut self, lines: super::Interrupts) {
+        let mut pins = self.pins.or(lines);
+        // The 8080 only has the INTR input, the other lines are left as set
+        if self.model == CpuModel::I8080 {
+            pins = super::Interrupts { intr: pins.intr, ..Default::default() };
+        }
+
+        // TRAP is edge and level sensitive: a rising edge sets its flip-flop,
+        // but the request is only honoured while the pin stays high
+        if pins.trap && !self.last_pins.trap {
+            self.pending_int.trap = true;
+        }
+        if !pins.trap {
+            self.pending_int.trap = false;
+        }
+
+        // RST 7.5 latches rising edges, even while masked, until it is
+        // acknowledged or reset through SIM
+        if pins.rst7_5 && !self.last_pins.rst7_5 {
+            self.pending_int.rst7_5 = true;
+        }
+
+        // RST 6.5, RST 5.5 and INTR are level sensitive
+        self.pending_int.rst6_5 = pins.rst6_5;
+        self.pending_int.rst5_5 = pins.rst5_5;
+        self.pending_int.intr = pins.intr;
+
+        self.last_pins = pins;
+    }
+
+    // Accepts the highest priority pending interrupt, if any. Returns true
+    // when an interrupt was serviced, which takes the place of an instruction.
+    pub(super) fn accept_interrupt(&mut self, bus: &mut impl Bus8085) -> bool {
+        if self.pending_int.trap {
+            // Acknowledging clears the flip-flop, so a pin held high does not re-fire
+            self.pending_int.trap = false;
+            self.ie_before_trap = Some(self.int);
+            self.enter_interrupt(bus, TRAP_VECTOR, InterruptSource::Trap);
+            return true;
+        }
+
+        // EI only takes effect after the instruction that follows it
+        if !self.int || self.int_delay {
+            return false;
+        }
+
+        if self.pending_int.rst7_5 && !self.masked_int.rst7_5 {
+            self.pending_int.rst7_5 = false;
+            self.enter_interrupt(bus, RST7_5_VECTOR, InterruptSource::Rst7_5);
+        } else if self.pending_int.rst6_5 && !self.masked_int.rst6_5 {
+            self.enter_interrupt(bus, RST6_5_VECTOR, InterruptSource::Rst6_5);
+        } else if self.pending_int.rst5_5 && !self.masked_int.rst5_5 {
+            self.enter_interrupt(bus, RST5_5_VECTOR, InterruptSource::Rst5_5);
+        } else if self.pending_int.intr {
+            self.intr_acknowledge(bus);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    // Internal RST for TRAP and RST n.5: push PC and jump to the vector (12 T-states)
+    fn enter_interrupt(&mut self, bus: &mut impl Bus8085, vector: u16, source: InterruptSource) {
+        self.int = false;
+        self.halted = false;
+        self.cycles += 12;
+        self.call_to(bus, vector, Some(source));
+    }
+
+    // T-states of an instruction jammed in during INTA, same as when fetched
+    fn t_states(&self, opcode: u8) -> u64 {
+        opcodes::get(opcode).map_or(0, |info| info.timing(self.model).0 as u64)
+    }
+
+    // INTR is serviced through INTA cycles, in which the interrupt controller
+    // supplies an opcode instead of memory. RST and CALL push PC; any other
+    // one-byte instruction runs in place of the next one, as EI or NOP would.
+    // Longer instructions and undefined opcodes would need operands no INTA
+    // cycle gives, and are taken as NOP.
+    fn intr_acknowledge(&mut self, bus: &mut impl Bus8085) {
+        self.int = false;
+        self.halted = false;
+        self.inta = true;
+
+        let opcode = bus.inta();
+        match opcode {
+            0xCD => {
+                let lo = bus.inta() as u16;
+                let hi = bus.inta() as u16;
+                self.cycles += self.t_states(opcode);
+                self.call_to(bus, hi << 8 | lo, Some(InterruptSource::Intr));
+            }
+            op if op & 0xC7 == 0xC7 => {
+                self.cycles += self.t_states(opcode);
+                self.call_to(bus, (op & 0x38) as u16, Some(InterruptSource::Intr));
+            }
+            op if opcodes::get(op).is_some_and(|info| info.length == 1) => {
+                self.cycles += self.t_states(op);
+                self.dispatch(bus, op);
+            }
+            _ => self.cycles += self.t_states(0x00),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::{CPU, CpuModel, Frame, InterruptSource, Interrupts};
+use crate::snapshot::{Reader, SnapshotError, Writer};
+
+fn write_interrupts(w: &mut Writer, ints: Interrupts) {
+    for line in [ints.trap, ints.rst7_5, ints.rst6_5, ints.rst5_5, ints.intr] {
+        w.bool(line);
+    }
+}
+
+fn read_interrupts(r: &mut Reader) -> Result<Interrupts, SnapshotError> {
+    Ok(Interrupts {
+        trap: r.bool()?,
+        rst7_5: r.bool()?,
+        rst6_5: r.bool()?,
+        rst5_5: r.bool()?,
+        intr: r.bool()?,
+    })
+}
+
+fn source_code(source: InterruptSource) -> u8 {
+    match source {
+        InterruptSource::Trap => 0,
+        InterruptSource::Rst7_5 => 1,
+        InterruptSource::Rst6_5 => 2,
+        InterruptSource::Rst5_5 => 3,
+        InterruptSource::Intr => 4,
+    }
+}
+
+fn source_from(code: u8) -> Result<InterruptSource, SnapshotError> {
+    match code {
+        0 => Ok(InterruptSource::Trap),
+        1 => Ok(InterruptSource::Rst7_5),
+        2 => Ok(InterruptSource::Rst6_5),
+        3 => Ok(InterruptSource::Rst5_5),
+        4 => Ok(InterruptSource::Intr),
+        other => Err(SnapshotError::Invalid(format!("unknown interrupt source {other}"))),
+    }
+}
+
+impl CPU {
+    pub fn save_state(&self, w: &mut Writer) {
+        w.u8(match self.model {
+            CpuModel::I8085 => 0,
+            CpuModel::I8080 => 1,
+        });
+        for reg in [self.a, self.b, self.c, self.d, self.e, self.h, self.l] {
+            w.u8(reg);
+        }
+        w.u16(self.sp);
+        w.u16(self.pc);
+        for flag in [self.s, self.z, self.ac, self.p, self.cy] {
+            w.bool(flag);
+        }
+        w.bool(self.sid);
+        w.bool(self.sod);
+
+        for ints in [self.pins, self.last_pins, self.pending_int, self.masked_int] {
+            write_interrupts(w, ints);
+        }
+        w.bool(self.int);
+        w.bool(self.int_delay);
+        w.option(self.ie_before_trap, Writer::bool);
+        w.bool(self.inta);
+        w.bool(self.halted);
+        w.u64(self.cycles);
+
+        w.u32(self.call_stack.len() as u32);
+        for frame in &self.call_stack {
+            w.u16(frame.caller);
+            w.u16(frame.target);
+            w.u16(frame.return_addr);
+            w.u16(frame.sp);
+            w.option(frame.interrupt, |w, source| w.u8(source_code(source)));
+        }
+    }
+
+    pub fn load_state(r: &mut Reader) -> Result<CPU, SnapshotError> {
+        let model = match r.u8()? {
+            0 => CpuModel::I8085,
+            1 => CpuModel::I8080,
+            other => return Err(SnapshotError::Invalid(format!("unknown CPU model {other}"))),
+        };
+        let mut cpu = CPU::with_model(model);
+        for reg in [&mut cpu.a, &mut cpu.b, &mut cpu.c, &mut cpu.d, &mut cpu.e, &mut cpu.h, &mut cpu.l] {
+            *reg = r.u8()?;
+        }
+        cpu.sp = r.u16()?;
+        cpu.pc = r.u16()?;
+        for flag in [&mut cpu.s, &mut cpu.z, &mut cpu.ac, &mut cpu.p, &mut cpu.cy] {
+            *flag = r.bool()?;
+        }
+        cpu.sid = r.bool()?;
+        cpu.sod = r.bool()?;
+
+        cpu.pins = read_interrupts(r)?;
+        cpu.last_pins = read_interrupts(r)?;
+        cpu.pending_int = read_interrupts(r)?;
+        cpu.masked_int = read_interrupts(r)?;
+        cpu.int = r.bool()?;
+        cpu.int_delay = r.bool()?;
+        cpu.ie_before_trap = r.option(Reader::bool)?;
+        cpu.inta = r.bool()?;
+        cpu.halted = r.bool()?;
+        cpu.cycles = r.u64()?;
+
+        for _ in 0..r.u32()? {
+            let frame = Frame {
+                caller: r.u16()?,
+                target: r.u16()?,
+                return_addr: r.u16()?,
+                sp: r.u16()?,
+                interrupt: r.option(|r| source_from(r.u8()?))?,
+            };
+            cpu.call_stack.push(frame);
+        }
+        Ok(cpu)
+    }
+}
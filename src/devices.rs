@@ -0,0 +1,10 @@
+// Peripheral chips of 8085 boards, each an IoDevice or a pair of them. The
+// chips are shared behind a handle so frontends and tests can see and drive
+// their pins while the bus owns them.
+pub mod i8155;
+pub mod i8253;
+pub mod i8255;
+
+pub use i8155::I8155;
+pub use i8253::I8253;
+pub use i8255::I8255;
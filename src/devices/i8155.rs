@@ -0,0 +1,533 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    Simulator,
+    bus::device::IoDevice,
+    cpu::{InterruptSource, Interrupts},
+    snapshot::{Reader, SnapshotError, Writer},
+};
+
+pub const RAM_SIZE: u16 = 0x100;
+
+// Registers by offset from the IO base
+const STATUS: u16 = 0;   // Command when written
+const PORT_A: u16 = 1;
+const PORT_B: u16 = 2;
+const PORT_C: u16 = 3;
+const TIMER_LOW: u16 = 4;
+const TIMER_HIGH: u16 = 5; // Count bits 8-13, and the mode in bits 6-7
+
+// Ports used by the chip, from the IO base
+pub const PORTS: u8 = 6;
+
+// Port C has 6 bits, and the high count register 6 bits of the count
+const PORT_C_MASK: u8 = 0x3F;
+const COUNT_HIGH_MASK: u16 = 0x3F;
+
+// Port A and B, as indexes into the per-port fields
+const A: usize = 0;
+const B: usize = 1;
+
+// Shortest count the timer runs with
+const MIN_COUNT: u16 = 2;
+
+// What port C does, from bits 2-3 of the command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PortCMode {
+    Input,      // ALT 1
+    Output,     // ALT 2
+    HandshakeA, // ALT 3: PC0-2 control port A, PC3-5 are outputs
+    HandshakeAB, // ALT 4: PC0-2 control port A, PC3-5 port B
+}
+
+// Timer modes, from bits 6-7 of the high count register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimerMode {
+    SingleSquare,
+    Square,
+    SinglePulse,
+    Pulse,
+}
+
+impl TimerMode {
+    fn from_bits(bits: u8) -> TimerMode {
+        match bits & 0x03 {
+            0 => TimerMode::SingleSquare,
+            1 => TimerMode::Square,
+            2 => TimerMode::SinglePulse,
+            _ => TimerMode::Pulse,
+        }
+    }
+
+    fn is_square(self) -> bool {
+        matches!(self, TimerMode::SingleSquare | TimerMode::Square)
+    }
+
+    fn is_continuous(self) -> bool {
+        matches!(self, TimerMode::Square | TimerMode::Pulse)
+    }
+}
+
+// 14-bit down counter clocked by TIMER IN, here the CPU clock, one count
+// per T-state
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    low: u8,  // Registers as written, loaded on START
+    high: u8,
+    length: u16,
+    mode: TimerMode,
+    count: u16,
+    running: bool,
+    stop_at_tc: bool, // STOP AFTER TC was given
+    reload: bool,     // START was given while running, taken at the next TC
+    flag: bool,       // TIMER bit of the status, set at TC until read
+    pulse: bool,      // TIMER OUT pulse of the last TC, held until the next tick
+}
+
+impl Timer {
+    fn new() -> Timer {
+        Timer {
+            low: 0,
+            high: 0,
+            length: 0,
+            mode: TimerMode::SingleSquare,
+            count: 0,
+            running: false,
+            stop_at_tc: false,
+            reload: false,
+            flag: false,
+            pulse: false,
+        }
+    }
+
+    fn load(&mut self) {
+        self.length = ((self.high as u16 & COUNT_HIGH_MASK) << 8 | self.low as u16).max(MIN_COUNT);
+        self.mode = TimerMode::from_bits(self.high >> 6);
+        self.count = self.length;
+    }
+
+    fn command(&mut self, bits: u8) {
+        match bits {
+            1 => self.running = false,
+            2 => self.stop_at_tc = self.running,
+            3 if self.running => self.reload = true,
+            3 => {
+                self.load();
+                self.running = true;
+                self.stop_at_tc = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.pulse = false;
+        let mut left = cycles;
+        while self.running && left > 0 {
+            let counted = left.min(self.count as u64);
+            self.count -= counted as u16;
+            left -= counted;
+            if self.count == 0 {
+                self.terminal_count();
+            }
+        }
+    }
+
+    fn terminal_count(&mut self) {
+        self.flag = true;
+        self.pulse = !self.mode.is_square();
+        if self.reload {
+            self.reload = false;
+            self.load();
+        } else if self.stop_at_tc || !self.mode.is_continuous() {
+            self.running = false;
+            self.stop_at_tc = false;
+            self.count = self.length;
+        } else {
+            self.count = self.length;
+        }
+    }
+
+    // TIMER OUT, which idles high. Square waves are high for the first half
+    // of the count, the larger one when it is odd, and pulses go low at TC.
+    fn out(&self) -> bool {
+        if self.mode.is_square() {
+            !self.running || self.count > self.length / 2
+        } else {
+            !self.pulse
+        }
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        for byte in [self.low, self.high] {
+            w.u8(byte);
+        }
+        w.u16(self.length);
+        w.u8(self.mode as u8);
+        w.u16(self.count);
+        for flag in [self.running, self.stop_at_tc, self.reload, self.flag, self.pulse] {
+            w.bool(flag);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.low = r.u8()?;
+        self.high = r.u8()?;
+        self.length = r.u16()?;
+        self.mode = TimerMode::from_bits(r.u8()?);
+        self.count = r.u16()?;
+        for flag in [&mut self.running, &mut self.stop_at_tc, &mut self.reload, &mut self.flag, &mut self.pulse] {
+            *flag = r.bool()?;
+        }
+        Ok(())
+    }
+}
+
+// Intel 8155 RAM, IO ports and timer, as on the SDK-85. The 8156 only
+// differs in the polarity of its chip enable, so it is the same here.
+// Ports A and B are 8 bits and port C 6 bits; in the handshake modes port C
+// carries INTR, BF and STB for A, and for B in ALT 4. TIMER OUT and the
+// port INTR outputs can each drive an interrupt input.
+#[derive(Debug, Clone)]
+pub struct I8155 {
+    ram: Vec<u8>,
+    command: u8,
+    out: [u8; 3],   // Output latches of A, B and C
+    pins: [u8; 3],  // Levels driven from outside on A, B and C
+    latch: [u8; 2], // Input of A and B latched by STB
+    bf: [bool; 2],  // Buffer full
+    intr: [bool; 2],
+    timer: Timer,
+    timer_irq: Option<InterruptSource>,
+    port_irq: [Option<InterruptSource>; 2],
+}
+
+impl Default for I8155 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I8155 {
+    pub fn new() -> I8155 {
+        I8155 {
+            ram: vec![0; RAM_SIZE as usize],
+            command: 0,
+            out: [0; 3],
+            pins: [0xFF, 0xFF, PORT_C_MASK], // Pulled up
+            latch: [0; 2],
+            bf: [false; 2],
+            intr: [false; 2],
+            timer: Timer::new(),
+            timer_irq: None,
+            port_irq: [None; 2],
+        }
+    }
+
+    // Inverted TIMER OUT drives `line`, so it is high while the output is low
+    pub fn with_timer_irq(mut self, line: InterruptSource) -> I8155 {
+        self.timer_irq = Some(line);
+        self
+    }
+
+    // INTR of port A drives `line` in the handshake modes
+    pub fn with_port_a_irq(mut self, line: InterruptSource) -> I8155 {
+        self.port_irq[A] = Some(line);
+        self
+    }
+
+    pub fn with_port_b_irq(mut self, line: InterruptSource) -> I8155 {
+        self.port_irq[B] = Some(line);
+        self
+    }
+
+    // Puts the chip on the bus with its ports from io_base and its RAM from
+    // ram_base, and returns a handle to reach its pins
+    pub fn attach(self, sim: &mut Simulator, io_base: u8, ram_base: u16) -> Rc<RefCell<I8155>> {
+        let chip = Rc::new(RefCell::new(self));
+        sim.add_io_device(io_base, io_base.wrapping_add(PORTS - 1), Box::new(Ports(chip.clone())));
+        sim.add_mem_device(ram_base, ram_base.wrapping_add(RAM_SIZE - 1), Box::new(Ram(chip.clone())));
+        chip
+    }
+
+    fn port_c_mode(&self) -> PortCMode {
+        match (self.command >> 2) & 0x03 {
+            0 => PortCMode::Input,
+            3 => PortCMode::Output,
+            1 => PortCMode::HandshakeA,
+            _ => PortCMode::HandshakeAB,
+        }
+    }
+
+    fn is_output(&self, port: usize) -> bool {
+        self.command & (1 << port) != 0
+    }
+
+    fn inte(&self, port: usize) -> bool {
+        self.command & (0x10 << port) != 0
+    }
+
+    fn handshake(&self, port: usize) -> bool {
+        match self.port_c_mode() {
+            PortCMode::HandshakeA => port == A,
+            PortCMode::HandshakeAB => true,
+            _ => false,
+        }
+    }
+
+    fn status(&self) -> u8 {
+        self.intr[A] as u8
+            | (self.bf[A] as u8) << 1
+            | (self.inte(A) as u8) << 2
+            | (self.intr[B] as u8) << 3
+            | (self.bf[B] as u8) << 4
+            | (self.inte(B) as u8) << 5
+            | (self.timer.flag as u8) << 6
+    }
+
+    // Port A or B as a read would see it
+    fn port(&self, port: usize) -> u8 {
+        if self.is_output(port) {
+            self.out[port]
+        } else if self.handshake(port) {
+            self.latch[port]
+        } else {
+            self.pins[port]
+        }
+    }
+
+    // Levels on port A, the output latch when it is an output
+    pub fn port_a(&self) -> u8 {
+        if self.is_output(A) { self.out[A] } else { self.pins[A] }
+    }
+
+    pub fn port_b(&self) -> u8 {
+        if self.is_output(B) { self.out[B] } else { self.pins[B] }
+    }
+
+    // Levels on PC0-PC5, with the handshake signals where port C carries them
+    pub fn port_c(&self) -> u8 {
+        let control = |port: usize| self.intr[port] as u8 | (self.bf[port] as u8) << 1 | 0x04;
+        match self.port_c_mode() {
+            PortCMode::Input => self.pins[2] & PORT_C_MASK,
+            PortCMode::Output => self.out[2] & PORT_C_MASK,
+            PortCMode::HandshakeA => control(A) | (self.out[2] & 0x38),
+            PortCMode::HandshakeAB => control(A) | control(B) << 3,
+        }
+    }
+
+    // Drives the pins of a port from outside, seen when it is an input
+    pub fn set_port_a(&mut self, value: u8) {
+        self.pins[A] = value;
+    }
+
+    pub fn set_port_b(&mut self, value: u8) {
+        self.pins[B] = value;
+    }
+
+    pub fn set_port_c(&mut self, value: u8) {
+        self.pins[2] = value & PORT_C_MASK;
+    }
+
+    // Pulses STB of port A: in the handshake modes an input latches the
+    // pins, and an output takes it as the peripheral's acknowledge
+    pub fn strobe_a(&mut self) {
+        self.strobe(A);
+    }
+
+    pub fn strobe_b(&mut self) {
+        self.strobe(B);
+    }
+
+    fn strobe(&mut self, port: usize) {
+        if !self.handshake(port) {
+            return;
+        }
+        if self.is_output(port) {
+            self.bf[port] = false;
+        } else {
+            self.latch[port] = self.pins[port];
+            self.bf[port] = true;
+        }
+        self.intr[port] = self.inte(port);
+    }
+
+    // TIMER OUT, which idles high
+    pub fn timer_out(&self) -> bool {
+        self.timer.out()
+    }
+
+    pub fn is_timer_running(&self) -> bool {
+        self.timer.running
+    }
+
+    fn peek(&self, reg: u16) -> u8 {
+        match reg {
+            STATUS => self.status(),
+            PORT_A => self.port(A),
+            PORT_B => self.port(B),
+            PORT_C => self.port_c(),
+            TIMER_LOW => self.timer.count as u8,
+            TIMER_HIGH => (self.timer.count >> 8) as u8 | (self.timer.high & 0xC0),
+            _ => 0xFF,
+        }
+    }
+
+    fn read(&mut self, reg: u16) -> u8 {
+        let value = self.peek(reg);
+        match reg {
+            // Reading the status clears the timer's TC flag
+            STATUS => self.timer.flag = false,
+            PORT_A | PORT_B => {
+                let port = (reg - PORT_A) as usize;
+                if self.handshake(port) && !self.is_output(port) {
+                    self.bf[port] = false;
+                    self.intr[port] = false;
+                }
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            STATUS => {
+                self.command = value;
+                self.timer.command(value >> 6);
+            }
+            PORT_A | PORT_B => {
+                let port = (reg - PORT_A) as usize;
+                self.out[port] = value;
+                if self.handshake(port) && self.is_output(port) {
+                    self.bf[port] = true;
+                    self.intr[port] = false;
+                }
+            }
+            PORT_C => self.out[2] = value & PORT_C_MASK,
+            TIMER_LOW | TIMER_HIGH => {
+                if reg == TIMER_LOW {
+                    self.timer.low = value;
+                } else {
+                    self.timer.high = value;
+                }
+                // A stopped timer shows what will be loaded
+                if !self.timer.running {
+                    self.timer.count = (self.timer.high as u16 & COUNT_HIGH_MASK) << 8 | self.timer.low as u16;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> Interrupts {
+        let mut lines = Interrupts::default();
+        if let Some(line) = self.timer_irq.filter(|_| !self.timer.out()) {
+            lines = lines.or(line.into());
+        }
+        for port in [A, B] {
+            if let Some(line) = self.port_irq[port].filter(|_| self.handshake(port) && self.intr[port]) {
+                lines = lines.or(line.into());
+            }
+        }
+        lines
+    }
+
+    // Everything but the RAM, which its own slot saves
+    fn save_state(&self, w: &mut Writer) {
+        w.u8(self.command);
+        for byte in self.out.iter().chain(&self.pins).chain(&self.latch) {
+            w.u8(*byte);
+        }
+        for flag in self.bf.iter().chain(&self.intr) {
+            w.bool(*flag);
+        }
+        self.timer.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.command = r.u8()?;
+        for byte in self.out.iter_mut().chain(&mut self.pins).chain(&mut self.latch) {
+            *byte = r.u8()?;
+        }
+        for flag in self.bf.iter_mut().chain(&mut self.intr) {
+            *flag = r.bool()?;
+        }
+        self.timer.load_state(r)
+    }
+}
+
+// The chip in IO space. It runs the timer and keeps the state of the chip
+// but the RAM.
+#[derive(Debug)]
+struct Ports(Rc<RefCell<I8155>>);
+
+impl IoDevice for Ports {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.borrow_mut().read(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.0.borrow().peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.0.borrow_mut().write(addr, value);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.0.borrow_mut().timer.tick(cycles);
+    }
+
+    fn is_idle(&self) -> bool {
+        let timer = &self.0.borrow().timer;
+        !timer.running && !timer.pulse
+    }
+
+    fn irq(&self) -> Interrupts {
+        self.0.borrow().irq()
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        self.0.borrow().save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.0.borrow_mut().load_state(r)
+    }
+}
+
+// The chip's RAM in memory space
+#[derive(Debug)]
+struct Ram(Rc<RefCell<I8155>>);
+
+impl IoDevice for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.0.borrow().ram[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.0.borrow_mut().ram[addr as usize] = value;
+    }
+
+    fn is_idle(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        w.bytes(&self.0.borrow().ram);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        let ram = r.bytes()?;
+        if ram.len() != RAM_SIZE as usize {
+            return Err(SnapshotError::Invalid(format!("8155 RAM of {} bytes", ram.len())));
+        }
+        self.0.borrow_mut().ram.copy_from_slice(ram);
+        Ok(())
+    }
+}
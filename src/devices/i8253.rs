@@ -0,0 +1,635 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    Simulator,
+    bus::device::IoDevice,
+    cpu::{InterruptSource, Interrupts},
+    snapshot::{Reader, SnapshotError, Writer},
+};
+
+// Counters 0-2 are at offsets 0-2 from the IO base, and the control word at 3
+const CONTROL: u16 = 3;
+
+// Ports used by the chip, from the IO base
+pub const PORTS: u8 = 4;
+
+pub const COUNTERS: usize = 3;
+
+// Counter select of a control word that is a readback command on the 8254
+const READBACK: u8 = 3;
+
+// Which bytes of the count reads and writes take, from bits 4-5 of the
+// control word. 0 is the counter latch command.
+const RW_LATCH: u8 = 0;
+const RW_LSB: u8 = 1;
+const RW_MSB: u8 = 2;
+const RW_BOTH: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PitModel {
+    #[default]
+    I8253,
+    I8254, // Adds the readback command
+}
+
+// What drives the CLK input of a counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clock {
+    // The CPU clock through the chip's divider
+    #[default]
+    Cpu,
+    // Pulses given through the handle, for event counting
+    External,
+    // Rising edges of another counter's OUT, to cascade them
+    Counter(usize),
+}
+
+// One 16-bit down counter. The count element is kept as a binary value below
+// the modulus, where 0 stands for the full 65536 (10000 in BCD) counts.
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    rw: u8,
+    mode: u8,
+    bcd: bool,
+    reload: u16,      // Count register, as written
+    value: u32,       // Count element
+    out: bool,
+    gate: bool,
+    has_count: bool,  // A count was written since the control word
+    running: bool,    // The count element was loaded
+    load: bool,       // Loads the count register on the next CLK
+    armed: bool,      // Modes 4 and 5 strobe once per load
+    strobe: bool,     // OUT is low for this one CLK
+    null_count: bool, // The count register wasn't loaded yet
+    write_msb: bool,  // The next byte written is the MSB of a count
+    low: u8,          // LSB written, waiting for the MSB
+    read_msb: bool,   // The next byte read is the MSB
+    latched: Option<u16>,
+    status: Option<u8>,
+}
+
+impl Counter {
+    fn new() -> Counter {
+        Counter {
+            rw: RW_LSB,
+            mode: 0,
+            bcd: false,
+            reload: 0,
+            value: 0,
+            out: false,
+            gate: true,
+            has_count: false,
+            running: false,
+            load: false,
+            armed: false,
+            strobe: false,
+            null_count: true,
+            write_msb: false,
+            low: 0,
+            read_msb: false,
+            latched: None,
+            status: None,
+        }
+    }
+
+    fn modulus(&self) -> u32 {
+        if self.bcd { 10000 } else { 0x10000 }
+    }
+
+    // Counts left before the count element reaches 0
+    fn remaining(&self) -> u32 {
+        if self.value == 0 { self.modulus() } else { self.value }
+    }
+
+    // The count register as a value of the count element
+    fn initial(&self) -> u32 {
+        if self.bcd {
+            let digits = (0..4).map(|i| ((self.reload >> (4 * i)) & 0x0F).min(9) as u32);
+            digits.rev().fold(0, |value, digit| value * 10 + digit)
+        } else {
+            self.reload as u32
+        }
+    }
+
+    // The count element as read, in BCD when counting in BCD
+    fn count(&self) -> u16 {
+        if self.bcd {
+            (0..4).rev().fold(0, |bcd, i| bcd << 4 | (self.value / 10u32.pow(i) % 10) as u16)
+        } else {
+            self.value as u16
+        }
+    }
+
+    fn status_byte(&self) -> u8 {
+        (self.out as u8) << 7 | (self.null_count as u8) << 6 | self.rw << 4 | self.mode << 1 | self.bcd as u8
+    }
+
+    fn control(&mut self, value: u8) {
+        let rw = (value >> 4) & 0x03;
+        if rw == RW_LATCH {
+            self.latch_count();
+            return;
+        }
+        let gate = self.gate;
+        *self = Counter::new();
+        self.gate = gate;
+        self.rw = rw;
+        // Modes 6 and 7 are 2 and 3
+        self.mode = match (value >> 1) & 0x07 {
+            mode @ 6..=7 => mode - 4,
+            mode => mode,
+        };
+        self.bcd = value & 0x01 != 0;
+        self.out = self.mode != 0;
+    }
+
+    fn latch_count(&mut self) {
+        if self.latched.is_none() {
+            self.latched = Some(self.count());
+            self.read_msb = false;
+        }
+    }
+
+    fn latch_status(&mut self) {
+        if self.status.is_none() {
+            self.status = Some(self.status_byte());
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        match self.rw {
+            RW_LSB => self.reload = value as u16,
+            RW_MSB => self.reload = (value as u16) << 8,
+            _ if !self.write_msb => {
+                self.low = value;
+                self.write_msb = true;
+                // Mode 0 stops counting until the count is complete
+                if self.mode == 0 {
+                    self.out = false;
+                }
+                return;
+            }
+            _ => {
+                self.reload = (value as u16) << 8 | self.low as u16;
+                self.write_msb = false;
+            }
+        }
+        self.has_count = true;
+        self.null_count = true;
+        match self.mode {
+            0 => {
+                self.out = false;
+                self.load = true;
+            }
+            // Taken at the end of the period once running
+            2 | 3 => self.load |= !self.running,
+            4 => self.load = true,
+            // Waits for a trigger on GATE
+            _ => {}
+        }
+    }
+
+    fn peek(&self) -> u8 {
+        if let Some(status) = self.status {
+            return status;
+        }
+        let count = self.latched.unwrap_or_else(|| self.count());
+        let msb = match self.rw {
+            RW_MSB => true,
+            RW_BOTH => self.read_msb,
+            _ => false,
+        };
+        if msb { (count >> 8) as u8 } else { count as u8 }
+    }
+
+    fn read(&mut self) -> u8 {
+        let value = self.peek();
+        if self.status.take().is_some() {
+            return value;
+        }
+        if self.rw == RW_BOTH {
+            self.read_msb = !self.read_msb;
+            if self.read_msb {
+                return value;
+            }
+        }
+        self.latched = None;
+        value
+    }
+
+    fn set_gate(&mut self, level: bool) {
+        let rising = level && !self.gate;
+        self.gate = level;
+        match self.mode {
+            1 | 5 if rising && self.has_count => self.load = true,
+            2 | 3 if rising && self.running => self.load = true,
+            // A low GATE holds OUT high in modes 2 and 3
+            2 | 3 if !level => {
+                self.out = true;
+                self.strobe = false;
+            }
+            _ => {}
+        }
+    }
+
+    // Whether a CLK decrements the count element
+    fn enabled(&self) -> bool {
+        let gated = matches!(self.mode, 0 | 2 | 3 | 4) && !self.gate;
+        let writing = self.mode == 0 && self.write_msb;
+        self.running && !gated && !writing
+    }
+
+    // CLK pulses that only decrement the count element, before the next one
+    // that changes OUT or loads the counter
+    fn quiet_pulses(&self) -> u64 {
+        if self.load || self.strobe {
+            return 0;
+        }
+        if !self.enabled() {
+            return u64::MAX;
+        }
+        let remaining = self.remaining() as u64;
+        match self.mode {
+            0 | 1 if !self.out => remaining - 1,
+            4 | 5 if self.armed => remaining - 1,
+            2 => remaining.saturating_sub(2),
+            3 if remaining.is_multiple_of(2) => remaining / 2 - 1,
+            3 => 0,
+            // Counts around without changing OUT
+            _ => u64::MAX,
+        }
+    }
+
+    fn skip(&mut self, pulses: u64) {
+        if !self.enabled() {
+            return;
+        }
+        let modulus = self.modulus() as u64;
+        let step = if self.mode == 3 { 2 } else { 1 };
+        let counted = (pulses % modulus) * step % modulus;
+        self.value = ((self.value as u64 + modulus - counted) % modulus) as u32;
+    }
+
+    fn pulse(&mut self) {
+        if self.load {
+            self.load = false;
+            self.null_count = false;
+            self.running = true;
+            self.value = self.initial() % self.modulus();
+            self.armed = true;
+            self.strobe = false;
+            self.out = self.mode != 0 && self.mode != 1;
+            return;
+        }
+        if self.strobe {
+            self.strobe = false;
+            self.out = true;
+            // Mode 2 reloads as OUT goes back high
+            if self.mode == 2 {
+                self.null_count = false;
+                self.value = self.initial() % self.modulus();
+                return;
+            }
+        }
+        if !self.enabled() {
+            return;
+        }
+        if self.mode == 3 {
+            // Odd counts take one more CLK high than low
+            let remaining = self.remaining();
+            let step = match (!remaining.is_multiple_of(2), self.out) {
+                (true, true) => 1,
+                (true, false) => 3,
+                _ => 2,
+            };
+            if remaining <= step {
+                self.out = !self.out;
+                self.null_count = false;
+                self.value = self.initial() % self.modulus();
+            } else {
+                self.value = remaining - step;
+            }
+            return;
+        }
+        self.value = (self.value + self.modulus() - 1) % self.modulus();
+        match self.mode {
+            0 | 1 if self.value == 0 => self.out = true,
+            2 if self.value == 1 => {
+                self.out = false;
+                self.strobe = true;
+            }
+            4 | 5 if self.value == 0 && self.armed => {
+                self.armed = false;
+                self.out = false;
+                self.strobe = true;
+            }
+            _ => {}
+        }
+    }
+
+    // Runs the counter for a number of CLK pulses, and returns how many
+    // times OUT went high
+    fn run(&mut self, mut pulses: u64) -> u64 {
+        let mut edges = 0;
+        while pulses > 0 {
+            let quiet = self.quiet_pulses().min(pulses);
+            if quiet > 0 {
+                self.skip(quiet);
+                pulses -= quiet;
+                continue;
+            }
+            let out = self.out;
+            self.pulse();
+            edges += (!out && self.out) as u64;
+            pulses -= 1;
+        }
+        edges
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        for byte in [self.rw, self.mode, self.low] {
+            w.u8(byte);
+        }
+        w.u16(self.reload);
+        w.u32(self.value);
+        let flags = [
+            self.bcd,
+            self.out,
+            self.gate,
+            self.has_count,
+            self.running,
+            self.load,
+            self.armed,
+            self.strobe,
+            self.null_count,
+            self.write_msb,
+            self.read_msb,
+        ];
+        for flag in flags {
+            w.bool(flag);
+        }
+        w.bool(self.latched.is_some());
+        w.u16(self.latched.unwrap_or(0));
+        w.bool(self.status.is_some());
+        w.u8(self.status.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.rw = r.u8()?;
+        self.mode = r.u8()?;
+        self.low = r.u8()?;
+        if self.rw > RW_BOTH || self.mode > 5 {
+            return Err(SnapshotError::Invalid(format!("8253 counter in mode {}", self.mode)));
+        }
+        self.reload = r.u16()?;
+        self.value = r.u32()?;
+        let flags = [
+            &mut self.bcd,
+            &mut self.out,
+            &mut self.gate,
+            &mut self.has_count,
+            &mut self.running,
+            &mut self.load,
+            &mut self.armed,
+            &mut self.strobe,
+            &mut self.null_count,
+            &mut self.write_msb,
+            &mut self.read_msb,
+        ];
+        for flag in flags {
+            *flag = r.bool()?;
+        }
+        let latched = r.bool()?;
+        let count = r.u16()?;
+        self.latched = latched.then_some(count);
+        let status = r.bool()?;
+        let byte = r.u8()?;
+        self.status = status.then_some(byte);
+        if self.value >= self.modulus() {
+            return Err(SnapshotError::Invalid(format!("8253 count of {}", self.value)));
+        }
+        Ok(())
+    }
+}
+
+// Intel 8253 programmable interval timer, or the 8254 with its readback
+// command. Each counter runs in one of the six modes, in binary or BCD, from
+// its own clock: the CPU clock through a divider, pulses given from outside,
+// or another counter's OUT. The OUT pins can each drive an interrupt input,
+// and frontends wire them on to other devices through the handle.
+#[derive(Debug, Clone)]
+pub struct I8253 {
+    model: PitModel,
+    counters: [Counter; COUNTERS],
+    clocks: [Clock; COUNTERS],
+    out_irq: [Option<InterruptSource>; COUNTERS],
+    divider: u64,
+    phase: u64, // T-states since the last CLK from the CPU
+}
+
+impl Default for I8253 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I8253 {
+    pub fn new() -> I8253 {
+        I8253 {
+            model: PitModel::I8253,
+            counters: [Counter::new(); COUNTERS],
+            clocks: [Clock::Cpu; COUNTERS],
+            out_irq: [None; COUNTERS],
+            divider: 1,
+            phase: 0,
+        }
+    }
+
+    pub fn with_model(mut self, model: PitModel) -> I8253 {
+        self.model = model;
+        self
+    }
+
+    // Counters clocked by the CPU count once every `divider` T-states
+    pub fn with_divider(mut self, divider: u64) -> I8253 {
+        assert!(divider > 0, "divisor do 8253 nulo");
+        self.divider = divider;
+        self
+    }
+
+    pub fn with_clock(mut self, counter: usize, clock: Clock) -> I8253 {
+        self.clocks[counter] = clock;
+        // Follows the chain of sources, which can't come back to the counter
+        let mut source = clock;
+        for _ in 0..COUNTERS {
+            match source {
+                Clock::Counter(other) if other == counter => panic!("ciclo de relógios no 8253"),
+                Clock::Counter(other) => source = self.clocks[other],
+                _ => break,
+            }
+        }
+        self
+    }
+
+    // OUT of `counter` drives `line` while it is high
+    pub fn with_out_irq(mut self, counter: usize, line: InterruptSource) -> I8253 {
+        self.out_irq[counter] = Some(line);
+        self
+    }
+
+    // Puts the chip on the bus with its ports from io_base, and returns a
+    // handle to reach its pins
+    pub fn attach(self, sim: &mut Simulator, io_base: u8) -> Rc<RefCell<I8253>> {
+        let chip = Rc::new(RefCell::new(self));
+        sim.add_io_device(io_base, io_base.wrapping_add(PORTS - 1), Box::new(Ports(chip.clone())));
+        chip
+    }
+
+    pub fn out(&self, counter: usize) -> bool {
+        self.counters[counter].out
+    }
+
+    pub fn gate(&self, counter: usize) -> bool {
+        self.counters[counter].gate
+    }
+
+    // Drives GATE of a counter, which idles high
+    pub fn set_gate(&mut self, counter: usize, level: bool) {
+        self.counters[counter].set_gate(level);
+    }
+
+    // Gives CLK pulses to a counter with an external clock
+    pub fn clock(&mut self, counter: usize, pulses: u64) {
+        if self.clocks[counter] == Clock::External {
+            self.drive(counter, pulses);
+        }
+    }
+
+    // Runs a counter and the ones it clocks
+    fn drive(&mut self, counter: usize, pulses: u64) {
+        let edges = self.counters[counter].run(pulses);
+        if edges == 0 {
+            return;
+        }
+        for next in 0..COUNTERS {
+            if self.clocks[next] == Clock::Counter(counter) {
+                self.drive(next, edges);
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.phase += cycles;
+        let pulses = self.phase / self.divider;
+        self.phase %= self.divider;
+        if pulses == 0 {
+            return;
+        }
+        for counter in 0..COUNTERS {
+            if self.clocks[counter] == Clock::Cpu {
+                self.drive(counter, pulses);
+            }
+        }
+    }
+
+    fn peek(&self, reg: u16) -> u8 {
+        match self.counters.get(reg as usize) {
+            Some(counter) => counter.peek(),
+            // The control word can't be read back
+            None => 0xFF,
+        }
+    }
+
+    fn read(&mut self, reg: u16) -> u8 {
+        match self.counters.get_mut(reg as usize) {
+            Some(counter) => counter.read(),
+            None => 0xFF,
+        }
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        if reg != CONTROL {
+            if let Some(counter) = self.counters.get_mut(reg as usize) {
+                counter.write(value);
+            }
+            return;
+        }
+        match value >> 6 {
+            READBACK if self.model == PitModel::I8254 => {
+                for (i, counter) in self.counters.iter_mut().enumerate() {
+                    if value & (0x02 << i) == 0 {
+                        continue;
+                    }
+                    // COUNT and STATUS are active low
+                    if value & 0x20 == 0 {
+                        counter.latch_count();
+                    }
+                    if value & 0x10 == 0 {
+                        counter.latch_status();
+                    }
+                }
+            }
+            READBACK => {}
+            select => self.counters[select as usize].control(value),
+        }
+    }
+
+    fn irq(&self) -> Interrupts {
+        let mut lines = Interrupts::default();
+        for (counter, line) in self.counters.iter().zip(self.out_irq) {
+            if let Some(line) = line.filter(|_| counter.out) {
+                lines = lines.or(line.into());
+            }
+        }
+        lines
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        for counter in &self.counters {
+            counter.save_state(w);
+        }
+        w.u64(self.phase);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        for counter in &mut self.counters {
+            counter.load_state(r)?;
+        }
+        self.phase = r.u64()?;
+        Ok(())
+    }
+}
+
+// The chip in IO space
+#[derive(Debug)]
+struct Ports(Rc<RefCell<I8253>>);
+
+impl IoDevice for Ports {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.borrow_mut().read(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.0.borrow().peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.0.borrow_mut().write(addr, value);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.0.borrow_mut().tick(cycles);
+    }
+
+    fn irq(&self) -> Interrupts {
+        self.0.borrow().irq()
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        self.0.borrow().save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.0.borrow_mut().load_state(r)
+    }
+}
@@ -0,0 +1,401 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    Simulator,
+    bus::device::IoDevice,
+    cpu::{InterruptSource, Interrupts},
+    snapshot::{Reader, SnapshotError, Writer},
+};
+
+// Registers by offset from the IO base
+const PORT_A: u16 = 0;
+const PORT_B: u16 = 1;
+const PORT_C: u16 = 2;
+const CONTROL: u16 = 3; // Mode set, or bit set/reset of port C
+
+// Ports used by the chip, from the IO base
+pub const PORTS: u8 = 4;
+
+// Port A, B and C, as indexes into the per-port fields
+const A: usize = 0;
+const B: usize = 1;
+const C: usize = 2;
+
+// Bits of a mode set control word
+const MODE_SET: u8 = 0x80;
+const A_INPUT: u8 = 0x10;
+const C_UPPER_INPUT: u8 = 0x08;
+const B_MODE_1: u8 = 0x04;
+const B_INPUT: u8 = 0x02;
+const C_LOWER_INPUT: u8 = 0x01;
+
+// Handshake of port A or B in modes 1 and 2
+#[derive(Debug, Clone, Copy, Default)]
+struct Handshake {
+    latch: u8,      // Input latched by STB
+    ibf: bool,      // Input buffer full
+    obf: bool,      // Output buffer full, until ACK; the OBF pin is low while set
+    inte_in: bool,  // INTE of the input side, PC4 for A and PC2 for B
+    inte_out: bool, // INTE of the output side, PC6 for A and PC2 for B
+}
+
+// Intel 8255 programmable peripheral interface. Group A is port A and PC4-7,
+// in mode 0, 1 or 2, and group B port B and PC0-3, in mode 0 or 1. In modes 1
+// and 2 port C carries the handshake: STB, IBF and INTR of an input, OBF,
+// ACK and INTR of an output, and the INTR outputs can each drive an
+// interrupt input.
+#[derive(Debug, Clone)]
+pub struct I8255 {
+    control: u8,
+    out: [u8; 3],  // Output latches of A, B and C
+    pins: [u8; 3], // Levels driven from outside on A, B and C
+    handshake: [Handshake; 2],
+    port_irq: [Option<InterruptSource>; 2],
+}
+
+impl Default for I8255 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I8255 {
+    // After reset every port is a mode 0 input
+    pub fn new() -> I8255 {
+        I8255 {
+            control: MODE_SET | A_INPUT | C_UPPER_INPUT | B_INPUT | C_LOWER_INPUT,
+            out: [0; 3],
+            pins: [0xFF; 3], // Pulled up
+            handshake: [Handshake::default(); 2],
+            port_irq: [None; 2],
+        }
+    }
+
+    // INTR A on PC3 drives `line` in modes 1 and 2
+    pub fn with_port_a_irq(mut self, line: InterruptSource) -> I8255 {
+        self.port_irq[A] = Some(line);
+        self
+    }
+
+    // INTR B on PC0 drives `line` in mode 1
+    pub fn with_port_b_irq(mut self, line: InterruptSource) -> I8255 {
+        self.port_irq[B] = Some(line);
+        self
+    }
+
+    // Puts the chip on the bus with its ports from io_base, and returns a
+    // handle to reach its pins
+    pub fn attach(self, sim: &mut Simulator, io_base: u8) -> Rc<RefCell<I8255>> {
+        let chip = Rc::new(RefCell::new(self));
+        sim.add_io_device(io_base, io_base.wrapping_add(PORTS - 1), Box::new(Ports(chip.clone())));
+        chip
+    }
+
+    // Mode of group A: 0, 1 or 2
+    fn mode_a(&self) -> u8 {
+        match (self.control >> 5) & 0x03 {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        }
+    }
+
+    fn mode(&self, port: usize) -> u8 {
+        match port {
+            A => self.mode_a(),
+            _ => (self.control & B_MODE_1 != 0) as u8,
+        }
+    }
+
+    fn is_input(&self, port: usize) -> bool {
+        match port {
+            A => self.control & A_INPUT != 0,
+            _ => self.control & B_INPUT != 0,
+        }
+    }
+
+    // Whether the port latches input on STB, or signals output with OBF
+    fn strobed_input(&self, port: usize) -> bool {
+        match self.mode(port) {
+            0 => false,
+            1 => self.is_input(port),
+            _ => true,
+        }
+    }
+
+    fn strobed_output(&self, port: usize) -> bool {
+        match self.mode(port) {
+            0 => false,
+            1 => !self.is_input(port),
+            _ => true,
+        }
+    }
+
+    fn intr(&self, port: usize) -> bool {
+        let hs = &self.handshake[port];
+        (self.strobed_input(port) && hs.inte_in && hs.ibf) || (self.strobed_output(port) && hs.inte_out && !hs.obf)
+    }
+
+    // Bits of port C carrying the handshake
+    fn control_mask(&self) -> u8 {
+        let upper = match self.mode_a() {
+            0 => 0x00,
+            1 if self.is_input(A) => 0x38,
+            1 => 0xC8,
+            _ => 0xF8,
+        };
+        let lower = if self.mode(B) == 1 { 0x07 } else { 0x00 };
+        upper | lower
+    }
+
+    // The handshake bits of port C. A read sees the INTE flags where the
+    // pins take STB and ACK, which idle high.
+    fn control_bits(&self, read: bool) -> u8 {
+        let [a, b] = &self.handshake;
+        let mut bits = 0;
+        if self.mode_a() != 0 {
+            bits |= (self.intr(A) as u8) << 3;
+            if self.strobed_input(A) {
+                bits |= ((!read || a.inte_in) as u8) << 4 | (a.ibf as u8) << 5;
+            }
+            if self.strobed_output(A) {
+                bits |= ((!read || a.inte_out) as u8) << 6 | (!a.obf as u8) << 7;
+            }
+        }
+        if self.mode(B) == 1 {
+            let pc1 = if self.is_input(B) { b.ibf } else { !b.obf };
+            bits |= self.intr(B) as u8 | (pc1 as u8) << 1 | ((!read || b.inte_in) as u8) << 2;
+        }
+        bits
+    }
+
+    // Port C as a read sees it, or as its pins are when `read` is false
+    fn port_c_value(&self, read: bool) -> u8 {
+        let mut input = 0;
+        if self.control & C_UPPER_INPUT != 0 {
+            input |= 0xF0;
+        }
+        if self.control & C_LOWER_INPUT != 0 {
+            input |= 0x0F;
+        }
+        let mask = self.control_mask();
+        let io = (self.pins[C] & input) | (self.out[C] & !input);
+        (io & !mask) | (self.control_bits(read) & mask)
+    }
+
+    // Port A or B as a read sees it
+    fn port(&self, port: usize) -> u8 {
+        if self.strobed_input(port) {
+            self.handshake[port].latch
+        } else if self.is_input(port) {
+            self.pins[port]
+        } else {
+            self.out[port]
+        }
+    }
+
+    // Level on a port's pins: the output latch when the chip drives them,
+    // which in mode 2 is what a peripheral takes on ACK
+    fn level(&self, port: usize) -> u8 {
+        if self.mode(port) == 2 || !self.is_input(port) { self.out[port] } else { self.pins[port] }
+    }
+
+    pub fn port_a(&self) -> u8 {
+        self.level(A)
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.level(B)
+    }
+
+    // Levels on PC0-PC7, with the handshake outputs where port C carries them
+    pub fn port_c(&self) -> u8 {
+        self.port_c_value(false)
+    }
+
+    pub fn intr_a(&self) -> bool {
+        self.intr(A)
+    }
+
+    pub fn intr_b(&self) -> bool {
+        self.intr(B)
+    }
+
+    // Drives the pins of a port from outside, seen where it is an input
+    pub fn set_port_a(&mut self, value: u8) {
+        self.pins[A] = value;
+    }
+
+    pub fn set_port_b(&mut self, value: u8) {
+        self.pins[B] = value;
+    }
+
+    pub fn set_port_c(&mut self, value: u8) {
+        self.pins[C] = value;
+    }
+
+    // Pulses STB of port A, which latches the pins of a strobed input
+    pub fn strobe_a(&mut self) {
+        self.strobe(A);
+    }
+
+    pub fn strobe_b(&mut self) {
+        self.strobe(B);
+    }
+
+    // Pulses ACK of port A, the peripheral taking a strobed output
+    pub fn ack_a(&mut self) {
+        self.ack(A);
+    }
+
+    pub fn ack_b(&mut self) {
+        self.ack(B);
+    }
+
+    fn strobe(&mut self, port: usize) {
+        if self.strobed_input(port) {
+            self.handshake[port].latch = self.pins[port];
+            self.handshake[port].ibf = true;
+        }
+    }
+
+    fn ack(&mut self, port: usize) {
+        if self.strobed_output(port) {
+            self.handshake[port].obf = false;
+        }
+    }
+
+    // Bit set/reset: sets or clears a bit of port C, or the INTE flag whose
+    // pin it is in modes 1 and 2
+    fn bit_set_reset(&mut self, value: u8) {
+        let bit = (value >> 1) & 0x07;
+        let set = value & 0x01 != 0;
+        if set {
+            self.out[C] |= 1 << bit;
+        } else {
+            self.out[C] &= !(1 << bit);
+        }
+        match bit {
+            4 if self.strobed_input(A) => self.handshake[A].inte_in = set,
+            6 if self.strobed_output(A) => self.handshake[A].inte_out = set,
+            2 if self.mode(B) == 1 => {
+                self.handshake[B].inte_in = set;
+                self.handshake[B].inte_out = set;
+            }
+            _ => {}
+        }
+    }
+
+    fn peek(&self, reg: u16) -> u8 {
+        match reg {
+            PORT_A => self.port(A),
+            PORT_B => self.port(B),
+            PORT_C => self.port_c_value(true),
+            // The control word can't be read back
+            _ => 0xFF,
+        }
+    }
+
+    fn read(&mut self, reg: u16) -> u8 {
+        let value = self.peek(reg);
+        if let PORT_A | PORT_B = reg {
+            let port = reg as usize;
+            if self.strobed_input(port) {
+                self.handshake[port].ibf = false;
+            }
+        }
+        value
+    }
+
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            PORT_A | PORT_B => {
+                let port = reg as usize;
+                self.out[port] = value;
+                if self.strobed_output(port) {
+                    self.handshake[port].obf = true;
+                }
+            }
+            PORT_C => self.out[C] = value,
+            // A mode set clears the output latches and the handshake
+            CONTROL if value & MODE_SET != 0 => {
+                self.control = value;
+                self.out = [0; 3];
+                self.handshake = [Handshake::default(); 2];
+            }
+            CONTROL => self.bit_set_reset(value),
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> Interrupts {
+        let mut lines = Interrupts::default();
+        for port in [A, B] {
+            if let Some(line) = self.port_irq[port].filter(|_| self.intr(port)) {
+                lines = lines.or(line.into());
+            }
+        }
+        lines
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        w.u8(self.control);
+        for byte in self.out.iter().chain(&self.pins) {
+            w.u8(*byte);
+        }
+        for hs in &self.handshake {
+            w.u8(hs.latch);
+            for flag in [hs.ibf, hs.obf, hs.inte_in, hs.inte_out] {
+                w.bool(flag);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.control = r.u8()?;
+        for byte in self.out.iter_mut().chain(&mut self.pins) {
+            *byte = r.u8()?;
+        }
+        for hs in &mut self.handshake {
+            hs.latch = r.u8()?;
+            for flag in [&mut hs.ibf, &mut hs.obf, &mut hs.inte_in, &mut hs.inte_out] {
+                *flag = r.bool()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The chip in IO space
+#[derive(Debug)]
+struct Ports(Rc<RefCell<I8255>>);
+
+impl IoDevice for Ports {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.borrow_mut().read(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.0.borrow().peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.0.borrow_mut().write(addr, value);
+    }
+
+    fn irq(&self) -> Interrupts {
+        self.0.borrow().irq()
+    }
+
+    fn is_idle(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        self.0.borrow().save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.0.borrow_mut().load_state(r)
+    }
+}
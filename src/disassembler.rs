@@ -0,0 +1,35 @@
+use crate::opcodes;
+
+/// Disassembles the instruction at the start of `bytes`. Returns its text in
+/// assembler syntax and its length. Missing operand bytes read as zero.
+pub fn disassemble_one(bytes: &[u8]) -> (String, u8) {
+    let opcode = bytes.first().copied().unwrap_or(0);
+    match opcodes::get(opcode) {
+        Some(info) => {
+            let operands = info
+                .operands
+                .iter()
+                .map(|operand| opcodes::operand_text(opcode, *operand, bytes))
+                .collect::<Vec<_>>()
+                .join(",");
+            if operands.is_empty() {
+                (info.mnemonic.to_string(), info.length)
+            } else {
+                (format!("{} {}", info.mnemonic, operands), info.length)
+            }
+        }
+        None => (format!("DB {opcode:02X}h"), 1),
+    }
+}
+
+/// Disassembles a whole block loaded at `origin`
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<(u16, String)> {
+    let mut listing = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let (text, length) = disassemble_one(&bytes[i..]);
+        listing.push((origin.wrapping_add(i as u16), text));
+        i += length as usize;
+    }
+    listing
+}
@@ -1,17 +1,27 @@
 use bobs8085::{
-    changes::Changes,
+    DEFAULT_MAX_STEPS,
+    EndCondition,
     Simulator,
+    StepOutcome,
     assemble,
+    assembler::assemble_source,
+    cpu::CpuModel,
+    history::Watch,
+    snapshot::Snapshot,
+    stack::StackGuard,
+    throttle::is_valid_speed,
 };
 
 use std::{
     fs::File,
     io::Write,
+    time::Duration,
 };
 
 use iced::{
     Element, Fill, Alignment, Length,
     Border, Color, Theme, Font, window, Settings,
+    Subscription, time,
 };
 
 #[allow(unused_imports, dead_code)]
@@ -19,7 +29,7 @@ use iced::widget::{
     Scrollable, Row, Column, Container,
     row, column, text, button,
     text_editor, scrollable, container,
-    horizontal_space,
+    horizontal_space, text_input,
 };
 
 
@@ -34,6 +44,20 @@ enum Message {
     ForwardStep,
     BackwardStep,
     StopStep,
+    SeekInput(String),
+    Seek,
+    ConditionInput(String),
+    ReverseContinue,
+    ToggleModel,
+    SpeedInput(String),
+    SetSpeed,
+    EndInput(String),
+    SetEnd,
+    Tick,
+    StopRun,
+    SnapshotInput(String),
+    SaveSnapshot,
+    LoadSnapshot,
 }
 
 #[derive(Debug)]
@@ -44,31 +68,38 @@ struct State {
     memory_page_number: u8,
     current_memory_page: u8,
     step: bool,
-    changes: Vec<Changes>,
+    seek_input: String,
+    condition_input: String, // "TARGET=VALUE", e.g. "a=05" or "c020=ff"
+    history_status: String,  // Error of the last step back, seek or reverse continue
+    snapshot_input: String,
+    snapshot_status: String,  // Result of the last save or load
+    speed_input: String,      // Multiple of the clock rate, empty for full speed
+    end_input: String,        // "halt", "addr=XXXX" or "cycles=N"
+    end_status: String,       // Error of the last end condition given
+    running: bool,            // A real-time run is in progress
 }
 
 impl Default for State {
     fn default() -> Self {
-        let mut state = State { 
-            sim: Simulator::default(),
+        let mut sim = Simulator::default();
+        sim.set_max_steps(Some(DEFAULT_MAX_STEPS));
+        State { 
+            sim,
             editor_content: text_editor::Content::default(),
             memory_page_number: 16,
             current_memory_page: 0,
             step: false,
-            changes: vec![Changes::default(); 1],
-        };
-        state.changes[0].cpu.pc = 0xC000;
-        state
-    }
-}
-
-impl State {
-    
-    fn reset_changes(&mut self) {
-        self.changes = vec![Changes::default(); 1];
-        self.changes[0].cpu.pc = 0xC000;
+            seek_input: String::new(),
+            condition_input: String::new(),
+            history_status: String::new(),
+            snapshot_input: String::from("machine.snap"),
+            snapshot_status: String::new(),
+            speed_input: String::new(),
+            end_input: String::new(),
+            end_status: String::new(),
+            running: false,
+        }
     }
-
 }
 
 
@@ -156,7 +187,7 @@ fn get_io_box (state: &State) -> Column<'_, Message> {
     let mut i : u16 = 0;
     while i < 0xFF {
         let mut io_row = row![text(format!("{:04X}: ", i))];
-        while ((i+1) % 16) != 0 {
+        while !(i + 1).is_multiple_of(16) {
             io_row = io_row.push( text_center!(format!("{:02X}", state.sim.io_get8(i as u8))).size(14) );
             i += 1;
         }
@@ -172,10 +203,10 @@ fn get_memory_pages (state: &State) -> Vec<Column<'_, Message>> {
     let mut mem_pages : Vec<Column<'_, Message>> = vec![];
     let mut mem_box = column![memory_header()];
 
-    let mut i = 0xC000;
+    let mut i: u16 = 0xC000;
     while i < 0xCFFF {
         let mut mem_row = row![text(format!("{:04X}: ", i))];
-        while ((i+1) % 16) != 0 {
+        while !(i + 1).is_multiple_of(16) {
             let mut text = text(format!("{:02X}", state.sim.mem_get8(i)) )
                 .width(Fill)
                 .size(14);
@@ -190,7 +221,7 @@ fn get_memory_pages (state: &State) -> Vec<Column<'_, Message>> {
         }
         mem_box = mem_box.push(mem_row.spacing(5));
 
-        if ((i+1) % 256) == 0 {
+        if (i + 1).is_multiple_of(256) {
             mem_pages.push(mem_box);
             mem_box = column![memory_header()];
         }
@@ -230,6 +261,7 @@ fn register_box (state: &State) -> Container<'_, Message> {
         reg_row(row![text("Register L: "), text(format!("{:02X}", state.sim.cpu_get_reg(5)))]),
         reg_row(row![text("Memory: "), text(format!("{:02X}", state.sim.cpu_get_reg(6)))]),
         row![text_center!(format!("pc: 0x{:04X}", state.sim.get_pc())), text_center!(format!("sp: 0x{:04X}", state.sim.get_sp()))],
+        row![text_center!(format!("next: {}", state.sim.disassemble(state.sim.get_pc()).0))],
     ].spacing(5);
 
     add_border!(reg_box).padding([10, 0])
@@ -293,13 +325,43 @@ fn update (state: &mut State, message: Message) {
 
     match message {
         Message::RunAll => {
-            state.reset_changes();
             state.sim.clear_cpu();
             state.sim.set_pc(0xC000);
-            while state.sim.execute() {}
+            // A throttled run goes on in ticks so the window stays responsive
+            if state.sim.throttle().is_some() {
+                state.running = true;
+            } else {
+                while state.sim.execute() {}
+            }
+        },
+        Message::Tick => {
+            if state.running {
+                state.running = state.sim.run_due();
+            }
+        },
+        Message::StopRun => state.running = false,
+        Message::SpeedInput(input) => state.speed_input = input,
+        Message::SetSpeed => {
+            match state.speed_input.trim() {
+                "" | "off" => state.sim.set_speed(None),
+                speed => if let Ok(speed) = speed.parse::<f64>()
+                    && is_valid_speed(speed)
+                {
+                    state.sim.set_speed(Some(speed));
+                },
+            }
+        },
+        Message::EndInput(input) => state.end_input = input,
+        Message::SetEnd => {
+            match state.end_input.parse::<EndCondition>() {
+                Ok(condition) => {
+                    state.sim.set_end_condition(condition);
+                    state.end_status.clear();
+                }
+                Err(err) => state.end_status = err,
+            }
         },
         Message::RunStep => {
-            state.reset_changes();
             state.sim.clear_cpu();
             state.sim.set_pc(0xC000);
             state.step = true;
@@ -308,24 +370,54 @@ fn update (state: &mut State, message: Message) {
             state.step = false;
         }
         Message::ForwardStep => {
-            let (cpu_old, mem_old, io_old) = state.sim.clone_cpu_bus();
-            let status = state.sim.execute();
-            if !status {
+            if state.sim.step() == StepOutcome::Finished {
                 state.step = false;
             }
-            else {
-                let diff = state.sim.get_changes(cpu_old, mem_old, io_old);
-                state.changes.push(diff);
-            }
         },
         Message::BackwardStep => {
-            if state.changes.len() > 1 {
-                match &state.changes.pop() {
-                    Some(changes) => state.sim.restore(changes),
-                    _ => (),
-                }
+            state.history_status = state.sim.reverse_step().err().map(|err| err.to_string()).unwrap_or_default();
+        },
+        Message::SeekInput(input) => state.seek_input = input,
+        Message::Seek => {
+            if let Ok(step) = state.seek_input.trim().parse() {
+                state.history_status = state.sim.seek(step).err().map(|err| err.to_string()).unwrap_or_default();
             }
         },
+        Message::ConditionInput(input) => state.condition_input = input,
+        Message::ReverseContinue => {
+            if let Some((target, value)) = state.condition_input.split_once('=')
+                && let Ok(watch) = target.trim().parse::<Watch>()
+                && let Ok(value) = u16::from_str_radix(value.trim().trim_end_matches('h'), 16)
+            {
+                state.history_status = state.sim.reverse_continue(|sim| watch.read(sim) == value)
+                    .err().map(|err| err.to_string()).unwrap_or_default();
+            }
+        },
+        Message::ToggleModel => {
+            let model = match state.sim.get_model() {
+                CpuModel::I8085 => CpuModel::I8080,
+                CpuModel::I8080 => CpuModel::I8085,
+            };
+            state.sim.set_model(model);
+        },
+        Message::SnapshotInput(input) => state.snapshot_input = input,
+        Message::SaveSnapshot => {
+            state.snapshot_status = match state.sim.snapshot().save(&state.snapshot_input) {
+                Ok(()) => format!("Saved to \"{}\"", state.snapshot_input),
+                Err(err) => err.to_string(),
+            };
+        },
+        Message::LoadSnapshot => {
+            let restored = Snapshot::load(&state.snapshot_input).and_then(|snapshot| state.sim.restore_snapshot(&snapshot));
+            state.snapshot_status = match restored {
+                Ok(()) => {
+                    // Resume where the snapshot was taken
+                    state.step = true;
+                    format!("Loaded \"{}\"", state.snapshot_input)
+                }
+                Err(err) => err.to_string(),
+            };
+        },
         Message::MemoryPage(page) => state.current_memory_page = page,
         Message::Edit(action) => state.editor_content.perform(action),
         Message::Assemble => {
@@ -334,12 +426,46 @@ fn update (state: &mut State, message: Message) {
             let text = state.editor_content.text();
             let _ = write![file, "{}", text];
             let _ = assemble("program.asm", "out");
+            state.running = false;
+            let model = state.sim.get_model();
+            let throttle = state.sim.throttle().cloned();
+            let end = state.sim.get_end_condition();
             state.sim = Simulator::bus_from_file("bin/out.bin");
-            state.reset_changes();
+            state.sim.set_model(model);
+            state.sim.set_end_condition(end);
+            state.sim.set_max_steps(Some(DEFAULT_MAX_STEPS));
+            state.sim.set_throttle(throttle);
+            state.sim.set_program(assemble_source("program.asm").ok());
+            state.sim.set_stack_guard(Some(StackGuard::new()));
         },
     }
 }
 
+fn stack_box (state: &State) -> Container<'_, Message> {
+    let mut frames = column![title!("Call Stack")].spacing(5);
+
+    for frame in state.sim.get_call_stack().iter().rev() {
+        let entry = match frame.interrupt {
+            Some(source) => format!("{} ({})", state.sim.symbolize(frame.target), source),
+            None => state.sim.symbolize(frame.target),
+        };
+        frames = frames.push(text_center!(format!("{entry} <- {:04X}", frame.caller)));
+    }
+
+    if let Some(warning) = state.sim.get_stack_warning() {
+        frames = frames.push(
+            text_center!(warning.to_string()).color(Color::from_rgb(255.0, 0.0, 0.0))
+        );
+    }
+    if let Some(fault) = state.sim.get_stack_fault() {
+        frames = frames.push(
+            text_center!(fault.to_string()).color(Color::from_rgb(255.0, 0.0, 0.0))
+        );
+    }
+
+    add_border!(frames, [10, 0])
+}
+
 fn view (state: &State) -> Element<'_, Message> {
 
 //      let inst_binary = column![text("binary placeholder")].height(Fill);
@@ -355,10 +481,19 @@ fn view (state: &State) -> Element<'_, Message> {
 
     // Section 2
     let control_buttons;
-    if state.step == false {
+    if state.running {
+        control_buttons = row![
+            button("Stop").on_press(Message::StopRun),
+        ];
+    }
+    else if !state.step {
         control_buttons = row![
             button("Run All").on_press(Message::RunAll),
-            button("Run Step").on_press(Message::RunStep)
+            button("Run Step").on_press(Message::RunStep),
+            button(match state.sim.get_model() {
+                CpuModel::I8085 => "Model: 8085",
+                CpuModel::I8080 => "Model: 8080",
+            }).on_press(Message::ToggleModel),
         ];
     }
     else {
@@ -369,11 +504,78 @@ fn view (state: &State) -> Element<'_, Message> {
         ];
     }
 
+    let time_travel = if state.step {
+        column![
+            text_center!(format!("step: {}", state.sim.get_step_count())),
+            row![
+                text_input("step", &state.seek_input)
+                    .on_input(Message::SeekInput)
+                    .on_submit(Message::Seek),
+                button("Seek").on_press(Message::Seek),
+            ].spacing(10),
+            row![
+                text_input("a=05", &state.condition_input)
+                    .on_input(Message::ConditionInput)
+                    .on_submit(Message::ReverseContinue),
+                button("Reverse Continue").on_press(Message::ReverseContinue),
+            ].spacing(10),
+            text_center!(&state.history_status),
+        ].spacing(10)
+    } else {
+        column![]
+    };
+
+    let speed = column![
+        row![
+            text_input("speed (1 = real time)", &state.speed_input)
+                .on_input(Message::SpeedInput)
+                .on_submit(Message::SetSpeed),
+            button("Set Speed").on_press(Message::SetSpeed),
+        ].spacing(10),
+        text_center!(match (state.sim.throttle(), state.sim.get_drift()) {
+            (Some(throttle), Some(drift)) => format!("{:.3} MHz, drift {:.1} ms", throttle.get_rate() / 1e6, drift * 1e3),
+            _ => String::from("Full speed"),
+        }),
+    ].spacing(5);
+
+    let end = column![
+        row![
+            text_input("halt, addr=XXXX or cycles=N", &state.end_input)
+                .on_input(Message::EndInput)
+                .on_submit(Message::SetEnd),
+            button("Set End").on_press(Message::SetEnd),
+        ].spacing(10),
+        text_center!(if !state.end_status.is_empty() {
+            state.end_status.clone()
+        } else if let Some(fault) = state.sim.get_undefined_opcode() {
+            format!("Stopped: {fault}")
+        } else if state.sim.is_out_of_steps() {
+            format!("Stopped after {} steps without meeting {}", state.sim.get_step_count(), state.sim.get_end_condition())
+        } else {
+            format!("End: {}", state.sim.get_end_condition())
+        }),
+    ].spacing(5);
+
+    let snapshot = column![
+        row![
+            text_input("snapshot file", &state.snapshot_input)
+                .on_input(Message::SnapshotInput),
+            button("Save").on_press(Message::SaveSnapshot),
+            button("Load").on_press(Message::LoadSnapshot),
+        ].spacing(10),
+        text_center!(&state.snapshot_status),
+    ].spacing(5);
+
     let section_2 = column![
         register_box(state),
         flags_box(state),
         interrupts_box(state),
+        stack_box(state),
         control_buttons.spacing(10),
+        time_travel,
+        speed,
+        end,
+        snapshot,
     ].spacing(10);
 
 
@@ -430,17 +632,30 @@ fn view (state: &State) -> Element<'_, Message> {
     interface.into()
 }
 
+fn subscription (state: &State) -> Subscription<Message> {
+    if state.running {
+        time::every(Duration::from_millis(16)).map(|_| Message::Tick)
+    } else {
+        Subscription::none()
+    }
+}
+
 fn main () -> iced::Result {
 
-    let mut window_settings = window::Settings::default();
-    window_settings.size = iced::Size { width: 1200.0, height: 780.0 };
-    window_settings.min_size = Some(iced::Size { width: 1000.0, height: 660.0 });
+    let window_settings = window::Settings {
+        size: iced::Size { width: 1200.0, height: 780.0 },
+        min_size: Some(iced::Size { width: 1000.0, height: 660.0 }),
+        ..Default::default()
+    };
 
-    let mut app_settings = Settings::default();
-    app_settings.default_font = Font { family: iced::font::Family::Monospace, ..Font::default() };
-    app_settings.default_text_size = iced::Pixels(14.0);
+    let app_settings = Settings {
+        default_font: Font { family: iced::font::Family::Monospace, ..Font::default() },
+        default_text_size: iced::Pixels(14.0),
+        ..Default::default()
+    };
 
     iced::application("bobs8085-gui", update, view)
+        .subscription(subscription)
         .theme(|_| Theme::Oxocarbon)
         .centered()
         .settings(app_settings)
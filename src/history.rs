@@ -0,0 +1,232 @@
+use std::str::FromStr;
+
+use crate::{
+    Simulator,
+    bus::{io::Io, mem::Memory},
+    cpu::CPU,
+    serial::{SoftUart, UartLine},
+    snapshot::SnapshotError,
+};
+
+// Steps between full-state checkpoints unless configured otherwise
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 10_000;
+
+// Checkpoints kept, the oldest are dropped past it
+pub const MAX_CHECKPOINTS: usize = 100;
+
+// Full machine state at the start of a step
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub cpu: CPU,
+    pub memory: Memory,
+    pub io: Io,
+    pub devices: Vec<u8>,
+    pub serial: Option<UartLine>,
+}
+
+/// A value that can be watched while travelling through the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// Register by its `get_reg` index (B, C, D, E, H, L, M, A)
+    Reg(u8),
+    Pc,
+    Sp,
+    /// Memory byte at the given address
+    Mem(u16),
+}
+
+impl Watch {
+    pub fn read(&self, sim: &Simulator) -> u16 {
+        match self {
+            Watch::Reg(target) => sim.cpu_get_reg(*target) as u16,
+            Watch::Pc => sim.get_pc(),
+            Watch::Sp => sim.get_sp(),
+            Watch::Mem(addr) => sim.mem_get8(*addr) as u16,
+        }
+    }
+}
+
+// Register names as in assembler syntax, anything else is read as a memory address
+impl FromStr for Watch {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target = match s.to_lowercase().as_str() {
+            "b" => Watch::Reg(0),
+            "c" => Watch::Reg(1),
+            "d" => Watch::Reg(2),
+            "e" => Watch::Reg(3),
+            "h" => Watch::Reg(4),
+            "l" => Watch::Reg(5),
+            "m" => Watch::Reg(6),
+            "a" => Watch::Reg(7),
+            "pc" => Watch::Pc,
+            "sp" => Watch::Sp,
+            addr => {
+                let hex = addr
+                    .strip_prefix("0x")
+                    .or_else(|| addr.strip_suffix('h'))
+                    .unwrap_or(addr);
+                Watch::Mem(u16::from_str_radix(hex, 16)?)
+            }
+        };
+        Ok(target)
+    }
+}
+
+impl Simulator {
+    pub fn get_checkpoint_interval(&self) -> u64 {
+        self.checkpoint_interval
+    }
+
+    // 0 disables checkpoints, so seeking is limited to the step-back history
+    pub fn set_checkpoint_interval(&mut self, interval: u64) {
+        self.checkpoint_interval = interval;
+    }
+
+    // Called before each forward step. Checkpoints past the current step
+    // belong to a timeline that is being rewritten, so they are dropped. The
+    // cached mode without a history is a plain run, which records none.
+    pub(crate) fn record_checkpoint(&mut self) {
+        if self.checkpoints.last_key_value().is_some_and(|(step, _)| *step > self.step_count) {
+            self.checkpoints.split_off(&(self.step_count + 1));
+        }
+        if self.checkpoint_interval > 0
+            && (self.history_depth > 0 || self.blocks.is_none())
+            && self.step_count.is_multiple_of(self.checkpoint_interval)
+            && !self.checkpoints.contains_key(&self.step_count)
+        {
+            let checkpoint = Checkpoint {
+                cpu: self.cpu.clone(),
+                memory: self.bus.mem_clone(),
+                io: self.bus.io_clone(),
+                devices: self.bus.devices_state(),
+                serial: self.serial.as_ref().map(SoftUart::get_line),
+            };
+            self.checkpoints.insert(self.step_count, checkpoint);
+            while self.checkpoints.len() > MAX_CHECKPOINTS {
+                self.checkpoints.pop_first();
+            }
+        }
+    }
+
+    // Runs `replay` with the tracer, the profiler and the coverage put
+    // aside, so steps that are run again are not recorded twice
+    fn replaying<T>(&mut self, replay: impl FnOnce(&mut Simulator) -> T) -> T {
+        let tracer = self.tracer.take();
+        let profiler = self.profiler.take();
+        let coverage = self.coverage.take();
+        let result = replay(self);
+        self.tracer = tracer;
+        self.profiler = profiler;
+        self.coverage = coverage;
+        result
+    }
+
+    // Goes back to the closest checkpoint before `step`, keeping the part of
+    // the step-back history that is older than it. Returns false if there is none.
+    fn restore_checkpoint_before(&mut self, step: u64) -> Result<bool, SnapshotError> {
+        let Some((&at, checkpoint)) = self.checkpoints.range(..step).next_back() else {
+            return Ok(false);
+        };
+        self.cpu = checkpoint.cpu.clone();
+        self.bus.mem_restore(checkpoint.memory.clone());
+        self.bus.io_restore(checkpoint.io.clone());
+        if !checkpoint.devices.is_empty() {
+            self.bus.devices_restore(&checkpoint.devices)?;
+        }
+        if let (Some(uart), Some(line)) = (&mut self.serial, checkpoint.serial) {
+            uart.restore_line(line);
+        }
+
+        let discarded = (self.step_count - at) as usize;
+        let kept = self.history.len().saturating_sub(discarded);
+        self.history.truncate(kept);
+        self.step_count = at;
+        Ok(true)
+    }
+
+    /// Moves to the given step, going back through the history or the
+    /// checkpoints, or running forward. Returns the step reached, which is
+    /// earlier than requested if the program finishes or stops on a fault first.
+    /// Fails when a device rejects a state saved in the history.
+    pub fn seek(&mut self, step: u64) -> Result<u64, SnapshotError> {
+        if step < self.step_count {
+            let back = (self.step_count - step) as usize;
+            if back > self.history.len() {
+                self.restore_checkpoint_before(step + 1)?;
+            }
+            while self.step_count > step && self.step_back()? {}
+        }
+        self.replaying(|sim| while sim.step_count < step && sim.execute() {});
+        Ok(self.step_count)
+    }
+
+    /// Undoes one step, replaying from a checkpoint when the step-back history
+    /// is exhausted. Returns false at the start of the program.
+    pub fn reverse_step(&mut self) -> Result<bool, SnapshotError> {
+        if self.step_count == 0 {
+            return Ok(false);
+        }
+        let target = self.step_count - 1;
+        Ok(self.seek(target)? == target)
+    }
+
+    /// Runs backwards until `condition` holds, stopping at the latest earlier
+    /// step where it does. Returns that step, or None after reaching the
+    /// earliest reachable step without a match.
+    pub fn reverse_continue(&mut self, mut condition: impl FnMut(&Simulator) -> bool) -> Result<Option<u64>, SnapshotError> {
+        loop {
+            while self.step_back()? {
+                if condition(self) {
+                    return Ok(Some(self.step_count));
+                }
+            }
+
+            // History exhausted: replay the stretch since the previous checkpoint
+            let end = self.step_count;
+            if !self.restore_checkpoint_before(end)? {
+                return Ok(None);
+            }
+            let start = self.step_count;
+            let mut found = None;
+            self.replaying(|sim| {
+                while sim.step_count < end {
+                    if condition(sim) {
+                        found = Some(sim.step_count);
+                    }
+                    if !sim.execute() {
+                        break;
+                    }
+                }
+            });
+            match found {
+                Some(step) => return self.seek(step).map(Some),
+                None => {
+                    self.seek(start)?;
+                }
+            }
+        }
+    }
+
+    /// Value of `watch` at step `from` and at every later step up to `to`
+    /// where it changed. The current step is restored afterwards.
+    pub fn value_history(&mut self, watch: Watch, from: u64, to: u64) -> Result<Vec<(u64, u16)>, SnapshotError> {
+        let origin = self.step_count;
+        let mut values: Vec<(u64, u16)> = vec![];
+
+        self.seek(from)?;
+        self.replaying(|sim| loop {
+            let value = watch.read(sim);
+            if values.last().is_none_or(|(_, last)| *last != value) {
+                values.push((sim.step_count, value));
+            }
+            if sim.step_count >= to || sim.is_finished() || !sim.execute() {
+                break;
+            }
+        });
+
+        self.seek(origin)?;
+        Ok(values)
+    }
+}
@@ -1,24 +1,143 @@
 pub mod assembler;
+pub mod bench;
 pub mod bus;
 pub mod changes;
+pub mod coverage;
 pub mod cpu;
+pub mod devices;
+pub mod disassembler;
+pub mod history;
+pub mod opcodes;
+pub mod profile;
+pub mod serial;
+pub mod snapshot;
+pub mod stack;
+pub mod throttle;
+pub mod trace;
+pub mod vcd;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
 
 use crate::{
-    assembler::assemble_program,
+    assembler::{Program, assemble_program},
     bus::{
         Bus,
-        mem::Memory,
-        io::Io,
+        Journal,
+        code::{CodeHook, CodeWatch, CodeWrite},
+        cycles::BusCycle,
+        intc::InterruptController,
+        device::IoDevice,
+        map::{MemFault, MemoryMap},
     },
     cpu::CPU,
+    cpu::UndefinedOpcode,
+    cpu::CpuModel,
+    cpu::{BlockCache, BlockLimits, ExecMode},
+    cpu::{Frame, StackWarning},
     cpu::Interrupts,
     changes::Changes,
+    coverage::Coverage,
+    history::{Checkpoint, DEFAULT_CHECKPOINT_INTERVAL},
+    profile::{Profiler, StepRecord},
+    serial::SoftUart,
+    snapshot::{Snapshot, SnapshotError},
+    stack::{StackAccess, StackFault, StackGuard},
+    throttle::Throttle,
+    trace::{TraceEntry, Tracer},
 };
 
+// Steps the frontends let a run take before stopping it, so a program that
+// never meets its end condition can't spin forever
+pub const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+// Steps kept for step-back unless configured otherwise
+pub const DEFAULT_HISTORY_DEPTH: usize = 100_000;
+
+/// Condition under which a running program is considered finished
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EndCondition {
+    /// HLT executed while interrupts are disabled, so nothing can wake the CPU
+    #[default]
+    Halt,
+    /// The program counter reached the given address
+    Address(u16),
+    /// At least the given number of T-states have elapsed
+    Cycles(u64),
+}
+
+// Written as halt, addr=XXXX (hex) or cycles=N
+impl FromStr for EndCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.split_once('=') {
+            None if s == "halt" => Ok(EndCondition::Halt),
+            Some(("addr", addr)) => u16::from_str_radix(addr.trim_start_matches("0x").trim_end_matches('h'), 16)
+                .map(EndCondition::Address)
+                .map_err(|_| format!("Not a valid address: {addr}")),
+            Some(("cycles", cycles)) => cycles.parse()
+                .map(EndCondition::Cycles)
+                .map_err(|_| format!("Not a valid number of cycles: {cycles}")),
+            _ => Err(format!("Unknown end condition: {s} (expected halt, addr=XXXX or cycles=N)")),
+        }
+    }
+}
+
+impl fmt::Display for EndCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndCondition::Halt => write!(f, "halt"),
+            EndCondition::Address(addr) => write!(f, "addr={addr:04X}"),
+            EndCondition::Cycles(cycles) => write!(f, "cycles={cycles}"),
+        }
+    }
+}
+
+/// Result of executing a single step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed
+    Running,
+    /// The CPU is halted, waiting for an interrupt
+    Halted,
+    /// The end condition was met
+    Finished,
+    /// The stack left its region, with a stack guard set to stop
+    StackFault(StackFault),
+    /// The instruction wrote to code, with the code watch set to stop
+    CodeWrite(CodeWrite),
+    /// The instruction made an access the memory map is set to stop on
+    MemFault(MemFault),
+    /// PC reached an opcode with no instruction, and stays on it
+    UndefinedOpcode(UndefinedOpcode),
+}
+
 #[derive(Debug)]
 pub struct Simulator {
     cpu: CPU,
     bus: Bus,
+    end_condition: EndCondition,
+    max_steps: Option<u64>,     // Steps after which a run is finished anyway, None for no limit
+    history: VecDeque<Changes>, // Undo records, oldest first
+    history_depth: usize,       // Maximum undo records kept, 0 disables the journal
+    step_count: u64,            // Steps executed since the CPU was cleared
+    checkpoints: BTreeMap<u64, Checkpoint>, // Full states by step, for seeking past the history
+    checkpoint_interval: u64,
+    serial: Option<SoftUart>, // UART on SID/SOD
+    serial_steps: u64,        // Steps whose serial output was delivered, run again without it
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    program: Option<Program>, // Source information of the loaded program, for labels
+    stack_guard: Option<StackGuard>,
+    stack_fault: Option<StackFault>, // Found by the last step
+    code_hook: Option<CodeHook>,
+    code_writes: Vec<CodeWrite>,     // Made by the last step
+    mem_fault: Option<MemFault>,     // Made by the last step
+    throttle: Option<Throttle>,      // Real-time pacing, off when None
+    blocks: Option<BlockCache>,      // Decoded code, in the cached mode
 }
 
 impl Default for Simulator {
@@ -29,7 +148,34 @@ impl Default for Simulator {
 
 impl Simulator {
     pub fn new() -> Simulator {
-        Simulator { cpu: CPU::default(), bus: Bus::default(), }
+        Simulator::with_bus(Bus::default())
+    }
+
+    fn with_bus(bus: Bus) -> Simulator {
+        Simulator {
+            cpu: CPU::default(),
+            bus,
+            end_condition: EndCondition::default(),
+            max_steps: None,
+            history: VecDeque::new(),
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            step_count: 0,
+            checkpoints: BTreeMap::new(),
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            serial: None,
+            serial_steps: 0,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            program: None,
+            stack_guard: None,
+            stack_fault: None,
+            code_hook: None,
+            code_writes: Vec::new(),
+            mem_fault: None,
+            throttle: None,
+            blocks: None,
+        }
     }
 
     pub fn cpu_print_state(&self) {
@@ -37,11 +183,361 @@ impl Simulator {
     }
 
     pub fn bus_from_file(filename: &str) -> Simulator {
-        Simulator { cpu: CPU::default(), bus: Bus::from_file(filename), }
+        Simulator::with_bus(Bus::from_file(filename))
+    }
+
+    pub fn set_end_condition(&mut self, condition: EndCondition) {
+        self.end_condition = condition;
+    }
+
+    pub fn get_end_condition(&self) -> EndCondition {
+        self.end_condition
+    }
+
+    pub fn set_max_steps(&mut self, steps: Option<u64>) {
+        self.max_steps = steps;
+    }
+
+    pub fn get_max_steps(&self) -> Option<u64> {
+        self.max_steps
+    }
+
+    // Whether the run was stopped by the step limit rather than its end
+    // condition
+    pub fn is_out_of_steps(&self) -> bool {
+        self.max_steps.is_some_and(|max| self.step_count >= max)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        let ended = match self.end_condition {
+            EndCondition::Halt => self.cpu.is_halted() && !self.cpu.get_int(),
+            EndCondition::Address(addr) => self.cpu.get_pc() == addr,
+            EndCondition::Cycles(budget) => self.cpu.get_cycles() >= budget,
+        };
+        ended || self.is_out_of_steps()
+    }
+
+    pub fn step(&mut self) -> StepOutcome {
+        if self.is_finished() {
+            return StepOutcome::Finished;
+        }
+        self.record_checkpoint();
+
+        let serial = self.serial.as_ref().map(SoftUart::get_line);
+        if let Some(uart) = &mut self.serial {
+            let delivered = uart.get_output_len();
+            self.cpu.sid = uart.update(self.cpu.get_cycles(), self.cpu.sod);
+            if self.step_count < self.serial_steps {
+                uart.truncate_output(delivered);
+            } else {
+                self.serial_steps = self.step_count + 1;
+            }
+        }
+
+        let pc = self.cpu.get_pc();
+        let sp = self.cpu.get_sp();
+        let cycles = self.cpu.get_cycles();
+        let was_halted = self.cpu.is_halted();
+        // Read before the step, which may overwrite it
+        let opcode = self.bus.mem_peek8(pc);
+
+        let traced = self.tracer.as_ref().is_some_and(|tracer| tracer.wants(self.cpu.get_pc()));
+        if self.history_depth > 0 || traced {
+            let cpu = self.cpu.clone();
+            self.bus.begin_journal();
+            self.cpu.execute(&mut self.bus);
+            let journal = self.bus.end_journal();
+
+            if traced {
+                self.trace_step(&cpu, &journal);
+            }
+            if self.history_depth > 0 {
+                if self.history.len() >= self.history_depth {
+                    self.history.pop_front();
+                }
+                self.history.push_back(Changes { cpu, memory: journal.memory, io: journal.io, devices: journal.devices, serial });
+            }
+        } else {
+            self.cpu.execute(&mut self.bus);
+        }
+        self.step_count += 1;
+
+        if self.profiler.is_some() || self.coverage.is_some() {
+            let record = StepRecord {
+                pc,
+                opcode,
+                next_pc: self.cpu.get_pc(),
+                cycles: self.cpu.get_cycles() - cycles,
+                end_cycles: self.cpu.get_cycles(),
+                executed: !was_halted && !self.cpu.was_interrupted() && self.cpu.get_undefined_opcode().is_none(),
+                interrupted: self.cpu.was_interrupted(),
+            };
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(&record);
+            }
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(&record);
+            }
+        }
+
+        self.stack_fault = self.check_stack(pc, opcode, sp, was_halted);
+        self.mem_fault = self.bus.take_mem_faults(pc).first().copied();
+        if let Some(throttle) = &mut self.throttle {
+            throttle.pace(self.cpu.get_cycles());
+        }
+        self.code_writes = self.bus.take_code_writes(pc);
+        if let Some(CodeHook(hook)) = &mut self.code_hook {
+            self.code_writes.iter().for_each(hook);
+        }
+        self.outcome()
+    }
+
+    // Outcome of the step just run
+    fn outcome(&self) -> StepOutcome {
+        if self.is_finished() {
+            StepOutcome::Finished
+        } else if let Some(fault) = self.cpu.get_undefined_opcode() {
+            StepOutcome::UndefinedOpcode(fault)
+        } else if let Some(fault) = self.stack_fault.filter(|_| self.stack_guard.as_ref().is_some_and(StackGuard::is_fault)) {
+            StepOutcome::StackFault(fault)
+        } else if let Some(fault) = self.mem_fault {
+            StepOutcome::MemFault(fault)
+        } else if let Some(write) = self.code_writes.first().filter(|_| self.bus.stops_on_code_writes()) {
+            StepOutcome::CodeWrite(*write)
+        } else if self.cpu.is_halted() {
+            StepOutcome::Halted
+        } else {
+            StepOutcome::Running
+        }
+    }
+
+    // Runs the stack guard over the step just run from the given PC, opcode
+    // and SP
+    fn check_stack(&mut self, pc: u16, opcode: u8, sp: u16, was_halted: bool) -> Option<StackFault> {
+        let guard = self.stack_guard.as_mut()?;
+        let opcode = if self.cpu.was_interrupted() {
+            None
+        } else if was_halted {
+            return None;
+        } else {
+            Some(opcode)
+        };
+        if opcode.is_some_and(stack::loads_sp) {
+            guard.load(self.cpu.get_sp());
+            return None;
+        }
+        let access = StackAccess::of(opcode, sp, self.cpu.get_sp())?;
+        guard.check(access, pc, opcode, sp, self.program.as_ref())
+    }
+
+    // Writes the trace line of the step just run from the given CPU state
+    fn trace_step(&mut self, before: &CPU, journal: &Journal) {
+        // A halted CPU waiting for an interrupt executes nothing
+        if before.is_halted() && !self.cpu.was_interrupted() {
+            return;
+        }
+        let pc = before.get_pc();
+        let (bytes, mnemonic) = if self.cpu.was_interrupted() {
+            (Vec::new(), format!("INT {:04X}H", self.cpu.get_pc()))
+        } else {
+            // Memory as it was before the step, in case the instruction overwrote itself
+            let mut bytes = (0..3)
+                .map(|i| {
+                    let addr = pc.wrapping_add(i);
+                    journal.memory.iter().find(|(a, _)| *a == addr).map_or(self.bus.mem_peek8(addr), |(_, old)| *old)
+                })
+                .collect::<Vec<_>>();
+            let (text, length) = disassembler::disassemble_one(&bytes);
+            bytes.truncate(length as usize);
+            (bytes, text)
+        };
+        let entry = TraceEntry {
+            cycles: before.get_cycles(),
+            bytes,
+            mnemonic,
+            regs: before.regs(),
+            memory: journal.memory.iter().map(|(addr, _)| (*addr, self.bus.mem_peek8(*addr))).collect(),
+            io: journal.io.iter().map(|(port, _)| (*port, self.bus.io_peek8(*port))).collect(),
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(&entry);
+        }
+    }
+
+    // Starts tracing executed instructions, replacing any tracer already set
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    // Removes the tracer so it can be finished
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // Starts profiling executed instructions, replacing any profiler already set
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
     }
 
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    // Starts recording instruction and branch coverage
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage;
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    // Returns false once the end condition is met
     pub fn execute(&mut self) -> bool {
-        self.cpu.execute(&mut self.bus)
+        matches!(self.step(), StepOutcome::Running | StepOutcome::Halted)
+    }
+
+    pub fn set_exec_mode(&mut self, mode: ExecMode) {
+        if mode == self.get_exec_mode() {
+            return;
+        }
+        self.blocks = (mode == ExecMode::Cached).then(BlockCache::new);
+        self.bus.track_cached_code(mode == ExecMode::Cached);
+    }
+
+    pub fn get_exec_mode(&self) -> ExecMode {
+        if self.blocks.is_some() { ExecMode::Cached } else { ExecMode::Interpreter }
+    }
+
+    // Whether the cached mode can skip the per-step work: only the stack
+    // guard and the throttle are kept up with, everything else that looks at
+    // single steps makes it fall back to the interpreter
+    fn runs_blocks(&self) -> bool {
+        self.blocks.is_some()
+            && self.history_depth == 0
+            && self.serial.is_none()
+            && self.tracer.is_none()
+            && self.profiler.is_none()
+            && self.coverage.is_none()
+            && !self.bus.is_watching_code()
+            && !self.bus.is_recording_cycles()
+            && self.cpu.can_run_block(&self.bus)
+    }
+
+    /// Runs up to `max_steps` steps and returns the outcome of the last one.
+    /// Stops early when a step does not leave the program running, or leaves
+    /// a stack warning, a stack fault, a memory fault or a write to code to
+    /// report.
+    pub fn run(&mut self, max_steps: u64) -> StepOutcome {
+        let mut outcome = StepOutcome::Running;
+        let mut left = max_steps;
+        while left > 0 {
+            let steps;
+            (outcome, steps) = if self.runs_blocks() { self.step_block(left) } else { (self.step(), 1) };
+            left = left.saturating_sub(steps);
+            let reported = self.cpu.get_stack_warning().is_some()
+                || self.stack_fault.is_some()
+                || self.mem_fault.is_some()
+                || !self.code_writes.is_empty();
+            if outcome != StepOutcome::Running || reported {
+                break;
+            }
+        }
+        outcome
+    }
+
+    // Runs steps from the block cache. With a stack guard they stop after
+    // the first instruction that uses the stack, for the guard to look at.
+    fn step_block(&mut self, max_steps: u64) -> (StepOutcome, u64) {
+        if self.is_finished() {
+            return (StepOutcome::Finished, 0);
+        }
+
+        // Blocks only run without a history, which leaves nothing to seek
+        // through, so there are no checkpoints to stop at
+        let mut limit = max_steps;
+        if let Some(max) = self.max_steps {
+            limit = limit.min(max - self.step_count);
+        }
+        let (cycles, pc) = match self.end_condition {
+            EndCondition::Halt => (u64::MAX, None),
+            EndCondition::Address(addr) => (u64::MAX, Some(addr)),
+            EndCondition::Cycles(budget) => (budget, None),
+        };
+        let limits = BlockLimits { steps: limit, cycles, pc, stack: self.stack_guard.is_some() };
+        let cache = self.blocks.as_mut().expect("modo de execução sem cache de blocos");
+        let exit = self.cpu.run_blocks(&mut self.bus, cache, limits);
+        if exit.steps == 0 {
+            // Nothing decodable at PC, the interpreter reports it
+            return (self.step(), 1);
+        }
+        self.step_count += exit.steps;
+
+        self.stack_fault = self.check_stack(exit.pc, exit.opcode, exit.sp, false);
+        self.mem_fault = self.bus.take_mem_faults(exit.pc).first().copied();
+        if let Some(throttle) = &mut self.throttle {
+            throttle.pace(self.cpu.get_cycles());
+        }
+        self.code_writes.clear();
+        (self.outcome(), exit.steps)
+    }
+
+    // Runs the steps that are due by now at the throttle's rate, for callers
+    // driven by a timer. Without a throttle it runs a single step. Returns
+    // false once the program finishes or stops on a fault.
+    pub fn run_due(&mut self) -> bool {
+        let Some(throttle) = &mut self.throttle else { return self.execute() };
+        let due = throttle.due_cycles(self.cpu.get_cycles());
+        while self.cpu.get_cycles() < due {
+            if !self.execute() {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Undoes the last step, returns false when there is no history left.
+    // Fails when a device rejects the state it had before the step.
+    pub fn step_back(&mut self) -> Result<bool, SnapshotError> {
+        match self.history.pop_back() {
+            Some(changes) => {
+                self.cpu.restore(&mut self.bus, &changes)?;
+                if let (Some(uart), Some(line)) = (&mut self.serial, changes.serial) {
+                    uart.restore_line(line);
+                }
+                self.step_count -= 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn get_step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn get_history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    // Sets how many steps can be undone, dropping the oldest records if needed
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history_depth = depth;
+        while self.history.len() > depth {
+            self.history.pop_front();
+        }
     }
 
     pub fn print_state(&self) {
@@ -57,8 +553,87 @@ impl Simulator {
         }
     }
 
+    pub fn get_model(&self) -> CpuModel {
+        self.cpu.get_model()
+    }
+
+    pub fn set_model(&mut self, model: CpuModel) {
+        self.cpu.set_model(model);
+    }
+
+    // Full machine state, to be saved and restored later
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            memory: self.bus.mem_clone(),
+            io: self.bus.io_clone(),
+            devices: self.bus.devices_state(),
+            serial: self.serial.clone(),
+            program: self.program.clone(),
+        }
+    }
+
+    // Resumes from a snapshot. The step history starts over from it, and a
+    // serial UART already connected stays. Fails, leaving the machine as it
+    // was, when the snapshot has state for other devices than the ones on
+    // the bus.
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if !snapshot.devices.is_empty() || self.bus.has_devices() {
+            let current = self.bus.devices_state();
+            if let Err(err) = self.bus.devices_restore(&snapshot.devices) {
+                if !current.is_empty() {
+                    self.bus.devices_restore(&current)?;
+                }
+                return Err(err);
+            }
+        }
+        self.cpu = snapshot.cpu.clone();
+        self.bus.mem_restore(snapshot.memory.clone());
+        self.bus.io_restore(snapshot.io.clone());
+        // A UART the frontend connected stays, resuming the saved line
+        match &mut self.serial {
+            Some(uart) => uart.resume_line(snapshot.serial.as_ref().map(SoftUart::get_line).unwrap_or_default()),
+            None => self.serial = snapshot.serial.clone(),
+        }
+        self.set_program(snapshot.program.clone());
+        self.history.clear();
+        self.checkpoints.clear();
+        self.step_count = 0;
+        self.serial_steps = 0;
+        self.stack_fault = None;
+        self.mem_fault = None;
+        self.code_writes.clear();
+        self.reset_stack_guard();
+        self.restart_throttle();
+        Ok(())
+    }
+
+    // Starts the guard over. When resuming inside subroutines, the outermost
+    // call shows where the stack began.
+    fn reset_stack_guard(&mut self) {
+        let Some(guard) = &mut self.stack_guard else { return };
+        guard.reset();
+        if let Some(frame) = self.cpu.get_call_stack().first() {
+            guard.load(frame.sp.wrapping_add(2));
+        }
+    }
+
     pub fn clear_cpu (&mut self) {
-        self.cpu = CPU::default();
+        self.cpu = CPU::with_model(self.cpu.get_model());
+        self.history.clear();
+        self.checkpoints.clear();
+        self.step_count = 0;
+        self.serial_steps = 0;
+        self.stack_fault = None;
+        self.mem_fault = None;
+        self.reset_stack_guard();
+        self.restart_throttle();
+    }
+
+    fn restart_throttle(&mut self) {
+        if let Some(throttle) = &mut self.throttle {
+            throttle.restart(self.cpu.get_cycles());
+        }
     }
     
     pub fn get_pc(&self) -> u16 {
@@ -69,6 +644,19 @@ impl Simulator {
         self.cpu.get_sp()
     }
 
+    pub fn get_cycles(&self) -> u64 {
+        self.cpu.get_cycles()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    // Undefined opcode the last step stopped on
+    pub fn get_undefined_opcode(&self) -> Option<UndefinedOpcode> {
+        self.cpu.get_undefined_opcode()
+    }
+
     pub fn get_flag(&self, target: u8) -> bool {
         self.cpu.get_flag(target)
     }
@@ -89,8 +677,177 @@ impl Simulator {
         self.cpu.sod
     }
 
+    // Starts or stops recording the machine cycles of every step
+    pub fn record_bus_cycles(&mut self, enable: bool) {
+        self.bus.record_cycles(enable);
+    }
+
+    // Machine cycles run since the last call, while recording
+    pub fn take_bus_cycles(&mut self) -> Vec<BusCycle> {
+        self.bus.take_cycles()
+    }
+
+    pub fn set_serial(&mut self, uart: Option<SoftUart>) {
+        self.serial = uart;
+        self.serial_steps = 0;
+    }
+
+    pub fn serial(&mut self) -> Option<&mut SoftUart> {
+        self.serial.as_mut()
+    }
+
+    // Subroutines entered and not yet returned from, innermost last
+    pub fn get_call_stack(&self) -> &[Frame] {
+        self.cpu.get_call_stack()
+    }
+
+    // Mismatched return found by the last step, if any
+    pub fn get_stack_warning(&self) -> Option<StackWarning> {
+        self.cpu.get_stack_warning()
+    }
+
+    // Checks every step's stack accesses against the guard's region
+    pub fn set_stack_guard(&mut self, guard: Option<StackGuard>) {
+        self.stack_guard = guard;
+        self.stack_fault = None;
+        self.reset_stack_guard();
+    }
+
+    pub fn stack_guard(&self) -> Option<&StackGuard> {
+        self.stack_guard.as_ref()
+    }
+
+    // Stack fault found by the last step, whether it stopped the program or not
+    pub fn get_stack_fault(&self) -> Option<StackFault> {
+        self.stack_fault
+    }
+
+    // Decodes addresses through the map, or as flat RAM without one
+    pub fn set_memory_map(&mut self, map: Option<MemoryMap>) {
+        self.bus.set_memory_map(map);
+        self.mem_fault = None;
+    }
+
+    pub fn memory_map(&self) -> Option<&MemoryMap> {
+        self.bus.memory_map()
+    }
+
+    // Memory fault made by the last step
+    pub fn get_mem_fault(&self) -> Option<MemFault> {
+        self.mem_fault
+    }
+
+    // Runs in real time at the throttle's clock rate, or as fast as possible
+    // without one
+    pub fn set_throttle(&mut self, throttle: Option<Throttle>) {
+        self.throttle = throttle;
+        self.restart_throttle();
+    }
+
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    // Changes the speed while running, as a multiple of the clock rate.
+    // None runs unthrottled.
+    pub fn set_speed(&mut self, speed: Option<f64>) {
+        match speed {
+            Some(speed) => {
+                let cycles = self.cpu.get_cycles();
+                self.throttle.get_or_insert_default().set_speed(speed, cycles);
+            }
+            None => self.throttle = None,
+        }
+    }
+
+    // Seconds behind wall-clock time, negative when ahead
+    pub fn get_drift(&self) -> Option<f64> {
+        self.throttle.as_ref().map(|throttle| throttle.drift(self.cpu.get_cycles()))
+    }
+
+    // Writes an assembled program to memory at its origin, ROM included,
+    // starts the CPU there and keeps the program's symbols
+    pub fn load_program(&mut self, program: Program) {
+        self.bus.mem_load(program.origin, &program.bytes);
+        self.cpu.set_pc(program.origin);
+        self.set_program(Some(program));
+    }
+
+    pub fn get_program(&self) -> Option<&Program> {
+        self.program.as_ref()
+    }
+
+    pub fn set_program(&mut self, program: Option<Program>) {
+        self.program = program;
+        self.mark_code();
+    }
+
+    // Reports writes to the assembled program and to every address fetched
+    // as an opcode
+    pub fn set_code_watch(&mut self, watch: Option<CodeWatch>) {
+        self.bus.set_code_watch(watch);
+        self.code_writes.clear();
+        self.mark_code();
+    }
+
+    pub fn is_watching_code(&self) -> bool {
+        self.bus.is_watching_code()
+    }
+
+    pub fn stops_on_code_writes(&self) -> bool {
+        self.bus.stops_on_code_writes()
+    }
+
+    // Called with every write to code, as it is found
+    pub fn set_code_hook(&mut self, hook: Option<CodeHook>) {
+        self.code_hook = hook;
+    }
+
+    // Writes to code made by the last step
+    pub fn get_code_writes(&self) -> &[CodeWrite] {
+        &self.code_writes
+    }
+
+    fn mark_code(&mut self) {
+        let Some(watch) = self.bus.code_watch() else { return };
+        watch.clear_assembled();
+        if let Some(program) = &self.program {
+            program.code_addresses().for_each(|addr| watch.mark_assembled(addr));
+        }
+    }
+
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.program.as_ref()?.label_at(addr)
+    }
+
+    // Address as LABEL or LABEL+offset from the closest label before it, when symbols are loaded
+    pub fn symbolize(&self, addr: u16) -> String {
+        let closest = self.program.as_ref().and_then(|program| {
+            program.labels.iter()
+                .filter(|(_, at)| **at <= addr)
+                .max_by_key(|(name, at)| (**at, std::cmp::Reverse(name.as_str())))
+        });
+        match closest {
+            Some((name, at)) if *at == addr => format!("{name} ({addr:04X})"),
+            Some((name, at)) => format!("{name}+{:X} ({addr:04X})", addr - at),
+            None => format!("{addr:04X}"),
+        }
+    }
+
     pub fn get_pending_int(&self) -> Interrupts {
-        self.cpu.pending_int
+        self.cpu.get_pending_int()
+    }
+
+    pub fn get_pins(&self) -> Interrupts {
+        self.cpu.pins
+    }
+
+    pub fn set_pins(&mut self, pins: Interrupts) {
+        self.cpu.pins = pins;
+    }
+
+    pub fn set_interrupt_controller(&mut self, intc: Box<dyn InterruptController>) {
+        self.bus.set_interrupt_controller(intc);
     }
 
     pub fn get_masked_int(&self) -> Interrupts {
@@ -102,35 +859,50 @@ impl Simulator {
     }
 
     pub fn cpu_get_reg(&self, target: u8) -> u8 {
-        self.cpu.get_reg(&self.bus, target)
+        self.cpu.peek_reg(&self.bus, target)
     }
 
     pub fn cpu_get_reg_pair(&self, target: u8) -> u16 {
         self.cpu.get_reg_pair(target)
     }
 
-    pub fn clone_cpu_bus(&self) -> (CPU, Memory, Io) {
-        (self.cpu.clone(), self.bus.mem_clone(), self.bus.io_clone(), )
+    // Memory as the CPU would read it, without disturbing a device
+    pub fn mem_get8(&self, pos: u16) -> u8 {
+        self.bus.mem_peek8(pos)
     }
 
-    pub fn get_changes(&self, cpu_old: CPU, mem_old: Memory, io_old: Io) -> Changes {
-        Changes { 
-            cpu: self.cpu.diff(cpu_old), 
-            memory: self.bus.mem_diff(mem_old),
-            io: self.bus.io_diff(io_old),
-        }
+    pub fn mem_set8(&mut self, pos: u16, value: u8) {
+        self.bus.mem_set8(pos, value);
     }
 
-    pub fn mem_get8(&self, pos: u16) -> u8 {
-        self.bus.mem_get8(pos)
+    // Disassembles the instruction at the given address, returning its text and length
+    pub fn disassemble(&self, addr: u16) -> (String, u8) {
+        let bytes = [
+            self.bus.mem_peek8(addr),
+            self.bus.mem_peek8(addr.wrapping_add(1)),
+            self.bus.mem_peek8(addr.wrapping_add(2)),
+        ];
+        disassembler::disassemble_one(&bytes)
     }
 
+    // Port as IN would read it, without disturbing a device
     pub fn io_get8(&self, pos: u8) -> u8 {
-        self.bus.io_get8(pos)
+        self.bus.io_peek8(pos)
+    }
+
+    // Connects a device to the ports lower..=upper, over any already there
+    pub fn add_io_device(&mut self, lower: u8, upper: u8, device: Box<dyn IoDevice>) {
+        self.bus.add_io_device(lower, upper, device);
+    }
+
+    pub fn has_devices(&self) -> bool {
+        self.bus.has_devices()
     }
 
-    pub fn restore(&mut self, changes: &Changes) {
-        self.cpu.restore(&mut self.bus, changes);
+    // Connects a device to the memory addresses lower..=upper, over memory
+    // and any device already there
+    pub fn add_mem_device(&mut self, lower: u16, upper: u16, device: Box<dyn IoDevice>) {
+        self.bus.add_mem_device(lower, upper, device);
     }
 
     pub fn print_program(&self) {
@@ -2,15 +2,39 @@ mod utils;
 
 use std::{
     io,
-    io::Write,
+    io::{Read, Write},
+    sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc},
+    thread,
 };
 
 use bobs8085::{
-    changes::Changes,
+    DEFAULT_MAX_STEPS,
+    EndCondition,
     Simulator,
+    StepOutcome,
+    assembler::assemble_source,
+    coverage::Coverage,
+    profile::Profiler,
+    history::Watch,
     //cpu::CPU,
     //bus::Bus,
     assemble,
+    bench::{self, BENCHMARKS},
+    cpu::{CpuModel, ExecMode, InterruptSource},
+    devices::{I8155, I8253, I8255, i8253::PitModel},
+    disassembler::disassemble,
+    opcodes,
+    bus::code::CodeWatch,
+    bus::map::MemoryMap,
+    serial::{self, SoftUart},
+    snapshot::Snapshot,
+    throttle::{Throttle, is_valid_speed},
+    stack::StackGuard,
+    trace::{
+        TraceFormat, Tracer,
+        diff::{self, ColumnMap, TraceDiff},
+    },
+    vcd::write_vcd,
 };
 
 use utils::{
@@ -20,37 +44,160 @@ use utils::{
 
 // fn run_all(cpu: &mut CPU, bus: &mut Bus) {
 fn run_all(sim: &mut Simulator) {
-    sim.set_pc(0xC000);
+    if sim.serial().is_some() {
+        run_serial(sim);
+    } else {
+        // Nothing steps back in a plain run, and keeping the history would
+        // hold the cached mode back to single steps
+        if sim.get_exec_mode() == ExecMode::Cached {
+            sim.set_history_depth(0);
+        }
+        let mut running = true;
+        while running {
+            running = matches!(sim.run(u64::MAX), StepOutcome::Running | StepOutcome::Halted);
+            for message in step_messages(sim) {
+                eprintln!("{message}");
+            }
+        }
+    }
+    print_out_of_steps(sim);
+    print_drift(sim);
+    println!("\nCPU State at end of program:\n");
+    sim.print_state();
+}
+
+fn print_out_of_steps(sim: &Simulator) {
+    if sim.is_out_of_steps() {
+        eprintln!("\nStopped after {} steps without meeting the end condition ({}), see --end and --max-steps",
+            sim.get_step_count(), sim.get_end_condition());
+    }
+}
+
+// How well a throttled run kept up with real time
+fn print_drift(sim: &Simulator) {
+    let (Some(throttle), Some(drift)) = (sim.throttle(), sim.get_drift()) else { return };
+    let state = if drift >= 0.0 { "behind" } else { "ahead of" };
+    println!("\nReal time at {:.3} MHz: {:.1} ms {state} the clock, {:.1} ms dropped while lagging",
+        throttle.get_rate() / 1e6, drift.abs() * 1e3, throttle.get_lost().as_secs_f64() * 1e3);
+}
+
+// Unbalanced returns, stack guard faults, memory faults, writes to code and
+// undefined opcodes found by the last step
+fn step_messages(sim: &Simulator) -> Vec<String> {
+    let mut messages = Vec::new();
+    if let Some(warning) = sim.get_stack_warning() {
+        messages.push(format!("Warning: {warning}"));
+    }
+    if let Some(fault) = sim.get_stack_fault() {
+        let stopped = sim.stack_guard().is_some_and(StackGuard::is_fault);
+        messages.push(format!("{}: {fault}", if stopped { "Stopped" } else { "Warning" }));
+    }
+    if let Some(fault) = sim.get_mem_fault() {
+        messages.push(format!("Stopped: {fault}"));
+    }
+    for write in sim.get_code_writes() {
+        messages.push(format!("{}: {write}", if sim.stops_on_code_writes() { "Stopped" } else { "Warning" }));
+    }
+    if let Some(fault) = sim.get_undefined_opcode() {
+        messages.push(format!("Stopped: {fault}"));
+    }
+    messages
+}
+
+// Frames innermost first, like a debugger's backtrace
+fn print_backtrace(sim: &Simulator) {
+    let frames = sim.get_call_stack();
+    println!("#0  {}  (SP={:04X})", sim.symbolize(sim.get_pc()), sim.get_sp());
+    for (i, frame) in frames.iter().rev().enumerate() {
+        let via = match frame.interrupt {
+            Some(source) => format!("{source} at {}", sim.symbolize(frame.caller)),
+            None => format!("called from {}", sim.symbolize(frame.caller)),
+        };
+        println!("#{:<2} in {}  {via}  (SP={:04X}, returns to {:04X})",
+            i + 1, sim.symbolize(frame.target), frame.sp, frame.return_addr);
+    }
+}
+
+// Runs with the serial UART connected to the terminal: stdin is sent to SID
+// and whatever the program sends on SOD is printed
+fn run_serial(sim: &mut Simulator) {
+    let (tx, rx) = mpsc::channel();
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut byte = [0u8];
+            while io::stdin().read(&mut byte).is_ok_and(|n| n == 1) {
+                // Once the program is done, the next line only releases the prompt
+                if done.load(Ordering::Relaxed) {
+                    if byte[0] == b'\n' { break; }
+                } else {
+                    let _ = tx.send(byte[0]);
+                }
+            }
+        })
+    };
+
+    let mut stdout = io::stdout();
     let mut running = true;
     while running {
         running = sim.execute();
+        for message in step_messages(sim) {
+            eprintln!("\n{message}");
+        }
+        if let Some(uart) = sim.serial() {
+            let input = rx.try_iter().collect::<Vec<_>>();
+            uart.send(&input);
+            let mut flush = false;
+            while let Some(byte) = uart.receive() {
+                let _ = stdout.write_all(&[byte]);
+                flush = true;
+            }
+            if flush { let _ = stdout.flush(); }
+        }
     }
-    println!("\nCPU State at end of program:\n");
-    sim.print_state();
+
+    done.store(true, Ordering::Relaxed);
+    print_out_of_steps(sim);
+    print!("\nProgram finished, press [Enter] to continue\n");
+    let _ = stdout.flush();
+    let _ = reader.join();
 }
 
 // fn run_step(cpu: &mut CPU, bus: &mut Bus) {
 fn run_step(sim: &mut Simulator) {
-    sim.set_pc(0xC000);
-
-    let mut changes: Vec<Changes> = vec![];
-    let mut start = Changes::default();
-    start.cpu.pc = 0xC000;
-    changes.push(start);
+    sim.record_bus_cycles(true);
 
     let mut running = true;
-    let mut step = 0;
 
     while running {
         utils::clear();
-        println!("step: {step}\n");
+        println!("step: {}\n", sim.get_step_count());
         sim.print_state();
+        for message in step_messages(sim) {
+            println!("\n⚠️ {message}");
+        }
+        // Machine cycles of the steps just run forward
+        let cycles = sim.take_bus_cycles();
+        if !cycles.is_empty() {
+            println!("\nMachine cycles:");
+            for cycle in cycles.iter().rev().take(16).rev() {
+                println!("  T{:<10} {:<4} {}T  {:04X}  {:02X}",
+                    cycle.start, cycle.kind.short_name(), cycle.t_states, cycle.addr, cycle.data);
+            }
+        }
 
         let line = input!(
             "Options:\n
 [F]/[Forward]/[>]  => Go forward 1 step\n
 [S]/[Stop]/[Exit]/[|]  => Exit step by step execution\n
-[B]/[Backward]/[<]  => Go back 1 step\n
+[B]/[Backward]/[<] + [N]  => Go back 1 (or N) steps\n
+[G]/[Seek] + [N]  => Go to step N\n
+[RC] + [TARGET] [VALUE]  => Run backwards until TARGET (register, pc, sp or address) equals VALUE\n
+[Hist]/[History] + [TARGET] [FROM] [TO]  => Show how TARGET changed between two steps\n
+[BT]/[Backtrace]  => Show the subroutines entered and not yet returned from\n
+[Save] + [FILE]  => Save a snapshot of the machine to FILE\n
+[Speed] + [X|off]  => Run forward steps in real time at X times the clock rate\n
 [P]/[Print]/[Print + range]  => Print the memory\n
 > $ "
         )
@@ -63,34 +210,109 @@ fn run_step(sim: &mut Simulator) {
                     if cmd.len() >= 2 {
                         let n = cmd[1].parse().expect("Not a valid number");
                         let mut i = 0;
-                        while i < n && running == true {
-                            let (cpu_old, mem_old, io_old) = sim.clone_cpu_bus();
-
-                            running = sim.execute();
-
-                            let diff = sim.get_changes(cpu_old, mem_old, io_old);
-                            changes.push(diff);
-
+                        while i < n && running {
+                            match sim.step() {
+                                StepOutcome::Finished => running = false,
+                                // Stay in step mode to look at the fault
+                                StepOutcome::StackFault(_) | StepOutcome::CodeWrite(_) | StepOutcome::MemFault(_)
+                                | StepOutcome::UndefinedOpcode(_) => break,
+                                _ => {}
+                            }
                             i += 1;
                         }
                     } else {
-                        let (cpu_old, mem_old, io_old) = sim.clone_cpu_bus();
-                        running = sim.execute();
-                        let diff = sim.get_changes(cpu_old, mem_old, io_old);
-                        changes.push(diff);
+                        running = sim.step() != StepOutcome::Finished;
                     }
-                    step += 1;
                 }
                 "<" | "backward" | "b" => {
-                    if changes.len() != 0 {
-                        step -= 1;
-                        match &changes.pop() {
-                            Some(changes) => sim.restore(changes),
-                            _ => (),
+                    let n = if cmd.len() >= 2 { cmd[1].parse().expect("Not a valid number") } else { 1 };
+                    let mut i = 0;
+                    while i < n {
+                        match sim.reverse_step() {
+                            Ok(true) => i += 1,
+                            Ok(false) => {
+                                let _ = input!("Already at the start!\nPress [Enter] to continue\n");
+                                break;
+                            }
+                            Err(err) => {
+                                let _ = input!(format!("{err}\nPress [Enter] to continue\n"));
+                                break;
+                            }
                         }
-                    } else {
-                        println!("Already at the start!");
                     }
+                    // Replayed steps are not new bus activity
+                    sim.take_bus_cycles();
+                }
+                "g" | "seek" => {
+                    match cmd.get(1).map(|n| n.parse::<u64>()) {
+                        Some(Ok(n)) => match sim.seek(n) {
+                            Ok(reached) if reached != n => {
+                                let _ = input!(format!("Stopped at step {reached}\nPress [Enter] to continue\n"));
+                            }
+                            Ok(_) => {}
+                            Err(err) => { let _ = input!(format!("{err}\nPress [Enter] to continue\n")); }
+                        },
+                        _ => { let _ = input!("Usage: seek [STEP]\nPress [Enter] to continue\n"); }
+                    }
+                    sim.take_bus_cycles();
+                }
+                "rc" => {
+                    let watch = cmd.get(1).map(|t| t.parse::<Watch>());
+                    let value = cmd.get(2).map(|v| u16::from_str_radix(v.trim_end_matches('h'), 16));
+                    match (watch, value) {
+                        (Some(Ok(watch)), Some(Ok(value))) => {
+                            match sim.reverse_continue(|sim| watch.read(sim) == value) {
+                                Ok(Some(_)) => {}
+                                Ok(None) => {
+                                    let _ = input!("Condition never held, stopped at the earliest step\nPress [Enter] to continue\n");
+                                }
+                                Err(err) => { let _ = input!(format!("{err}\nPress [Enter] to continue\n")); }
+                            }
+                        }
+                        _ => { let _ = input!("Usage: rc [TARGET] [VALUE]\nPress [Enter] to continue\n"); }
+                    }
+                    sim.take_bus_cycles();
+                }
+                "hist" | "history" => {
+                    let watch = cmd.get(1).map(|t| t.parse::<Watch>());
+                    let from = cmd.get(2).and_then(|n| n.parse().ok()).unwrap_or(0);
+                    let to = cmd.get(3).and_then(|n| n.parse().ok()).unwrap_or(sim.get_step_count());
+                    match watch {
+                        Some(Ok(watch)) => match sim.value_history(watch, from, to) {
+                            Ok(values) => {
+                                for (step, value) in values {
+                                    println!("step {step:>8}: {value:04X}");
+                                }
+                            }
+                            Err(err) => println!("{err}"),
+                        },
+                        _ => println!("Usage: history [TARGET] [FROM] [TO]"),
+                    }
+                    let _ = input!("\nPress [Enter] to continue\n");
+                }
+                "bt" | "backtrace" => {
+                    print_backtrace(sim);
+                    let _ = input!("\nPress [Enter] to continue\n");
+                }
+                "speed" => {
+                    match cmd.get(1).copied() {
+                        Some("off") => sim.set_speed(None),
+                        Some(speed) => match speed.parse::<f64>() {
+                            Ok(speed) if is_valid_speed(speed) => sim.set_speed(Some(speed)),
+                            _ => { let _ = input!(format!("Not a valid speed: {speed}\nPress [Enter] to continue\n")); }
+                        },
+                        None => { let _ = input!("Usage: speed [X|off]\nPress [Enter] to continue\n"); }
+                    }
+                }
+                "save" => {
+                    let message = match cmd.get(1) {
+                        Some(path) => match sim.snapshot().save(path) {
+                            Ok(()) => format!("Snapshot saved to \"{path}\""),
+                            Err(err) => format!("Error saving snapshot: {err}"),
+                        },
+                        None => "Usage: save [FILE]".to_string(),
+                    };
+                    let _ = input!(format!("{message}\nPress [Enter] to continue\n"));
                 }
                 "|" | "stop" | "s" | "exit" => {
                     clear();
@@ -120,9 +342,7 @@ fn run_step(sim: &mut Simulator) {
                             Err(err) => eprintln!("ParseError: {}", err),
                         }
                         if lo > hi {
-                            let tmp = lo;
-                            lo = hi;
-                            hi = tmp;
+                            std::mem::swap(&mut lo, &mut hi);
                         }
                         sim.print_mem_range(lo, hi);
                     }
@@ -136,12 +356,529 @@ fn run_step(sim: &mut Simulator) {
     }
 
     clear();
+    for message in step_messages(sim) {
+        eprintln!("{message}");
+    }
+    print_out_of_steps(sim);
     println!("Program finished.\nCPU State at end of program:\n");
     sim.print_state();
 }
 
+fn print_opcodes() {
+    println!("OP   INSTRUCTION      BYTES  T-STATES  FLAGS");
+    for op in 0..=0xFFu8 {
+        if let Some(info) = opcodes::get(op) {
+            let operands = info.operands.iter()
+                .map(|operand| match operand {
+                    opcodes::Operand::Imm8 => "d8".to_string(),
+                    opcodes::Operand::Imm16 => "a16".to_string(),
+                    _ => opcodes::operand_text(op, *operand, &[]),
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            let t_states = if info.t_states == info.t_states_taken {
+                info.t_states.to_string()
+            } else {
+                format!("{}/{}", info.t_states, info.t_states_taken)
+            };
+            println!("{op:02X}   {:<16} {:<6} {:<9} {}",
+                format!("{} {}", info.mnemonic, operands), info.length, t_states, opcodes::flags_text(info.flags));
+        }
+    }
+}
+
+// Runs a binary memory file to the end and writes its bus activity as a waveform
+fn export_vcd(sim: &mut Simulator, options: &RunOptions, output: &str) {
+    sim.set_end_condition(options.end);
+    sim.set_max_steps(options.step_limit());
+    sim.record_bus_cycles(true);
+    while sim.execute() {}
+    let cycles = sim.take_bus_cycles();
+    print_out_of_steps(sim);
+
+    let result = std::fs::File::create(output)
+        .and_then(|file| write_vcd(io::BufWriter::new(file), &cycles, serial::DEFAULT_CLOCK_HZ));
+    match result {
+        Ok(()) => println!("{} machine cycles saved to \"{output}\"", cycles.len()),
+        Err(err) => eprintln!("Error writing \"{output}\": {err}"),
+    }
+}
+
+// Compares two traces, reading the second one through a column mapping so
+// logs from other simulators can be used
+fn diff_traces(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    let mut map = ColumnMap::default();
+    let mut mapped = false;
+    let mut format = None;
+    let mut words = args.iter();
+    while let Some(word) = words.next() {
+        if !word.starts_with("--") {
+            files.push(*word);
+            continue;
+        }
+        let value = words.next().ok_or(format!("Missing value for option \"{word}\""))?;
+        if *word == "--format" {
+            format = Some(value.parse::<TraceFormat>()?);
+            continue;
+        }
+        mapped = true;
+        map = match *word {
+            "--map" => map.with_columns(value)?,
+            "--delimiter" => map.with_delimiter(match *value {
+                "tab" => '\t',
+                "space" => ' ',
+                other => other.chars().next().unwrap_or(','),
+            }),
+            "--radix" => map.with_radix(value.parse().map_err(|_| format!("Not a valid radix: {value}"))?)?,
+            "--skip" => map.with_skip(value.parse()?),
+            _ => return Err(format!("Unknown option: {word}").into()),
+        };
+    }
+    let [left, right] = files[..] else {
+        return Err("Please provide two trace files for command \"diff\"".into());
+    };
+
+    // A column mapping reads OTHER as a delimited log
+    let right_format = format.unwrap_or(if mapped { TraceFormat::Csv } else { TraceFormat::from_path(right) });
+    let left_steps = diff::load_trace(left, TraceFormat::from_path(left), &ColumnMap::default())?;
+    let right_steps = diff::load_trace(right, right_format, &map)?;
+    match diff::diff(&left_steps, &right_steps) {
+        TraceDiff::Same { steps } => println!("Traces match ({steps} steps)"),
+        TraceDiff::Truncated { steps, left: l, right: r } => {
+            println!("Traces match for {steps} steps, then one ends ({l} steps in \"{left}\", {r} in \"{right}\")");
+        }
+        TraceDiff::Diverged(divergence) => {
+            let field = divergence.field;
+            println!("First divergence at step {} in {field}: {} vs {}",
+                divergence.step,
+                divergence.left.values[&field],
+                divergence.right.values[&field]);
+            println!("  {left}:{}: {}", divergence.left.line, divergence.left.text);
+            println!("  {right}:{}: {}", divergence.right.line, divergence.right.text);
+        }
+    }
+    Ok(())
+}
+
+// Times the built-in benchmarks in both execution modes, checking that the
+// cached mode ends in the same state as the interpreter
+fn run_benchmarks(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut names = Vec::new();
+    let mut min_time = std::time::Duration::from_millis(500);
+    let mut words = args.iter();
+    while let Some(word) = words.next() {
+        match *word {
+            "--time" => {
+                let value = words.next().ok_or("Missing value for option \"--time\"")?;
+                min_time = std::time::Duration::from_millis(value.parse()?);
+            }
+            name => names.push(bench::find(name).ok_or(format!("Unknown benchmark: {name}"))?),
+        }
+    }
+    if names.is_empty() {
+        names = BENCHMARKS.iter().collect();
+    }
+
+    println!("{:<10} {:>9} {:>13} {:>13} {:>8}", "NAME", "STEPS", "INTERPRETER", "CACHED", "SPEEDUP");
+    let mut mismatches = 0;
+    for benchmark in names {
+        let reference = benchmark.run(ExecMode::Interpreter, min_time)?;
+        let cached = benchmark.run(ExecMode::Cached, min_time)?;
+        println!("{:<10} {:>9} {:>8.1} MIPS {:>8.1} MIPS {:>7.2}x",
+            benchmark.name, reference.steps, reference.mips(), cached.mips(), cached.mips() / reference.mips());
+        if cached.digest != reference.digest {
+            eprintln!("{}: the cached mode ended in a different state from the interpreter", benchmark.name);
+            mismatches += 1;
+        }
+    }
+    if mismatches > 0 {
+        return Err(format!("{mismatches} benchmark(s) differ between the execution modes").into());
+    }
+    Ok(())
+}
+
+fn print_disassembly(filename: &str) {
+    match std::fs::read(filename) {
+        Ok(bytes) => {
+            for (addr, text) in disassemble(&bytes, 0xC000) {
+                println!("{addr:04X}    {text}");
+            }
+        }
+        Err(err) => eprintln!("Error reading \"{filename}\": {err}"),
+    }
+}
+
+// Options accepted by "run" before or after its arguments, as --option VALUE
+#[derive(Debug, Default)]
+struct RunOptions {
+    trace: Option<String>,
+    trace_format: Option<TraceFormat>,
+    trace_range: Option<(u16, u16)>,
+    trace_max: Option<u64>,
+    profile: Option<String>,
+    coverage: Option<String>,
+    coverage_html: Option<String>,
+    stack_off: bool,
+    stack_range: Option<(u16, u16)>,
+    stack_fault: bool,
+    code_writes: Option<bool>, // Watch writes to code, stopping on them when true
+    speed: Option<f64>,
+    clock: Option<u64>,
+    exec: ExecMode,
+    end: EndCondition,
+    max_steps: Option<u64>, // Given with --max-steps, where 0 lifts the limit
+    memory_map: Option<String>,
+    i8155: Vec<(u8, u16, Option<InterruptSource>)>, // IO base, RAM base and the line TIMER OUT drives
+    i8255: Vec<(u8, Option<InterruptSource>, Option<InterruptSource>)>, // IO base and the lines INTR A and B drive
+    i8253: Vec<(u8, PitModel, u64, [Option<InterruptSource>; 3])>, // IO base, divider and the lines OUT 0-2 drive
+}
+
+impl RunOptions {
+    // Splits the options out of a command line, leaving the other words in order
+    fn parse<'a>(cmd: &[&'a str]) -> Result<(RunOptions, Vec<&'a str>), String> {
+        let mut options = RunOptions::default();
+        let mut rest = Vec::new();
+        let mut words = cmd.iter();
+        while let Some(word) = words.next() {
+            if !word.starts_with("--") {
+                rest.push(*word);
+                continue;
+            }
+            let Some(value) = words.next() else {
+                return Err(format!("Missing value for option \"{word}\""));
+            };
+            match *word {
+                "--trace" => options.trace = Some(value.to_string()),
+                "--trace-format" => options.trace_format = Some(value.parse()?),
+                "--trace-range" => options.trace_range = Some(parse_range(value)?),
+                "--stack" => match *value {
+                    "off" => options.stack_off = true,
+                    range => options.stack_range = Some(parse_range(range)?),
+                },
+                "--stack-action" => match *value {
+                    "warn" => options.stack_fault = false,
+                    "stop" => options.stack_fault = true,
+                    other => return Err(format!("Unknown stack action: {other} (expected warn or stop)")),
+                },
+                "--speed" => match value.parse::<f64>() {
+                    Ok(speed) if is_valid_speed(speed) => options.speed = Some(speed),
+                    _ => return Err(format!("Not a valid speed: {value}")),
+                },
+                "--clock" => match value.parse::<u64>() {
+                    Ok(hz) if hz > 0 => options.clock = Some(hz),
+                    _ => return Err(format!("Not a valid clock rate: {value} (expected Hz)")),
+                },
+                "--exec" => match *value {
+                    "interp" => options.exec = ExecMode::Interpreter,
+                    "cached" => options.exec = ExecMode::Cached,
+                    other => return Err(format!("Unknown execution mode: {other} (expected interp or cached)")),
+                },
+                "--code-writes" => match *value {
+                    "off" => options.code_writes = None,
+                    "warn" => options.code_writes = Some(false),
+                    "stop" => options.code_writes = Some(true),
+                    other => return Err(format!("Unknown action for writes to code: {other} (expected off, warn or stop)")),
+                },
+                "--memory-map" => options.memory_map = Some(value.to_string()),
+                "--8155" => {
+                    let parts = value.split(',').collect::<Vec<_>>();
+                    let (io, ram, line) = match parts[..] {
+                        [io, ram] => (io, ram, None),
+                        [io, ram, line] => (io, ram, Some(line.parse()?)),
+                        _ => return Err(format!("Not a valid 8155: {value} (expected IO,RAM[,INT])")),
+                    };
+                    let io = parse_hex(io).and_then(|io| u8::try_from(io).ok());
+                    match (io, parse_hex(ram)) {
+                        (Some(io), Some(ram)) => options.i8155.push((io, ram, line)),
+                        _ => return Err(format!("Not a valid 8155: {value} (expected hex addresses)")),
+                    }
+                }
+                "--8255" => {
+                    let mut parts = value.split(',');
+                    let io = parts.next().and_then(parse_hex).and_then(|io| u8::try_from(io).ok());
+                    let Some(io) = io else {
+                        return Err(format!("Not a valid 8255: {value} (expected a hex IO base)"));
+                    };
+                    let lines = parts.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+                    match lines[..] {
+                        [] => options.i8255.push((io, None, None)),
+                        [a] => options.i8255.push((io, Some(a), None)),
+                        [a, b] => options.i8255.push((io, Some(a), Some(b))),
+                        _ => return Err(format!("Not a valid 8255: {value} (expected IO[,A[,B]])")),
+                    }
+                }
+                "--8253" | "--8254" => {
+                    let model = if *word == "--8253" { PitModel::I8253 } else { PitModel::I8254 };
+                    let mut parts = value.split(',');
+                    let io = parts.next().and_then(parse_hex).and_then(|io| u8::try_from(io).ok());
+                    let divider = match parts.next() {
+                        None | Some("") => Some(1),
+                        Some(divider) => divider.parse().ok().filter(|&divider| divider > 0),
+                    };
+                    let (Some(io), Some(divider)) = (io, divider) else {
+                        return Err(format!("Not a valid 8253: {value} (expected a hex IO base and a divider)"));
+                    };
+                    let mut lines = [None; 3];
+                    for (i, line) in parts.enumerate() {
+                        if i >= lines.len() {
+                            return Err(format!("Not a valid 8253: {value} (expected IO[,DIV[,OUT0,OUT1,OUT2]])"));
+                        }
+                        if !line.is_empty() {
+                            lines[i] = Some(line.parse()?);
+                        }
+                    }
+                    options.i8253.push((io, model, divider, lines));
+                }
+                "--profile" => options.profile = Some(value.to_string()),
+                "--coverage" => options.coverage = Some(value.to_string()),
+                "--coverage-html" => options.coverage_html = Some(value.to_string()),
+                "--trace-max" => match value.parse() {
+                    Ok(lines) => options.trace_max = Some(lines),
+                    Err(_) => return Err(format!("Not a valid number of lines: {value}")),
+                },
+                "--end" => options.end = value.parse()?,
+                "--max-steps" => match value.parse::<u64>() {
+                    Ok(steps) => options.max_steps = Some(steps),
+                    Err(_) => return Err(format!("Not a valid number of steps: {value} (0 for no limit)")),
+                },
+                _ => return Err(format!("Unknown option: {word}")),
+            }
+        }
+        Ok((options, rest))
+    }
+
+    // The default budget unless --max-steps is given, where 0 lifts the limit
+    fn step_limit(&self) -> Option<u64> {
+        match self.max_steps {
+            None => Some(DEFAULT_MAX_STEPS),
+            Some(0) => None,
+            Some(steps) => Some(steps),
+        }
+    }
+
+    fn tracer(&self) -> Option<std::io::Result<Tracer>> {
+        let path = self.trace.as_deref()?;
+        let format = self.trace_format.unwrap_or(TraceFormat::from_path(path));
+        Some(Tracer::to_file(path, format).map(|mut tracer| {
+            if let Some((lower, upper)) = self.trace_range {
+                tracer = tracer.with_range(lower, upper);
+            }
+            if let Some(lines) = self.trace_max {
+                tracer = tracer.with_max_lines(lines);
+            }
+            tracer
+        }))
+    }
+
+    // Real time when either the speed or the clock rate is given
+    fn throttle(&self) -> Option<Throttle> {
+        if self.speed.is_none() && self.clock.is_none() {
+            return None;
+        }
+        let clock = self.clock.unwrap_or(serial::DEFAULT_CLOCK_HZ);
+        Some(Throttle::new(clock).with_speed(self.speed.unwrap_or(1.0)))
+    }
+
+    fn add_devices(&self, sim: &mut Simulator) {
+        for (io, ram, line) in &self.i8155 {
+            let mut chip = I8155::new();
+            if let Some(line) = line {
+                chip = chip.with_timer_irq(*line);
+            }
+            chip.attach(sim, *io, *ram);
+        }
+        for (io, line_a, line_b) in &self.i8255 {
+            let mut chip = I8255::new();
+            if let Some(line) = line_a {
+                chip = chip.with_port_a_irq(*line);
+            }
+            if let Some(line) = line_b {
+                chip = chip.with_port_b_irq(*line);
+            }
+            chip.attach(sim, *io);
+        }
+        for (io, model, divider, lines) in &self.i8253 {
+            let mut chip = I8253::new().with_model(*model).with_divider(*divider);
+            for (counter, line) in lines.iter().enumerate() {
+                if let Some(line) = line {
+                    chip = chip.with_out_irq(counter, *line);
+                }
+            }
+            chip.attach(sim, *io);
+        }
+    }
+
+    fn stack_guard(&self) -> Option<StackGuard> {
+        if self.stack_off {
+            return None;
+        }
+        let mut guard = StackGuard::new().with_fault(self.stack_fault);
+        if let Some((floor, top)) = self.stack_range {
+            guard = guard.with_floor(floor).with_top(top);
+        }
+        Some(guard)
+    }
+}
+
+fn parse_range(value: &str) -> Result<(u16, u16), String> {
+    value.split_once('-')
+        .and_then(|(lo, hi)| Some((parse_hex(lo)?, parse_hex(hi)?)))
+        .ok_or(format!("Not a valid address range: {value} (expected LOWER-UPPER in hex)"))
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x").trim_end_matches(['h', 'H']), 16).ok()
+}
+
+// Runs a simulator with the given options, writing the trace and profile if
+// they were asked for. The source file, when there is one, gives the profile
+// its labels and line numbers.
+fn run_with(mut sim: Simulator, options: &RunOptions, source: Option<&str>, run: fn(&mut Simulator)) {
+    // A snapshot brings its own symbols
+    if let Some(path) = source {
+        sim.set_program(assemble_source(path).ok());
+    }
+    let program = sim.get_program().cloned();
+    sim.set_exec_mode(options.exec);
+    sim.set_end_condition(options.end);
+    sim.set_max_steps(options.step_limit());
+    if let Some(path) = &options.memory_map {
+        match MemoryMap::from_file(path) {
+            Ok(map) => sim.set_memory_map(Some(map)),
+            Err(err) => {
+                eprintln!("Error loading memory map \"{path}\": {err}");
+                return;
+            }
+        }
+    }
+    // A restored snapshot already has them
+    if !sim.has_devices() {
+        options.add_devices(&mut sim);
+    }
+    sim.set_stack_guard(options.stack_guard());
+    sim.set_code_watch(options.code_writes.map(|stop| CodeWatch::new().with_stop(stop)));
+    sim.set_throttle(options.throttle());
+    if options.profile.is_some() {
+        let mut profiler = Profiler::new();
+        if let Some(program) = program {
+            profiler = profiler.with_program(program);
+        }
+        sim.set_profiler(Some(profiler));
+    }
+    let covered = options.coverage.is_some() || options.coverage_html.is_some();
+    if covered {
+        if source.is_some() {
+            sim.set_coverage(Some(Coverage::new()));
+        } else {
+            eprintln!("Coverage needs the source file, run the .asm file instead of the binary");
+        }
+    }
+    match options.tracer() {
+        Some(Ok(tracer)) => sim.set_tracer(Some(tracer)),
+        Some(Err(err)) => {
+            eprintln!("Error creating trace file: {err}");
+            return;
+        }
+        None => {}
+    }
+    run(&mut sim);
+    if let (Some(tracer), Some(path)) = (sim.take_tracer(), &options.trace) {
+        match tracer.finish() {
+            Ok(lines) => println!("{lines} trace lines saved to \"{path}\""),
+            Err(err) => eprintln!("Error writing trace: {err}"),
+        }
+    }
+    if let (Some(profiler), Some(path)) = (sim.take_profiler(), &options.profile) {
+        print_profile(&sim, &profiler);
+        let result = std::fs::File::create(path).and_then(|file| profiler.write_folded(io::BufWriter::new(file)));
+        match result {
+            Ok(()) => println!("Folded stacks saved to \"{path}\""),
+            Err(err) => eprintln!("Error writing profile: {err}"),
+        }
+    }
+    if let (Some(coverage), Some(source)) = (sim.take_coverage(), source) {
+        write_coverage(&coverage, options, source);
+    }
+}
+
+fn write_coverage(coverage: &Coverage, options: &RunOptions, source: &str) {
+    let text = match std::fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Error reading \"{source}\": {err}");
+            return;
+        }
+    };
+    let program = match assemble_source(source) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
+    if let Some(path) = &options.coverage {
+        let result = std::fs::File::create(path)
+            .and_then(|file| coverage.write_lcov(io::BufWriter::new(file), &program, source));
+        match result {
+            Ok(()) => println!("Coverage saved to \"{path}\""),
+            Err(err) => eprintln!("Error writing coverage: {err}"),
+        }
+    }
+    if let Some(path) = &options.coverage_html {
+        let result = std::fs::File::create(path)
+            .and_then(|file| coverage.write_html(io::BufWriter::new(file), &program, &text, source));
+        match result {
+            Ok(()) => println!("Coverage report saved to \"{path}\""),
+            Err(err) => eprintln!("Error writing coverage report: {err}"),
+        }
+    }
+}
+
+// Rows shown in each profile table
+const PROFILE_ROWS: usize = 20;
+
+fn print_profile(sim: &Simulator, profiler: &Profiler) {
+    let total = sim.get_cycles().max(1);
+    println!("\nHot spots:");
+    println!("ADDR  {:<16} {:>10} {:>12} {:>7}", "INSTRUCTION", "COUNT", "CYCLES", "%");
+    for (addr, stats) in profiler.hot_spots().iter().take(PROFILE_ROWS) {
+        println!("{addr:04X}  {:<16} {:>10} {:>12} {:>6.2}%",
+            sim.disassemble(*addr).0, stats.count, stats.cycles, stats.cycles as f64 * 100.0 / total as f64);
+    }
+
+    let mut lines = profiler.line_stats();
+    if !lines.is_empty() {
+        lines.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.cycles));
+        println!("\nSource lines:");
+        println!("LINE  {:>10} {:>12} {:>7}", "COUNT", "CYCLES", "%");
+        for (line, stats) in lines.iter().take(PROFILE_ROWS) {
+            println!("{line:<5} {:>10} {:>12} {:>6.2}%",
+                stats.count, stats.cycles, stats.cycles as f64 * 100.0 / total as f64);
+        }
+    }
+
+    println!("\nSubroutines:");
+    println!("{:<16} {:>8} {:>12} {:>12}", "NAME", "CALLS", "INCLUSIVE", "EXCLUSIVE");
+    for (addr, stats) in profiler.routine_stats().iter().take(PROFILE_ROWS) {
+        println!("{:<16} {:>8} {:>12} {:>12}", profiler.name(*addr), stats.calls, stats.inclusive, stats.exclusive);
+    }
+}
+
+// Loads a memory file into a simulator for the selected CPU model and serial
+// setting, ready to start at the program
+fn load(filename: &str, model: CpuModel, baud: Option<u32>) -> Simulator {
+    let mut sim = Simulator::bus_from_file(filename);
+    sim.set_pc(0xC000);
+    sim.set_model(model);
+    sim.set_serial(baud.map(|baud| SoftUart::new(baud, serial::DEFAULT_CLOCK_HZ)));
+    sim
+}
+
 fn main() {
     // utils::clear();
+    let mut model = CpuModel::default();
+    let mut baud: Option<u32> = None; // Serial terminal on SID/SOD when set
     loop {
         let word = input!("> $ ");
         let cmd = word.as_str().split_whitespace().collect::<Vec<_>>();
@@ -150,6 +887,55 @@ fn main() {
                 "exit" | "q" | "quit" => break,
                 "cls" | "clear" => utils::clear(),
                 "h" | "help" => utils::help_simulator(),
+                "opcodes" => print_opcodes(),
+                "model" => {
+                    match cmd.get(1).copied() {
+                        None => {}
+                        Some("8085") => model = CpuModel::I8085,
+                        Some("8080") => model = CpuModel::I8080,
+                        Some(other) => eprintln!("Unknown CPU model: {other}"),
+                    }
+                    match model {
+                        CpuModel::I8085 => println!("CPU model: 8085"),
+                        CpuModel::I8080 => println!("CPU model: 8080"),
+                    }
+                }
+                "serial" => {
+                    match cmd.get(1).copied() {
+                        None => {}
+                        Some("off") => baud = None,
+                        Some(rate) => match rate.parse::<u32>() {
+                            Ok(rate) if rate > 0 => baud = Some(rate),
+                            _ => eprintln!("Not a valid baud rate: {rate}"),
+                        },
+                    }
+                    match baud {
+                        Some(rate) => println!("Serial terminal on SID/SOD at {rate} baud"),
+                        None => println!("Serial terminal off"),
+                    }
+                }
+                "bench" => {
+                    if let Err(err) = run_benchmarks(&cmd[1..]) {
+                        eprintln!("{err}");
+                    }
+                }
+                "diff" => {
+                    if let Err(err) = diff_traces(&cmd[1..]) {
+                        eprintln!("{err}");
+                    }
+                }
+                "vcd" => {
+                    let (options, cmd) = match RunOptions::parse(&cmd) {
+                        Ok(parsed) => parsed,
+                        Err(err) => { eprintln!("{err}"); continue; }
+                    };
+                    if cmd.len() < 3 { eprintln!("Please provide a binary file and an output file for command \"vcd\""); }
+                    else { export_vcd(&mut load(cmd[1], model, None), &options, cmd[2]); }
+                }
+                "disassemble" | "dis" => {
+                    if cmd.len() < 2 { eprintln!("Please provide a binary file for command \"disassemble\""); }
+                    else { print_disassembly(cmd[1]); }
+                }
                 "assemble" => {
                     if cmd.len() < 3 { eprintln!("Please provide a input file and an output file for command \"assemble\""); }
                     else {
@@ -159,7 +945,37 @@ fn main() {
                         }
                     }
                 }
+                "load" => {
+                    let (options, cmd) = match RunOptions::parse(&cmd) {
+                        Ok(parsed) => parsed,
+                        Err(err) => { eprintln!("{err}"); continue; }
+                    };
+                    let (step, path) = match cmd.get(1) {
+                        Some(&"step") => (true, cmd.get(2)),
+                        other => (false, other),
+                    };
+                    match path.map(|path| Snapshot::load(path)) {
+                        None => eprintln!("Please provide a snapshot file for command \"load\""),
+                        Some(Err(err)) => eprintln!("Error loading snapshot: {err}"),
+                        Some(Ok(snapshot)) => {
+                            // The devices have to be on the bus to take their state
+                            let mut sim = Simulator::new();
+                            options.add_devices(&mut sim);
+                            if !step {
+                                sim.set_serial(baud.map(|baud| SoftUart::new(baud, serial::DEFAULT_CLOCK_HZ)));
+                            }
+                            match sim.restore_snapshot(&snapshot) {
+                                Ok(()) => run_with(sim, &options, None, if step { run_step } else { run_all }),
+                                Err(err) => eprintln!("Error loading snapshot: {err}"),
+                            }
+                        }
+                    }
+                }
                 "run" => {
+                    let (options, cmd) = match RunOptions::parse(&cmd) {
+                        Ok(parsed) => parsed,
+                        Err(err) => { eprintln!("{err}"); continue; }
+                    };
                     if cmd.len() < 2 { eprintln!("Please provide a file name for command \"run\""); }
                     else {
                         match cmd[1] {
@@ -172,7 +988,7 @@ fn main() {
 
                                     let outfile = format!("bin/{fname}.bin");
                                     match assemble(cmd[2], fname) {
-                                        Ok(_) =>   run_step(&mut Simulator::bus_from_file(&outfile)),
+                                        Ok(_) =>   run_with(load(&outfile, model, None), &options, Some(cmd[2]), run_step),
                                         Err(err) => panic!("{}", err),
                                     }
                                 }
@@ -184,10 +1000,10 @@ fn main() {
                                         "step" => {
                                             if cmd.len() < 4 { eprintln!("Please provide a file name for command \"run bin step\""); }
                                             else {
-                                                run_step(&mut Simulator::bus_from_file(cmd[3]));
+                                                run_with(load(cmd[3], model, None), &options, None, run_step);
                                             }
                                         }
-                                        _ => run_all(&mut Simulator::bus_from_file(cmd[2])),
+                                        _ => run_with(load(cmd[2], model, baud), &options, None, run_all),
                                     }
                                 }
                             },
@@ -198,7 +1014,7 @@ fn main() {
 
                                 let outfile = format!("bin/{fname}.bin");
                                 match assemble(cmd[1], fname) {
-                                    Ok(_) =>   run_all(&mut Simulator::bus_from_file(&outfile)),
+                                    Ok(_) =>   run_with(load(&outfile, model, baud), &options, Some(cmd[1]), run_all),
                                     Err(err) => panic!("{}", err),
                                 }
                             }
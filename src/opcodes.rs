@@ -0,0 +1,392 @@
+// Single source of truth for the 8085 instruction set, shared by the
+// assembler, the CPU dispatcher, the disassembler and the CLI reference.
+
+use crate::cpu::CpuModel;
+use flags::*;
+use Operand::*;
+
+/// How an operand is encoded in the instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// Register encoded in bits 3-5 (B, C, D, E, H, L, M, A)
+    Dst,
+    /// Register encoded in bits 0-2
+    Src,
+    /// Register pair encoded in bits 4-5 (B, D, H, SP)
+    Pair,
+    /// Register pair encoded in bits 4-5, with PSW in place of SP (PUSH, POP)
+    PairPsw,
+    /// Register pair B or D encoded in bit 4 (LDAX, STAX)
+    PairBD,
+    /// Restart number encoded in bits 3-5
+    Rst,
+    /// 8-bit value following the opcode
+    Imm8,
+    /// 16-bit value following the opcode, low byte first
+    Imm16,
+}
+
+/// Flag masks, laid out as in the PSW byte pushed by PUSH PSW
+pub mod flags {
+    pub const S: u8 = 0x80;
+    pub const Z: u8 = 0x40;
+    pub const AC: u8 = 0x10;
+    pub const P: u8 = 0x04;
+    pub const CY: u8 = 0x01;
+    pub const ALL: u8 = S | Z | AC | P | CY;
+    pub const NONE: u8 = 0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand],
+    /// Instruction length in bytes, opcode included
+    pub length: u8,
+    /// T-states, or T-states when the condition fails for conditional branches
+    pub t_states: u8,
+    /// T-states when a conditional branch is taken
+    pub t_states_taken: u8,
+    /// Same as `t_states` and `t_states_taken`, on an 8080
+    pub t_states_8080: u8,
+    pub t_states_taken_8080: u8,
+    /// Flags written by the instruction
+    pub flags: u8,
+}
+
+impl Opcode {
+    /// T-states on the given CPU, as (condition failed, branch taken)
+    pub fn timing(&self, model: CpuModel) -> (u8, u8) {
+        match model {
+            CpuModel::I8085 => (self.t_states, self.t_states_taken),
+            CpuModel::I8080 => (self.t_states_8080, self.t_states_taken_8080),
+        }
+    }
+}
+
+// T-states are given as (condition failed, branch taken) pairs, which only
+// differ for conditional branches
+const fn op(
+    mnemonic: &'static str,
+    operands: &'static [Operand],
+    length: u8,
+    t_states: (u8, u8),
+    t_states_8080: (u8, u8),
+    flags: u8,
+) -> Option<Opcode> {
+    Some(Opcode {
+        mnemonic,
+        operands,
+        length,
+        t_states: t_states.0,
+        t_states_taken: t_states.1,
+        t_states_8080: t_states_8080.0,
+        t_states_taken_8080: t_states_8080.1,
+        flags,
+    })
+}
+
+// Undocumented opcodes are left out. On the 8080, RIM and SIM execute as NOP.
+#[rustfmt::skip]
+pub static OPCODES: [Option<Opcode>; 256] = [
+    /* 00 */ op("NOP", &[], 1, (4, 4), (4, 4), NONE),
+    /* 01 */ op("LXI", &[Pair, Imm16], 3, (10, 10), (10, 10), NONE),
+    /* 02 */ op("STAX", &[PairBD], 1, (7, 7), (7, 7), NONE),
+    /* 03 */ op("INX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 04 */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 05 */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 06 */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 07 */ op("RLC", &[], 1, (4, 4), (4, 4), CY),
+    /* 08 */ None,
+    /* 09 */ op("DAD", &[Pair], 1, (10, 10), (10, 10), CY),
+    /* 0A */ op("LDAX", &[PairBD], 1, (7, 7), (7, 7), NONE),
+    /* 0B */ op("DCX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 0C */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 0D */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 0E */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 0F */ op("RRC", &[], 1, (4, 4), (4, 4), CY),
+    /* 10 */ None,
+    /* 11 */ op("LXI", &[Pair, Imm16], 3, (10, 10), (10, 10), NONE),
+    /* 12 */ op("STAX", &[PairBD], 1, (7, 7), (7, 7), NONE),
+    /* 13 */ op("INX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 14 */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 15 */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 16 */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 17 */ op("RAL", &[], 1, (4, 4), (4, 4), CY),
+    /* 18 */ None,
+    /* 19 */ op("DAD", &[Pair], 1, (10, 10), (10, 10), CY),
+    /* 1A */ op("LDAX", &[PairBD], 1, (7, 7), (7, 7), NONE),
+    /* 1B */ op("DCX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 1C */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 1D */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 1E */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 1F */ op("RAR", &[], 1, (4, 4), (4, 4), CY),
+    /* 20 */ op("RIM", &[], 1, (4, 4), (4, 4), NONE),
+    /* 21 */ op("LXI", &[Pair, Imm16], 3, (10, 10), (10, 10), NONE),
+    /* 22 */ op("SHLD", &[Imm16], 3, (16, 16), (16, 16), NONE),
+    /* 23 */ op("INX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 24 */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 25 */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 26 */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 27 */ op("DAA", &[], 1, (4, 4), (4, 4), ALL),
+    /* 28 */ None,
+    /* 29 */ op("DAD", &[Pair], 1, (10, 10), (10, 10), CY),
+    /* 2A */ op("LHLD", &[Imm16], 3, (16, 16), (16, 16), NONE),
+    /* 2B */ op("DCX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 2C */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 2D */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 2E */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 2F */ op("CMA", &[], 1, (4, 4), (4, 4), NONE),
+    /* 30 */ op("SIM", &[], 1, (4, 4), (4, 4), NONE),
+    /* 31 */ op("LXI", &[Pair, Imm16], 3, (10, 10), (10, 10), NONE),
+    /* 32 */ op("STA", &[Imm16], 3, (13, 13), (13, 13), NONE),
+    /* 33 */ op("INX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 34 */ op("INR", &[Dst], 1, (10, 10), (10, 10), S | Z | AC | P),
+    /* 35 */ op("DCR", &[Dst], 1, (10, 10), (10, 10), S | Z | AC | P),
+    /* 36 */ op("MVI", &[Dst, Imm8], 2, (10, 10), (10, 10), NONE),
+    /* 37 */ op("STC", &[], 1, (4, 4), (4, 4), CY),
+    /* 38 */ None,
+    /* 39 */ op("DAD", &[Pair], 1, (10, 10), (10, 10), CY),
+    /* 3A */ op("LDA", &[Imm16], 3, (13, 13), (13, 13), NONE),
+    /* 3B */ op("DCX", &[Pair], 1, (6, 6), (5, 5), NONE),
+    /* 3C */ op("INR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 3D */ op("DCR", &[Dst], 1, (4, 4), (5, 5), S | Z | AC | P),
+    /* 3E */ op("MVI", &[Dst, Imm8], 2, (7, 7), (7, 7), NONE),
+    /* 3F */ op("CMC", &[], 1, (4, 4), (4, 4), CY),
+    /* 40 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 41 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 42 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 43 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 44 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 45 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 46 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 47 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 48 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 49 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 4A */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 4B */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 4C */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 4D */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 4E */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 4F */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 50 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 51 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 52 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 53 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 54 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 55 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 56 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 57 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 58 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 59 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 5A */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 5B */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 5C */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 5D */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 5E */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 5F */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 60 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 61 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 62 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 63 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 64 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 65 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 66 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 67 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 68 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 69 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 6A */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 6B */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 6C */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 6D */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 6E */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 6F */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 70 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 71 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 72 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 73 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 74 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 75 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 76 */ op("HLT", &[], 1, (5, 5), (7, 7), NONE),
+    /* 77 */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 78 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 79 */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 7A */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 7B */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 7C */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 7D */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 7E */ op("MOV", &[Dst, Src], 1, (7, 7), (7, 7), NONE),
+    /* 7F */ op("MOV", &[Dst, Src], 1, (4, 4), (5, 5), NONE),
+    /* 80 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 81 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 82 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 83 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 84 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 85 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 86 */ op("ADD", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* 87 */ op("ADD", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 88 */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 89 */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 8A */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 8B */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 8C */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 8D */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 8E */ op("ADC", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* 8F */ op("ADC", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 90 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 91 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 92 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 93 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 94 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 95 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 96 */ op("SUB", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* 97 */ op("SUB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 98 */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 99 */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 9A */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 9B */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 9C */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 9D */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* 9E */ op("SBB", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* 9F */ op("SBB", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A0 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A1 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A2 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A3 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A4 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A5 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A6 */ op("ANA", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* A7 */ op("ANA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A8 */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* A9 */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* AA */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* AB */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* AC */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* AD */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* AE */ op("XRA", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* AF */ op("XRA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B0 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B1 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B2 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B3 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B4 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B5 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B6 */ op("ORA", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* B7 */ op("ORA", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B8 */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* B9 */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* BA */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* BB */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* BC */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* BD */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* BE */ op("CMP", &[Src], 1, (7, 7), (7, 7), ALL),
+    /* BF */ op("CMP", &[Src], 1, (4, 4), (4, 4), ALL),
+    /* C0 */ op("RNZ", &[], 1, (6, 12), (5, 11), NONE),
+    /* C1 */ op("POP", &[PairPsw], 1, (10, 10), (10, 10), NONE),
+    /* C2 */ op("JNZ", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* C3 */ op("JMP", &[Imm16], 3, (10, 10), (10, 10), NONE),
+    /* C4 */ op("CNZ", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* C5 */ op("PUSH", &[PairPsw], 1, (12, 12), (11, 11), NONE),
+    /* C6 */ op("ADI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* C7 */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* C8 */ op("RZ", &[], 1, (6, 12), (5, 11), NONE),
+    /* C9 */ op("RET", &[], 1, (10, 10), (10, 10), NONE),
+    /* CA */ op("JZ", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* CB */ None,
+    /* CC */ op("CZ", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* CD */ op("CALL", &[Imm16], 3, (18, 18), (17, 17), NONE),
+    /* CE */ op("ACI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* CF */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* D0 */ op("RNC", &[], 1, (6, 12), (5, 11), NONE),
+    /* D1 */ op("POP", &[PairPsw], 1, (10, 10), (10, 10), NONE),
+    /* D2 */ op("JNC", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* D3 */ op("OUT", &[Imm8], 2, (10, 10), (10, 10), NONE),
+    /* D4 */ op("CNC", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* D5 */ op("PUSH", &[PairPsw], 1, (12, 12), (11, 11), NONE),
+    /* D6 */ op("SUI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* D7 */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* D8 */ op("RC", &[], 1, (6, 12), (5, 11), NONE),
+    /* D9 */ None,
+    /* DA */ op("JC", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* DB */ op("IN", &[Imm8], 2, (10, 10), (10, 10), NONE),
+    /* DC */ op("CC", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* DD */ None,
+    /* DE */ op("SBI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* DF */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* E0 */ op("RPO", &[], 1, (6, 12), (5, 11), NONE),
+    /* E1 */ op("POP", &[PairPsw], 1, (10, 10), (10, 10), NONE),
+    /* E2 */ op("JPO", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* E3 */ op("XTHL", &[], 1, (16, 16), (18, 18), NONE),
+    /* E4 */ op("CPO", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* E5 */ op("PUSH", &[PairPsw], 1, (12, 12), (11, 11), NONE),
+    /* E6 */ op("ANI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* E7 */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* E8 */ op("RPE", &[], 1, (6, 12), (5, 11), NONE),
+    /* E9 */ op("PCHL", &[], 1, (6, 6), (5, 5), NONE),
+    /* EA */ op("JPE", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* EB */ op("XCHG", &[], 1, (4, 4), (4, 4), NONE),
+    /* EC */ op("CPE", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* ED */ None,
+    /* EE */ op("XRI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* EF */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* F0 */ op("RP", &[], 1, (6, 12), (5, 11), NONE),
+    /* F1 */ op("POP", &[PairPsw], 1, (10, 10), (10, 10), ALL),
+    /* F2 */ op("JP", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* F3 */ op("DI", &[], 1, (4, 4), (4, 4), NONE),
+    /* F4 */ op("CP", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* F5 */ op("PUSH", &[PairPsw], 1, (12, 12), (11, 11), NONE),
+    /* F6 */ op("ORI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* F7 */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+    /* F8 */ op("RM", &[], 1, (6, 12), (5, 11), NONE),
+    /* F9 */ op("SPHL", &[], 1, (6, 6), (5, 5), NONE),
+    /* FA */ op("JM", &[Imm16], 3, (7, 10), (10, 10), NONE),
+    /* FB */ op("EI", &[], 1, (4, 4), (4, 4), NONE),
+    /* FC */ op("CM", &[Imm16], 3, (9, 18), (11, 17), NONE),
+    /* FD */ None,
+    /* FE */ op("CPI", &[Imm8], 2, (7, 7), (7, 7), ALL),
+    /* FF */ op("RST", &[Rst], 1, (12, 12), (11, 11), NONE),
+];
+
+pub const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+pub const PAIRS: [&str; 4] = ["B", "D", "H", "SP"];
+pub const PAIRS_PSW: [&str; 4] = ["B", "D", "H", "PSW"];
+
+pub fn get(opcode: u8) -> Option<&'static Opcode> {
+    OPCODES[opcode as usize].as_ref()
+}
+
+/// Looks up a mnemonic, case insensitive. Returns the base opcode, i.e. the
+/// one with every register field set to zero, with its table entry.
+pub fn find(mnemonic: &str) -> Option<(u8, &'static Opcode)> {
+    OPCODES
+        .iter()
+        .enumerate()
+        .find_map(|(i, entry)| match entry {
+            Some(info) if info.mnemonic.eq_ignore_ascii_case(mnemonic) => Some((i as u8, info)),
+            _ => None,
+        })
+}
+
+/// Text of one operand of the instruction in `bytes`, in assembler syntax
+pub fn operand_text(opcode: u8, operand: Operand, bytes: &[u8]) -> String {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    match operand {
+        Dst => REGISTERS[(opcode as usize >> 3) & 0x07].to_string(),
+        Src => REGISTERS[opcode as usize & 0x07].to_string(),
+        Pair | PairBD => PAIRS[(opcode as usize >> 4) & 0x03].to_string(),
+        PairPsw => PAIRS_PSW[(opcode as usize >> 4) & 0x03].to_string(),
+        Rst => ((opcode >> 3) & 0x07).to_string(),
+        Imm8 => format!("{:02X}h", byte(1)),
+        Imm16 => format!("{:04X}h", (byte(2) as u16) << 8 | byte(1) as u16),
+    }
+}
+
+/// Human readable list of the flags in a mask, e.g. "S Z AC P CY"
+pub fn flags_text(mask: u8) -> String {
+    [(S, "S"), (Z, "Z"), (AC, "AC"), (P, "P"), (CY, "CY")]
+        .iter()
+        .filter(|(flag, _)| mask & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
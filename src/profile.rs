@@ -0,0 +1,198 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+use crate::assembler::Program;
+
+// What the simulator saw of one step, enough to follow calls and returns
+#[derive(Debug, Clone, Copy)]
+pub struct StepRecord {
+    pub pc: u16,
+    pub opcode: u8,
+    pub next_pc: u16,     // PC after the step
+    pub cycles: u64,      // T-states the step took
+    pub end_cycles: u64,  // T-state count after the step
+    pub executed: bool,   // False while halted, when an interrupt was entered or on an undefined opcode
+    pub interrupted: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+// Cycles of a subroutine, inclusive of the subroutines it calls or not
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    routine: u16,
+    return_addr: u16,
+    entered: u64,
+}
+
+// Counts executions and cycles per address and follows CALL/RST and RET to
+// attribute cycles to subroutines. The code run before the first call is the
+// root routine, named after the address it started at.
+#[derive(Debug, Default, Clone)]
+pub struct Profiler {
+    addresses: BTreeMap<u16, Stats>,
+    routines: BTreeMap<u16, RoutineStats>,
+    stack: Vec<Frame>,
+    path: Vec<u16>,                 // Routines on the stack, root first
+    folded: HashMap<Vec<u16>, u64>, // Exclusive cycles per stack path
+    last_cycles: u64,
+    program: Option<Program>,
+}
+
+fn is_call(opcode: u8) -> bool {
+    opcode == 0xCD || opcode & 0xC7 == 0xC4
+}
+
+fn is_rst(opcode: u8) -> bool {
+    opcode & 0xC7 == 0xC7
+}
+
+fn is_ret(opcode: u8) -> bool {
+    opcode == 0xC9 || opcode & 0xC7 == 0xC0
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // Uses the program's labels to name subroutines and its line numbers for
+    // per-line totals
+    pub fn with_program(mut self, program: Program) -> Profiler {
+        self.program = Some(program);
+        self
+    }
+
+    pub fn record(&mut self, step: &StepRecord) {
+        if self.stack.is_empty() {
+            self.push(step.pc, step.pc, step.end_cycles - step.cycles);
+        }
+        self.last_cycles = step.end_cycles;
+
+        if step.executed {
+            let stats = self.addresses.entry(step.pc).or_default();
+            stats.count += 1;
+            stats.cycles += step.cycles;
+        }
+        let top = self.stack.last().expect("root frame").routine;
+        self.routines.entry(top).or_default().exclusive += step.cycles;
+        match self.folded.get_mut(&self.path[..]) {
+            Some(cycles) => *cycles += step.cycles,
+            None => { self.folded.insert(self.path.clone(), step.cycles); }
+        }
+
+        if step.interrupted {
+            self.push(step.next_pc, step.pc, step.end_cycles);
+        } else if !step.executed {
+            // Halted, nothing else to follow
+        } else if is_rst(step.opcode) {
+            self.push(step.next_pc, step.pc.wrapping_add(1), step.end_cycles);
+        } else if is_call(step.opcode) && step.next_pc != step.pc.wrapping_add(3) {
+            self.push(step.next_pc, step.pc.wrapping_add(3), step.end_cycles);
+        } else if is_ret(step.opcode) && step.next_pc != step.pc.wrapping_add(1) {
+            self.ret(step.next_pc, step.end_cycles);
+        }
+    }
+
+    fn push(&mut self, routine: u16, return_addr: u16, entered: u64) {
+        self.routines.entry(routine).or_default().calls += 1;
+        self.stack.push(Frame { routine, return_addr, entered });
+        self.path.push(routine);
+    }
+
+    // Unwinds to the frame the return lands in. A return that matches no
+    // frame (the stack was rearranged by hand) leaves the innermost one.
+    fn ret(&mut self, target: u16, cycles: u64) {
+        if self.stack.len() < 2 {
+            return;
+        }
+        let depth = self.stack.iter()
+            .rposition(|frame| frame.return_addr == target)
+            .filter(|depth| *depth > 0)
+            .unwrap_or(self.stack.len() - 1);
+        while self.stack.len() > depth {
+            let frame = self.stack.pop().expect("frame");
+            self.path.pop();
+            self.close(frame, cycles);
+        }
+    }
+
+    fn close(&mut self, frame: Frame, cycles: u64) {
+        // Recursive calls are already counted by the outer frame
+        if !self.path.contains(&frame.routine) {
+            self.routines.entry(frame.routine).or_default().inclusive += cycles - frame.entered;
+        }
+    }
+
+    // Label of the address if known, otherwise its value in hex
+    pub fn name(&self, addr: u16) -> String {
+        self.program.as_ref()
+            .and_then(|program| program.label_at(addr))
+            .map_or(format!("{addr:04X}"), str::to_string)
+    }
+
+    // Addresses sorted by the cycles spent on them, most first
+    pub fn hot_spots(&self) -> Vec<(u16, Stats)> {
+        let mut spots = self.addresses.iter().map(|(addr, stats)| (*addr, *stats)).collect::<Vec<_>>();
+        spots.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        spots
+    }
+
+    // Totals per source line, in line order. Needs the program.
+    pub fn line_stats(&self) -> Vec<(usize, Stats)> {
+        let Some(program) = &self.program else { return Vec::new() };
+        let mut lines: BTreeMap<usize, Stats> = BTreeMap::new();
+        for (addr, stats) in &self.addresses {
+            if let Some(line) = program.line_at(*addr) {
+                let total = lines.entry(line).or_default();
+                total.count += stats.count;
+                total.cycles += stats.cycles;
+            }
+        }
+        lines.into_iter().collect()
+    }
+
+    // Subroutines sorted by inclusive cycles, counting the ones still running
+    // as if they returned now
+    pub fn routine_stats(&self) -> Vec<(u16, RoutineStats)> {
+        let mut routines = self.routines.clone();
+        for (depth, frame) in self.stack.iter().enumerate() {
+            if !self.path[..depth].contains(&frame.routine) {
+                routines.entry(frame.routine).or_default().inclusive += self.last_cycles - frame.entered;
+            }
+        }
+        let mut routines = routines.into_iter().collect::<Vec<_>>();
+        routines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        routines
+    }
+
+    // Writes the cycles per call stack in the folded format read by
+    // flamegraph tools: "root;caller;callee cycles"
+    pub fn write_folded<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut stacks = self.folded.iter()
+            .filter(|(_, cycles)| **cycles > 0)
+            .map(|(path, cycles)| {
+                let names = path.iter().map(|addr| self.name(*addr)).collect::<Vec<_>>();
+                (names.join(";"), *cycles)
+            })
+            .collect::<Vec<_>>();
+        stacks.sort();
+        for (stack, cycles) in stacks {
+            writeln!(out, "{stack} {cycles}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+
+use crate::snapshot::{Reader, SnapshotError, Writer};
+
+// Clock of the usual 8085 kits (6.144 MHz crystal, divided by 2)
+pub const DEFAULT_CLOCK_HZ: u64 = 3_072_000;
+pub const DEFAULT_BAUD: u32 = 9600;
+
+// Start bit, 8 data bits and the stop bit
+const FRAME_BITS: u64 = 10;
+
+// Software UART on the SID/SOD pins, timed against the CPU cycle counter.
+// Frames are 8N1, LSB first, with the line idle high. SOD is sampled in the
+// middle of each bit to decode what the program sends, and queued input is
+// shifted into SID one frame at a time. Input is kept once sent, so going
+// back in time can send it again.
+#[derive(Debug, Clone)]
+pub struct SoftUart {
+    bit_cycles: u64,
+    line: UartLine,
+    output: VecDeque<u8>,    // Bytes decoded from SOD
+    input: Vec<u8>,          // Bytes queued for SID, sent or not
+}
+
+// Everything a step can change besides the output, small enough to keep for
+// every step in the history
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UartLine {
+    sod: bool,               // SOD level as of the last update
+    rx_start: Option<u64>,   // Cycle of the start bit being received from SOD
+    rx_bits: u8,             // Data bits sampled so far
+    rx_byte: u8,
+    tx_start: Option<u64>,   // Cycle of the start bit being sent on SID
+    tx_byte: u8,
+    sent: usize,             // Input bytes taken for SID
+}
+
+impl Default for SoftUart {
+    fn default() -> Self {
+        SoftUart::new(DEFAULT_BAUD, DEFAULT_CLOCK_HZ)
+    }
+}
+
+impl SoftUart {
+    pub fn new(baud: u32, clock_hz: u64) -> SoftUart {
+        SoftUart {
+            bit_cycles: (clock_hz / baud.max(1) as u64).max(1),
+            // SOD is low after reset until the program raises it
+            line: UartLine::default(),
+            output: VecDeque::new(),
+            input: Vec::new(),
+        }
+    }
+
+    pub fn save_state(&self, w: &mut Writer) {
+        let line = &self.line;
+        w.u64(self.bit_cycles);
+        w.bool(line.sod);
+        w.option(line.rx_start, Writer::u64);
+        w.u8(line.rx_bits);
+        w.u8(line.rx_byte);
+        w.bytes(&self.output.iter().copied().collect::<Vec<_>>());
+        w.option(line.tx_start, Writer::u64);
+        w.u8(line.tx_byte);
+        // Only what is left to send
+        w.bytes(&self.input[line.sent..]);
+    }
+
+    pub fn load_state(r: &mut Reader) -> Result<SoftUart, SnapshotError> {
+        let bit_cycles = r.u64()?.max(1);
+        let (sod, rx_start, rx_bits, rx_byte) = (r.bool()?, r.option(Reader::u64)?, r.u8()?, r.u8()?);
+        let output = r.bytes()?.iter().copied().collect();
+        let (tx_start, tx_byte) = (r.option(Reader::u64)?, r.u8()?);
+        Ok(SoftUart {
+            bit_cycles,
+            line: UartLine { sod, rx_start, rx_bits, rx_byte, tx_start, tx_byte, sent: 0 },
+            output,
+            input: r.bytes()?.to_vec(),
+        })
+    }
+
+    pub fn get_bit_cycles(&self) -> u64 {
+        self.bit_cycles
+    }
+
+    // Queues bytes to be sent to the program through SID
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    // Takes the next byte the program sent through SOD
+    pub fn receive(&mut self) -> Option<u8> {
+        self.output.pop_front()
+    }
+
+    // Bytes decoded and not yet taken
+    pub fn get_output_len(&self) -> usize {
+        self.output.len()
+    }
+
+    // Drops the bytes decoded after the first `len`, for steps run again
+    pub fn truncate_output(&mut self, len: usize) {
+        self.output.truncate(len);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.line.tx_start.is_none() && self.line.sent == self.input.len() && self.line.rx_start.is_none()
+    }
+
+    // State of the line, to go back to with `restore_line`
+    pub fn get_line(&self) -> UartLine {
+        self.line
+    }
+
+    // Goes back to an earlier state of the line. The input sent since then
+    // is sent again, the output decoded since then is kept.
+    pub fn restore_line(&mut self, line: UartLine) {
+        self.line = UartLine { sent: line.sent.min(self.input.len()), ..line };
+    }
+
+    // Takes the line as another UART left it, as when resuming a snapshot,
+    // keeping this one's queues and timing
+    pub fn resume_line(&mut self, line: UartLine) {
+        self.line = UartLine { sent: self.line.sent, ..line };
+    }
+
+    // Advances the UART to the given cycle with the current SOD level and
+    // returns the level SID should have from now on
+    pub fn update(&mut self, cycles: u64, sod: bool) -> bool {
+        self.receive_bits(cycles);
+
+        // A falling edge on an idle line is a start bit
+        let line = &mut self.line;
+        if line.rx_start.is_none() && line.sod && !sod {
+            line.rx_start = Some(cycles);
+            line.rx_bits = 0;
+            line.rx_byte = 0;
+        }
+        line.sod = sod;
+
+        self.transmit_bit(cycles)
+    }
+
+    // Samples every bit whose middle has passed, using the SOD level held
+    // since the last update
+    fn receive_bits(&mut self, cycles: u64) {
+        let line = &mut self.line;
+        let Some(start) = line.rx_start else { return };
+        loop {
+            // Bit 0 is the start bit, 1..=8 are data, 9 is the stop bit
+            let bit = line.rx_bits as u64 + 1;
+            let sample = start + bit * self.bit_cycles + self.bit_cycles / 2;
+            if sample > cycles {
+                return;
+            }
+            if bit <= 8 {
+                if line.sod {
+                    line.rx_byte |= 1 << (bit - 1);
+                }
+                line.rx_bits += 1;
+            } else {
+                // A low stop bit is a framing error, the byte is dropped
+                if line.sod {
+                    self.output.push_back(line.rx_byte);
+                }
+                line.rx_start = None;
+                return;
+            }
+        }
+    }
+
+    // The cycle count only goes back with the line restored to match, the
+    // saturating differences keep a mismatch from wrapping around
+    fn transmit_bit(&mut self, cycles: u64) -> bool {
+        let line = &mut self.line;
+        if let Some(start) = line.tx_start
+            && cycles.saturating_sub(start) >= FRAME_BITS * self.bit_cycles
+        {
+            line.tx_start = None;
+        }
+        if line.tx_start.is_none() {
+            match self.input.get(line.sent) {
+                Some(byte) => {
+                    line.tx_start = Some(cycles);
+                    line.tx_byte = *byte;
+                    line.sent += 1;
+                }
+                None => return true,
+            }
+        }
+
+        let start = line.tx_start.unwrap_or(cycles);
+        match cycles.saturating_sub(start) / self.bit_cycles {
+            0 => false,
+            bit @ 1..=8 => line.tx_byte & (1 << (bit - 1)) != 0,
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+};
+
+use crate::{
+    assembler::Program,
+    bus::{io::Io, mem::Memory},
+    cpu::CPU,
+    serial::SoftUart,
+};
+
+// File layout: MAGIC, the version as a u16, then sections of a 4-byte tag, a
+// u32 length and the payload. Numbers are little endian. Readers skip the
+// sections they don't know, so new ones don't need a new version.
+pub const MAGIC: &[u8; 8] = b"BOBS8085";
+pub const VERSION: u16 = 1;
+
+const CPU_TAG: &[u8; 4] = b"CPU ";
+const MEMORY_TAG: &[u8; 4] = b"MEM ";
+const IO_TAG: &[u8; 4] = b"IO  ";
+const SERIAL_TAG: &[u8; 4] = b"UART";
+const PROGRAM_TAG: &[u8; 4] = b"PROG";
+const DEVICES_TAG: &[u8; 4] = b"DEVS";
+
+const MEMORY_SIZE: usize = 0xFFFF+1;
+const IO_SIZE: usize = 0xFF+1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    NotASnapshot,
+    Version(u16),         // Written by a newer simulator
+    Truncated,
+    Missing(&'static str), // Required section not found
+    Invalid(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "{err}"),
+            SnapshotError::NotASnapshot => write!(f, "Not a snapshot file"),
+            SnapshotError::Version(version) => write!(f, "Snapshot version {version} is newer than this simulator supports ({VERSION})"),
+            SnapshotError::Truncated => write!(f, "Snapshot file is truncated"),
+            SnapshotError::Missing(section) => write!(f, "Snapshot has no {section} section"),
+            SnapshotError::Invalid(message) => write!(f, "Invalid snapshot: {message}"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    // Length-prefixed
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes.extend(bytes);
+    }
+
+    pub fn str(&mut self, text: &str) {
+        self.bytes(text.as_bytes());
+    }
+
+    pub fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Writer, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    fn section(&mut self, tag: &[u8; 4], write: impl FnOnce(&mut Writer)) {
+        let mut section = Writer::default();
+        write(&mut section);
+        self.bytes.extend(tag);
+        self.bytes(&section.bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().expect("2 bytes")))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn str(&mut self) -> Result<String, SnapshotError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| SnapshotError::Invalid("text is not UTF-8".to_string()))
+    }
+
+    pub fn option<T>(&mut self, read: impl FnOnce(&mut Reader<'a>) -> Result<T, SnapshotError>) -> Result<Option<T>, SnapshotError> {
+        if self.bool()? { Ok(Some(read(self)?)) } else { Ok(None) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+// Everything needed to resume a machine where it was: the CPU with its
+// interrupt and serial lines, memory, IO ports and devices, the serial
+// terminal and the symbols of the loaded program. The step history is not
+// kept, and neither is which devices are on the bus.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub cpu: CPU,
+    pub memory: Memory,
+    pub io: Io,
+    pub devices: Vec<u8>, // Saved by the bus, empty without devices
+    pub serial: Option<SoftUart>,
+    pub program: Option<Program>,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer::default();
+        out.bytes.extend(MAGIC);
+        out.u16(VERSION);
+        out.section(CPU_TAG, |w| self.cpu.save_state(w));
+        out.section(MEMORY_TAG, |w| w.bytes(self.memory.as_bytes()));
+        out.section(IO_TAG, |w| w.bytes(self.io.as_bytes()));
+        if !self.devices.is_empty() {
+            out.section(DEVICES_TAG, |w| w.bytes(&self.devices));
+        }
+        if let Some(serial) = &self.serial {
+            out.section(SERIAL_TAG, |w| serial.save_state(w));
+        }
+        if let Some(program) = &self.program {
+            out.section(PROGRAM_TAG, |w| write_program(w, program));
+        }
+        out.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = reader.u16()?;
+        if version > VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+
+        let (mut cpu, mut memory, mut io) = (None, None, None);
+        let (mut devices, mut serial, mut program) = (Vec::new(), None, None);
+        while !reader.is_empty() {
+            let tag = reader.take(4)?;
+            let mut section = Reader::new(reader.bytes()?);
+            match tag {
+                t if t == CPU_TAG => cpu = Some(CPU::load_state(&mut section)?),
+                t if t == MEMORY_TAG => memory = Some(Memory::from_bytes(sized(section.bytes()?, MEMORY_SIZE, "memory")?)),
+                t if t == IO_TAG => io = Some(Io::from_bytes(sized(section.bytes()?, IO_SIZE, "IO")?)),
+                t if t == DEVICES_TAG => devices = section.bytes()?.to_vec(),
+                t if t == SERIAL_TAG => serial = Some(SoftUart::load_state(&mut section)?),
+                t if t == PROGRAM_TAG => program = Some(read_program(&mut section)?),
+                _ => {}
+            }
+        }
+        Ok(Snapshot {
+            cpu: cpu.ok_or(SnapshotError::Missing("CPU"))?,
+            memory: memory.ok_or(SnapshotError::Missing("memory"))?,
+            io: io.ok_or(SnapshotError::Missing("IO"))?,
+            devices,
+            serial,
+            program,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), SnapshotError> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot, SnapshotError> {
+        Snapshot::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Memory and IO are saved whole, anything else is a damaged file
+fn sized<'a>(bytes: &'a [u8], size: usize, section: &str) -> Result<&'a [u8], SnapshotError> {
+    if bytes.len() != size {
+        return Err(SnapshotError::Invalid(format!("{section} section has {} bytes instead of {size}", bytes.len())));
+    }
+    Ok(bytes)
+}
+
+fn write_program(w: &mut Writer, program: &Program) {
+    w.u16(program.origin);
+    w.bytes(&program.bytes);
+    w.u32(program.lines.len() as u32);
+    for (addr, line) in &program.lines {
+        w.u16(*addr);
+        w.u32(*line as u32);
+    }
+    // Sorted so the same program always gives the same file
+    let labels = program.labels.iter().collect::<BTreeMap<_, _>>();
+    w.u32(labels.len() as u32);
+    for (name, addr) in labels {
+        w.str(name);
+        w.u16(*addr);
+    }
+}
+
+fn read_program(r: &mut Reader) -> Result<Program, SnapshotError> {
+    let origin = r.u16()?;
+    let bytes = r.bytes()?.to_vec();
+    if bytes.len() > MEMORY_SIZE {
+        return Err(SnapshotError::Invalid(format!("program has {} bytes", bytes.len())));
+    }
+    let mut lines = BTreeMap::new();
+    for _ in 0..r.u32()? {
+        let addr = r.u16()?;
+        // Every line has to point at its instruction in the program
+        if addr.wrapping_sub(origin) as usize >= bytes.len() {
+            return Err(SnapshotError::Invalid(format!("program line at {addr:04X} is outside the program")));
+        }
+        lines.insert(addr, r.u32()? as usize);
+    }
+    let mut labels = HashMap::new();
+    for _ in 0..r.u32()? {
+        labels.insert(r.str()?, r.u16()?);
+    }
+    Ok(Program { bytes, origin, lines, labels })
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::{assembler::Program, opcodes};
+
+// How a step used the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackAccess {
+    Push,     // PUSH, a taken CALL, RST or an interrupt
+    Pop,      // POP or a taken RET
+    Exchange, // XTHL
+}
+
+impl StackAccess {
+    // Works out the access from the instruction and SP around the step.
+    // `opcode` is None when an interrupt was entered instead.
+    pub fn of(opcode: Option<u8>, sp_before: u16, sp_after: u16) -> Option<StackAccess> {
+        let Some(opcode) = opcode else { return Some(StackAccess::Push) };
+        let pushed = sp_after == sp_before.wrapping_sub(2);
+        let popped = sp_after == sp_before.wrapping_add(2);
+        match opcode {
+            0xE3 => Some(StackAccess::Exchange),
+            // PUSH, CALL and conditional calls, RST
+            op if pushed && (op & 0xCF == 0xC5 || op == 0xCD || op & 0xC7 == 0xC4 || op & 0xC7 == 0xC7) => Some(StackAccess::Push),
+            // POP, RET and conditional returns
+            op if popped && (op & 0xCF == 0xC1 || op == 0xC9 || op & 0xC7 == 0xC0) => Some(StackAccess::Pop),
+            _ => None,
+        }
+    }
+}
+
+// LXI SP and SPHL, which start a new stack
+pub fn loads_sp(opcode: u8) -> bool {
+    opcode == 0x31 || opcode == 0xF9
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFaultKind {
+    Overflow { floor: u16 },   // A push went below the floor or wrapped around
+    Unloaded,                  // A push before the program loaded SP
+    Underflow { top: u16 },    // A pop read above the initial SP
+    Collision { addr: u16 },   // A stack write landed on the program
+}
+
+// A stack access outside the guarded region, and the instruction that made it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFault {
+    pub pc: u16,
+    pub opcode: Option<u8>, // None for an interrupt
+    pub sp: u16,            // SP before the step
+    pub kind: StackFaultKind,
+}
+
+impl fmt::Display for StackFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.opcode.and_then(opcodes::get) {
+            Some(info) => info.mnemonic,
+            None => "Interrupt",
+        };
+        match self.kind {
+            StackFaultKind::Overflow { floor } => write!(f,
+                "Stack overflow: {name} at {:04X} with SP={:04X} pushes below the floor {floor:04X}", self.pc, self.sp),
+            StackFaultKind::Unloaded => write!(f,
+                "Stack overflow: {name} at {:04X} with SP={:04X} pushes before SP was loaded by LXI SP or SPHL", self.pc, self.sp),
+            StackFaultKind::Underflow { top } => write!(f,
+                "Stack underflow: {name} at {:04X} with SP={:04X} pops above the initial SP {top:04X}", self.pc, self.sp),
+            StackFaultKind::Collision { addr } => write!(f,
+                "Stack collision: {name} at {:04X} with SP={:04X} writes to {addr:04X}, inside the program", self.pc, self.sp),
+        }
+    }
+}
+
+// Region the stack may use, from the floor up to the initial SP. Without a
+// configured top, the last SP loaded by the program is the initial SP. A push
+// before it loads one is reported, and its SP taken as the initial SP. The
+// assembled program is always protected from stack writes.
+#[derive(Debug, Clone, Default)]
+pub struct StackGuard {
+    floor: u16,
+    top: Option<u16>,
+    adopted: Option<u16>,         // Initial SP taken from the program
+    protected: Vec<(u16, u16)>,   // Extra regions, inclusive
+    fault: bool,                  // Stop the program instead of warning
+}
+
+// Position of SP within the stack, where an initial SP of 0 is the top of
+// memory rather than its bottom
+fn position(sp: u16, top: u16) -> u32 {
+    if sp == 0 && top == 0 { 0x10000 } else { sp as u32 }
+}
+
+impl StackGuard {
+    pub fn new() -> StackGuard {
+        StackGuard::default()
+    }
+
+    pub fn with_floor(mut self, floor: u16) -> StackGuard {
+        self.floor = floor;
+        self
+    }
+
+    // Initial SP, the stack holds the bytes below it
+    pub fn with_top(mut self, top: u16) -> StackGuard {
+        self.top = Some(top);
+        self
+    }
+
+    pub fn with_fault(mut self, fault: bool) -> StackGuard {
+        self.fault = fault;
+        self
+    }
+
+    // Also protect lower..=upper from stack writes
+    pub fn protect(mut self, lower: u16, upper: u16) -> StackGuard {
+        self.protected.push((lower.min(upper), lower.max(upper)));
+        self
+    }
+
+    pub fn get_floor(&self) -> u16 {
+        self.floor
+    }
+
+    // Initial SP, configured or taken from the program
+    pub fn get_top(&self) -> Option<u16> {
+        self.top.or(self.adopted)
+    }
+
+    pub fn is_fault(&self) -> bool {
+        self.fault
+    }
+
+    // Forgets the initial SP taken from the program, for a new run
+    pub fn reset(&mut self) {
+        self.adopted = None;
+    }
+
+    // The program loaded SP with a new value
+    pub fn load(&mut self, sp: u16) {
+        self.adopted = Some(sp);
+    }
+
+    fn is_protected(&self, addr: u16, program: Option<&Program>) -> bool {
+        let in_program = program.is_some_and(|program| {
+            let end = program.origin as u32 + program.bytes.len() as u32;
+            (program.origin as u32..end).contains(&(addr as u32))
+        });
+        in_program || self.protected.iter().any(|(lower, upper)| (*lower..=*upper).contains(&addr))
+    }
+
+    // Checks one step's stack access, `sp` being SP before the step
+    pub fn check(&mut self, access: StackAccess, pc: u16, opcode: Option<u8>, sp: u16, program: Option<&Program>) -> Option<StackFault> {
+        let fault = |kind| Some(StackFault { pc, opcode, sp, kind });
+        // SP still holds whatever it was reset to, 0 wrapping around to the
+        // top of memory. Later pushes use it as the initial SP.
+        if access == StackAccess::Push && self.get_top().is_none() {
+            self.adopted = Some(sp);
+            return fault(StackFaultKind::Unloaded);
+        }
+        let top = self.get_top()?;
+        let at = position(sp, top);
+
+        let written = match access {
+            StackAccess::Push => {
+                if at < self.floor as u32 + 2 {
+                    return fault(StackFaultKind::Overflow { floor: self.floor });
+                }
+                [at - 1, at - 2]
+            }
+            StackAccess::Pop | StackAccess::Exchange => {
+                if at + 2 > position(top, top) {
+                    return fault(StackFaultKind::Underflow { top });
+                }
+                if access == StackAccess::Pop {
+                    return None;
+                }
+                [at, at + 1]
+            }
+        };
+        written.iter()
+            .map(|addr| *addr as u16)
+            .find(|addr| self.is_protected(*addr, program))
+            .and_then(|addr| fault(StackFaultKind::Collision { addr }))
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::serial::DEFAULT_CLOCK_HZ;
+
+// Shortest sleep worth taking, shorter ones are left to accumulate
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+// Falling further behind than this (a slow host, or the program paused in a
+// debugger) starts the schedule over instead of running flat out to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// Range of speeds, as multiples of the clock rate. Others are clamped to it.
+pub const MIN_SPEED: f64 = 1e-6;
+pub const MAX_SPEED: f64 = 1e6;
+
+// Furthest ahead a cycle count can be due, so a slow rate can't overflow
+const MAX_AHEAD: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+// Whether a speed is inside the range, for callers that reject the others
+pub fn is_valid_speed(speed: f64) -> bool {
+    (MIN_SPEED..=MAX_SPEED).contains(&speed)
+}
+
+fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_nan() { 1.0 } else { speed.clamp(MIN_SPEED, MAX_SPEED) }
+}
+
+// Keeps execution in step with wall-clock time at a clock rate, optionally
+// scaled. Time is measured from an origin that moves whenever the rate
+// changes or the schedule is abandoned.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    clock_hz: u64,
+    speed: f64,         // Multiple of the clock rate
+    origin: Instant,
+    origin_cycles: u64, // Cycle count at the origin
+    lost: Duration,     // Lag dropped when starting over
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle::new(DEFAULT_CLOCK_HZ)
+    }
+}
+
+impl Throttle {
+    pub fn new(clock_hz: u64) -> Throttle {
+        Throttle {
+            clock_hz: clock_hz.max(1),
+            speed: 1.0,
+            origin: Instant::now(),
+            origin_cycles: 0,
+            lost: Duration::ZERO,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Throttle {
+        self.speed = clamp_speed(speed);
+        self
+    }
+
+    pub fn get_clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Both take the current cycle count so the change applies from now on
+    pub fn set_clock_hz(&mut self, clock_hz: u64, cycles: u64) {
+        self.clock_hz = clock_hz.max(1);
+        self.restart(cycles);
+    }
+
+    pub fn set_speed(&mut self, speed: f64, cycles: u64) {
+        self.speed = clamp_speed(speed);
+        self.restart(cycles);
+    }
+
+    // Effective rate in T-states per second
+    pub fn get_rate(&self) -> f64 {
+        self.clock_hz as f64 * self.speed
+    }
+
+    // Time dropped so far because execution fell too far behind
+    pub fn get_lost(&self) -> Duration {
+        self.lost
+    }
+
+    pub fn restart(&mut self, cycles: u64) {
+        self.origin = Instant::now();
+        self.origin_cycles = cycles;
+    }
+
+    // Wall-clock time at which the given cycle count is due
+    pub fn due(&self, cycles: u64) -> Instant {
+        let elapsed = cycles.saturating_sub(self.origin_cycles) as f64 / self.get_rate();
+        let elapsed = Duration::try_from_secs_f64(elapsed).unwrap_or(MAX_AHEAD).min(MAX_AHEAD);
+        self.origin + elapsed
+    }
+
+    // Seconds execution is behind wall-clock time, negative when ahead
+    pub fn drift(&self, cycles: u64) -> f64 {
+        self.drift_at(cycles, Instant::now())
+    }
+
+    pub fn drift_at(&self, cycles: u64, now: Instant) -> f64 {
+        let due = self.due(cycles);
+        if now >= due {
+            (now - due).as_secs_f64()
+        } else {
+            -(due - now).as_secs_f64()
+        }
+    }
+
+    // Sleeps until the given cycle count is due
+    pub fn pace(&mut self, cycles: u64) {
+        let now = Instant::now();
+        let due = self.due(cycles);
+        if due > now + MIN_SLEEP {
+            thread::sleep(due - now);
+        } else if now > due + MAX_LAG {
+            self.lost += now - due;
+            self.restart(cycles);
+        }
+    }
+
+    // Cycle count that should have been reached by now, for callers that run
+    // in bursts from a timer instead of sleeping
+    pub fn due_cycles(&mut self, cycles: u64) -> u64 {
+        self.due_cycles_at(cycles, Instant::now())
+    }
+
+    pub fn due_cycles_at(&mut self, cycles: u64, now: Instant) -> u64 {
+        let due = self.due(cycles);
+        if now > due + MAX_LAG {
+            self.lost += now - due;
+            self.origin = now;
+            self.origin_cycles = cycles;
+        }
+        let elapsed = now.saturating_duration_since(self.origin).as_secs_f64();
+        self.origin_cycles.saturating_add((elapsed * self.get_rate()) as u64)
+    }
+}
@@ -0,0 +1,195 @@
+pub mod diff;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::changes::Regs;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Csv,
+    Json, // One JSON object per line
+}
+
+impl TraceFormat {
+    // Guesses the format from a file extension, defaulting to text
+    pub fn from_path(path: &str) -> TraceFormat {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => TraceFormat::Csv,
+            Some("json" | "jsonl") => TraceFormat::Json,
+            _ => TraceFormat::Text,
+        }
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" | "txt" => Ok(TraceFormat::Text),
+            "csv" => Ok(TraceFormat::Csv),
+            "json" | "jsonl" => Ok(TraceFormat::Json),
+            other => Err(format!("Unknown trace format: {other}")),
+        }
+    }
+}
+
+// One executed instruction: the state before it ran and what it wrote
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub cycles: u64,
+    pub bytes: Vec<u8>,         // Empty when an interrupt was entered instead
+    pub mnemonic: String,
+    pub regs: Regs,
+    pub memory: Vec<(u16, u8)>, // New values, in write order
+    pub io: Vec<(u8, u8)>,
+}
+
+impl TraceEntry {
+    pub fn flags_text(&self) -> String {
+        [(self.regs.s, 'S'), (self.regs.z, 'Z'), (self.regs.ac, 'A'), (self.regs.p, 'P'), (self.regs.cy, 'C')]
+            .iter()
+            .map(|(set, letter)| if *set { *letter } else { '-' })
+            .collect()
+    }
+
+    fn bytes_text(&self) -> String {
+        self.bytes.iter().map(|b| format!("{b:02X}")).collect()
+    }
+
+    fn writes_text(&self, separator: &str) -> (String, String) {
+        let memory = self.memory.iter().map(|(addr, value)| format!("{addr:04X}={value:02X}")).collect::<Vec<_>>();
+        let io = self.io.iter().map(|(port, value)| format!("{port:02X}={value:02X}")).collect::<Vec<_>>();
+        (memory.join(separator), io.join(separator))
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W, format: TraceFormat) -> io::Result<()> {
+        let r = &self.regs;
+        match format {
+            TraceFormat::Text => {
+                write!(out, "{:>10}  {:04X}  {:<6}  {:<14} A={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X} F={}",
+                    self.cycles, r.pc, self.bytes_text(), self.mnemonic,
+                    r.a, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, self.flags_text())?;
+                for (addr, value) in &self.memory {
+                    write!(out, " [{addr:04X}]={value:02X}")?;
+                }
+                for (port, value) in &self.io {
+                    write!(out, " ({port:02X})={value:02X}")?;
+                }
+                writeln!(out)
+            }
+            TraceFormat::Csv => {
+                let (memory, io) = self.writes_text(";");
+                writeln!(out, "{},{:04X},{},\"{}\",{:02X},{:02X},{:02X},{:02X},{:02X},{:02X},{:02X},{:04X},{},{},{}",
+                    self.cycles, r.pc, self.bytes_text(), self.mnemonic,
+                    r.a, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, self.flags_text(), memory, io)
+            }
+            TraceFormat::Json => {
+                let list = |pairs: Vec<String>| pairs.join(",");
+                writeln!(out, "{{\"cycles\":{},\"pc\":{},\"bytes\":[{}],\"mnemonic\":\"{}\",\"a\":{},\"b\":{},\"c\":{},\"d\":{},\"e\":{},\"h\":{},\"l\":{},\"sp\":{},\"flags\":\"{}\",\"memory\":[{}],\"io\":[{}]}}",
+                    self.cycles, r.pc, list(self.bytes.iter().map(u8::to_string).collect()), self.mnemonic,
+                    r.a, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, self.flags_text(),
+                    list(self.memory.iter().map(|(addr, value)| format!("[{addr},{value}]")).collect()),
+                    list(self.io.iter().map(|(port, value)| format!("[{port},{value}]")).collect()))
+            }
+        }
+    }
+}
+
+pub const CSV_HEADER: &str = "cycles,pc,bytes,mnemonic,a,b,c,d,e,h,l,sp,flags,memory,io";
+
+// Writes a trace line for every instruction the simulator executes, optionally
+// only for instructions within an address range and up to a number of lines.
+// Write errors stop the trace and are reported by `finish`.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write>>,
+    format: TraceFormat,
+    range: Option<(u16, u16)>,
+    max_lines: Option<u64>,
+    lines: u64,
+    error: Option<io::Error>,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("range", &self.range)
+            .field("max_lines", &self.max_lines)
+            .field("lines", &self.lines)
+            .finish()
+    }
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> Tracer {
+        let mut tracer = Tracer {
+            out: BufWriter::new(out),
+            format,
+            range: None,
+            max_lines: None,
+            lines: 0,
+            error: None,
+        };
+        if format == TraceFormat::Csv
+            && let Err(err) = writeln!(tracer.out, "{CSV_HEADER}")
+        {
+            tracer.error = Some(err);
+        }
+        tracer
+    }
+
+    pub fn to_file(path: &str, format: TraceFormat) -> io::Result<Tracer> {
+        Ok(Tracer::new(Box::new(File::create(path)?), format))
+    }
+
+    // Only trace instructions whose address is within lower..=upper
+    pub fn with_range(mut self, lower: u16, upper: u16) -> Tracer {
+        self.range = Some((lower.min(upper), lower.max(upper)));
+        self
+    }
+
+    // Stop tracing after this many lines
+    pub fn with_max_lines(mut self, lines: u64) -> Tracer {
+        self.max_lines = Some(lines);
+        self
+    }
+
+    pub fn get_lines(&self) -> u64 {
+        self.lines
+    }
+
+    // Whether an instruction at this address would be written
+    pub fn wants(&self, pc: u16) -> bool {
+        self.error.is_none()
+            && self.max_lines.is_none_or(|max| self.lines < max)
+            && self.range.is_none_or(|(lower, upper)| (lower..=upper).contains(&pc))
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) {
+        if !self.wants(entry.regs.pc) {
+            return;
+        }
+        match entry.write(&mut self.out, self.format) {
+            Ok(()) => self.lines += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    // Flushes the output and reports the first write error, if any
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.out.flush()?;
+        Ok(self.lines)
+    }
+}
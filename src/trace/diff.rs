@@ -0,0 +1,351 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    fs,
+    str::FromStr,
+};
+
+use crate::trace::TraceFormat;
+
+// Values compared between traces. Each trace only needs the ones it has,
+// fields missing from either side are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    Pc,
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Sp,
+    Flags,
+    Memory,
+}
+
+impl Field {
+    pub const ALL: [Field; 11] = [
+        Field::Pc, Field::A, Field::B, Field::C, Field::D, Field::E,
+        Field::H, Field::L, Field::Sp, Field::Flags, Field::Memory,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Pc => "pc",
+            Field::A => "a",
+            Field::B => "b",
+            Field::C => "c",
+            Field::D => "d",
+            Field::E => "e",
+            Field::H => "h",
+            Field::L => "l",
+            Field::Sp => "sp",
+            Field::Flags => "flags",
+            Field::Memory => "memory",
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        Field::ALL.into_iter()
+            .find(|field| field.name() == s)
+            .ok_or(format!("Unknown trace field: {s}"))
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+// Where each field is found in a delimited log line, by header name or by
+// zero-based column index, and how its numbers are written
+#[derive(Debug, Clone)]
+pub struct ColumnMap {
+    columns: Vec<(Field, String)>,
+    delimiter: char,
+    radix: u32,
+    skip: usize, // Lines before the header or the first step
+}
+
+impl Default for ColumnMap {
+    // The CSV written by the tracer
+    fn default() -> Self {
+        ColumnMap {
+            columns: Field::ALL.iter().map(|field| (*field, field.name().to_string())).collect(),
+            delimiter: ',',
+            radix: 16,
+            skip: 0,
+        }
+    }
+}
+
+impl ColumnMap {
+    // Replaces the columns with a mapping such as "pc=PC,a=2,flags=F", one
+    // FIELD=COLUMN per entry
+    pub fn with_columns(mut self, spec: &str) -> Result<ColumnMap, String> {
+        let mut columns = Vec::new();
+        for entry in spec.split(',').filter(|e| !e.is_empty()) {
+            let Some((field, column)) = entry.split_once('=') else {
+                return Err(format!("Expected FIELD=COLUMN, found \"{entry}\""));
+            };
+            columns.push((field.trim().parse()?, column.trim().to_string()));
+        }
+        self.columns = columns;
+        Ok(self)
+    }
+
+    pub fn with_delimiter(mut self, delimiter: char) -> ColumnMap {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_radix(mut self, radix: u32) -> Result<ColumnMap, String> {
+        if !(2..=36).contains(&radix) {
+            return Err(format!("Not a valid radix: {radix} (expected 2 to 36)"));
+        }
+        self.radix = radix;
+        Ok(self)
+    }
+
+    pub fn with_skip(mut self, lines: usize) -> ColumnMap {
+        self.skip = lines;
+        self
+    }
+
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        let mut cells = Vec::new();
+        let mut quoted = false;
+        let mut start = 0;
+        for (i, ch) in line.char_indices() {
+            if ch == '"' {
+                quoted = !quoted;
+            } else if ch == self.delimiter && !quoted {
+                cells.push(line[start..i].trim().trim_matches('"'));
+                start = i + ch.len_utf8();
+            }
+        }
+        cells.push(line[start..].trim().trim_matches('"'));
+        if self.delimiter.is_whitespace() {
+            cells.retain(|cell| !cell.is_empty());
+        }
+        cells
+    }
+
+    // Column index of every mapped field. Names need a header line.
+    fn indexes(&self, header: Option<&str>) -> Result<Vec<(Field, usize)>, String> {
+        let names = header.map(|line| self.split(line)).unwrap_or_default();
+        self.columns.iter()
+            .map(|(field, column)| {
+                let index = match column.parse::<usize>() {
+                    Ok(index) => Some(index),
+                    Err(_) => names.iter().position(|name| name.eq_ignore_ascii_case(column)),
+                };
+                index.map(|index| (*field, index)).ok_or(format!("Column \"{column}\" not found in the header"))
+            })
+            .collect()
+    }
+
+    // Column names need a header line, indexes alone don't
+    fn needs_header(&self) -> bool {
+        self.columns.iter().any(|(_, column)| column.parse::<usize>().is_err())
+    }
+}
+
+// One step of a trace, with its values normalized so traces from different
+// tools compare equal when they agree
+#[derive(Debug, Clone, Default)]
+pub struct TraceStep {
+    pub line: usize, // Line number in the file, from 1
+    pub text: String,
+    pub values: BTreeMap<Field, String>,
+}
+
+fn parse_number(value: &str, radix: u32) -> Result<u16, String> {
+    let digits = value.trim().trim_start_matches("0x").trim_end_matches(['h', 'H']);
+    u16::from_str_radix(digits, radix).map_err(|_| format!("Not a number: \"{value}\""))
+}
+
+// Flags as SZAPC letters with '-' when clear. Accepts that same form, the
+// 8-position "SZ-A-P-C" form or the numeric flag byte.
+fn normalize_flags(value: &str, radix: u32) -> Result<String, String> {
+    let value = value.trim();
+    let letters = value.chars().all(|c| c.is_ascii_alphabetic() || c == '-');
+    let set: Vec<bool> = match value.len() {
+        5 if letters => value.chars().map(|c| c != '-').collect(),
+        8 if letters => [0, 1, 3, 5, 7].iter().map(|i| value.as_bytes()[*i] != b'-').collect(),
+        _ => {
+            let byte = parse_number(value, radix)?;
+            [0x80, 0x40, 0x10, 0x04, 0x01].iter().map(|bit| byte & bit != 0).collect()
+        }
+    };
+    Ok(set.iter().zip("SZAPC".chars()).map(|(set, letter)| if *set { letter } else { '-' }).collect())
+}
+
+// Memory writes as ADDR=VALUE pairs in hex, separated by ';'
+fn normalize_memory(value: &str, radix: u32) -> Result<String, String> {
+    let pairs = value
+        .split([';', ' '])
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (addr, value) = pair.split_once('=').ok_or(format!("Expected ADDR=VALUE, found \"{pair}\""))?;
+            Ok(format!("{:04X}={:02X}", parse_number(addr, radix)?, parse_number(value, radix)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(pairs.join(";"))
+}
+
+fn normalize(field: Field, value: &str, radix: u32) -> Result<String, String> {
+    match field {
+        Field::Pc | Field::Sp => Ok(format!("{:04X}", parse_number(value, radix)?)),
+        Field::Flags => normalize_flags(value, radix),
+        Field::Memory => normalize_memory(value, radix),
+        _ => Ok(format!("{:02X}", parse_number(value, radix)?)),
+    }
+}
+
+// Reads a delimited log, one step per line
+pub fn parse_delimited(text: &str, map: &ColumnMap) -> Result<Vec<TraceStep>, String> {
+    let mut lines = text.lines().enumerate().skip(map.skip).filter(|(_, line)| !line.trim().is_empty());
+    let header = if map.needs_header() { lines.next().map(|(_, line)| line) } else { None };
+    let indexes = map.indexes(header)?;
+
+    lines.map(|(n, line)| {
+        let cells = map.split(line);
+        let mut values = BTreeMap::new();
+        for (field, index) in &indexes {
+            let cell = cells.get(*index).copied().unwrap_or("");
+            if cell.is_empty() && *field != Field::Memory {
+                continue;
+            }
+            let value = normalize(*field, cell, map.radix).map_err(|err| format!("line {}: {err}", n + 1))?;
+            values.insert(*field, value);
+        }
+        Ok(TraceStep { line: n + 1, text: line.to_string(), values })
+    })
+    .collect()
+}
+
+// Reads the text written by the tracer: the cycles, PC, bytes and mnemonic,
+// then the registers as NAME=VALUE and the writes as [ADDR]=VALUE
+pub fn parse_text(text: &str) -> Result<Vec<TraceStep>, String> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let error = |err: String| format!("line {}: {err}", n + 1);
+            let mut words = line.split_whitespace();
+            let pc = words.nth(1).ok_or(error("Missing PC".to_string()))?;
+            let mut values = BTreeMap::new();
+            values.insert(Field::Pc, normalize(Field::Pc, pc, 16).map_err(error)?);
+            let mut memory = Vec::new();
+            for (name, value) in words.filter_map(|word| word.split_once('=')) {
+                if let Some(addr) = name.strip_prefix('[') {
+                    memory.push(format!("{}={value}", addr.trim_end_matches(']')));
+                } else if let Ok(field) = name.parse::<Field>() {
+                    values.insert(field, normalize(field, value, 16).map_err(error)?);
+                } else if name == "F" {
+                    values.insert(Field::Flags, normalize_flags(value, 16).map_err(error)?);
+                }
+            }
+            values.insert(Field::Memory, normalize_memory(&memory.join(";"), 16).map_err(error)?);
+            Ok(TraceStep { line: n + 1, text: line.to_string(), values })
+        })
+        .collect()
+}
+
+// Value of a key in one of the tracer's JSON lines, as its raw text
+fn json_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{key}\":"))? + key.len() + 3;
+    let rest = &line[start..];
+    let mut depth = 0;
+    let mut quoted = false;
+    for (i, ch) in rest.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted => depth -= 1,
+            ',' | '}' if !quoted && depth == 0 => return Some(rest[..i].trim_matches('"')),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Reads the JSON lines written by the tracer
+pub fn parse_json_lines(text: &str) -> Result<Vec<TraceStep>, String> {
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let mut values = BTreeMap::new();
+            for field in Field::ALL {
+                let Some(raw) = json_value(line, field.name()) else { continue };
+                let value = match field {
+                    Field::Flags => normalize_flags(raw, 16),
+                    // [[addr,value],...] in decimal
+                    Field::Memory => {
+                        let numbers = raw.split(|c: char| !c.is_ascii_digit()).filter(|n| !n.is_empty()).collect::<Vec<_>>();
+                        let pairs = numbers.chunks(2).map(|pair| pair.join("=")).collect::<Vec<_>>();
+                        normalize_memory(&pairs.join(";"), 10)
+                    }
+                    _ => normalize(field, raw, 10),
+                };
+                values.insert(field, value.map_err(|err| format!("line {}: {err}", n + 1))?);
+            }
+            Ok(TraceStep { line: n + 1, text: line.to_string(), values })
+        })
+        .collect()
+}
+
+// Loads a trace file in one of the tracer's formats, CSV being any delimited
+// log read with the given column mapping
+pub fn load_trace(path: &str, format: TraceFormat, map: &ColumnMap) -> Result<Vec<TraceStep>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let steps = match format {
+        TraceFormat::Text => parse_text(&text)?,
+        TraceFormat::Csv => parse_delimited(&text, map)?,
+        TraceFormat::Json => parse_json_lines(&text)?,
+    };
+    Ok(steps)
+}
+
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub step: usize,
+    pub field: Field,
+    pub left: TraceStep,
+    pub right: TraceStep,
+}
+
+#[derive(Debug, Clone)]
+pub enum TraceDiff {
+    // Both traces agree on every common field for all their steps
+    Same { steps: usize },
+    // The traces agree as far as the shorter one goes
+    Truncated { steps: usize, left: usize, right: usize },
+    Diverged(Divergence),
+}
+
+// Compares two traces step by step and reports the first field that differs
+pub fn diff(left: &[TraceStep], right: &[TraceStep]) -> TraceDiff {
+    for (step, (l, r)) in left.iter().zip(right).enumerate() {
+        for field in Field::ALL {
+            if let (Some(a), Some(b)) = (l.values.get(&field), r.values.get(&field))
+                && a != b
+            {
+                return TraceDiff::Diverged(Divergence { step, field, left: l.clone(), right: r.clone() });
+            }
+        }
+    }
+    if left.len() == right.len() {
+        TraceDiff::Same { steps: left.len() }
+    } else {
+        TraceDiff::Truncated { steps: left.len().min(right.len()), left: left.len(), right: right.len() }
+    }
+}
@@ -57,7 +57,60 @@ pub fn help_simulator() {
     println!("run bin step [FILENAME]   --> Run program (Step by step) from binary memory file");
     println!("                          (Program in binary memory file should be between positions");
     println!("                           C000 and CFFF in memory)");
+    println!("    --trace [FILE]        --> Write a line per executed instruction to FILE");
+    println!("    --trace-format [FMT]  --> text, csv or json (default: from the file extension)");
+    println!("    --trace-range [LO-HI] --> Only trace instructions between two hex addresses");
+    println!("    --trace-max [N]       --> Stop tracing after N lines");
+    println!("    --profile [FILE]      --> Show hot spots, source lines and subroutine cycles, and save");
+    println!("                          the call stacks to FILE in folded format (for flamegraphs)");
+    println!("    --coverage [FILE]     --> Save instruction and branch coverage as lcov to FILE");
+    println!("    --coverage-html [FILE]--> Save the source annotated with coverage as HTML to FILE");
+    println!("    --stack [LO-HI|off]   --> Stack region, from its floor to the initial SP (default: the");
+    println!("                          SP loaded by LXI SP or SPHL, down to 0000; pushing before it is");
+    println!("                          reported)");
+    println!("    --stack-action [ACT]  --> warn or stop when the stack leaves its region or writes over");
+    println!("                          the program (default: warn)");
+    println!("    --speed [X]           --> Run in real time at X times the clock rate (1 = real speed, 1e-6 to 1e6)");
+    println!("    --clock [HZ]          --> Clock rate for real time (default: 3072000, an SDK-85)");
+    println!("    --code-writes [ACT]   --> off, warn or stop when the program writes to its own code or");
+    println!("                          to an address executed as an opcode (default: off)");
+    println!("    --exec [MODE]         --> interp, or cached to run from decoded blocks (default: interp).");
+    println!("                          Tracing, profiling, coverage, --code-writes and serial run as interp");
+    println!("    --end [COND]          --> halt, addr=XXXX to stop when PC gets there, or cycles=N to stop");
+    println!("                          after N T-states (default: halt)");
+    println!("    --max-steps [N]       --> Stop a run that takes N steps without meeting its end condition,");
+    println!("                          0 for no limit (default: 10000000)");
+    println!("    --memory-map [FILE]   --> Decode memory as the ROM, RAM, unmapped and mirrored regions in");
+    println!("                          FILE, one per line (see test/sdk85.map; default: all RAM)");
+    println!("    --8155 [IO,RAM[,INT]] --> Add an 8155 with its ports and RAM at hex bases (an SDK-85 has");
+    println!("                          20,2000), TIMER OUT driving trap, rst7.5, rst6.5, rst5.5 or intr");
+    println!("    --8255 [IO[,A[,B]]]   --> Add an 8255 with its ports at a hex base, its INTR A and B");
+    println!("                          driving the lines A and B (trap, rst7.5, rst6.5, rst5.5 or intr)");
+    println!("    --8253 [IO[,DIV,..]]  --> Add an 8253 (--8254 for one with readback) with its ports at a hex");
+    println!("                          base, counting every DIV T-states (default: 1), and the lines OUT 0,");
+    println!("                          1 and 2 drive after DIV, empty for none (e.g. 40,2,rst7.5)");
+    println!("load [FILE]               --> Resume (Without step) from a snapshot saved with \"save\" in");
+    println!("                          step mode. Accepts the options of \"run\"");
+    println!("load step [FILE]          --> Resume (Step by step) from a snapshot");
     println!("assemble [INPUT] [OUTPUT] --> Assemble program in plain text file([INPUT]) and creates");
     println!("                               memory file ([OUTPUT])");
+    println!("dis | disassemble [FILE]  --> Disassemble program in binary memory file");
+    println!("diff [TRACE] [OTHER]      --> Compare two traces and show the first step where they differ");
+    println!("    --map [FIELD=COL,..]  --> Columns of OTHER by header name or index (fields: pc, a, b, c,");
+    println!("                          d, e, h, l, sp, flags, memory)");
+    println!("    --delimiter [C]       --> Column separator of OTHER (\"tab\" and \"space\" allowed)");
+    println!("    --radix [N]           --> Base of the numbers in OTHER (default 16)");
+    println!("    --skip [N]            --> Lines to skip at the start of OTHER");
+    println!("    --format [FMT]        --> text, csv or json for OTHER (default: from the file extension,");
+    println!("                          csv with any of the options above)");
+    println!("vcd [FILE] [OUTPUT]       --> Run program from binary memory file and save its bus");
+    println!("                          cycles as a VCD waveform (GTKWave). Accepts --end and --max-steps");
+    println!("bench [NAME..]            --> Time the built-in benchmarks in both execution modes and check");
+    println!("                          that they end in the same state");
+    println!("    --time [MS]           --> Minimum time per benchmark and mode (default: 500)");
+    println!("opcodes                   --> List the instruction set with sizes, T-states and flags");
+    println!("model [8085|8080]         --> Show or select the CPU model used by \"run\"");
+    println!("serial [BAUD|off]         --> Connect the terminal to SID/SOD as a serial line (8N1)");
+    println!("                          when running without step");
     println!();
 }
@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+
+use crate::bus::cycles::{BusCycle, MachineCycle};
+
+// Pins written to the waveform, with their VCD identifiers. Active-low
+// signals keep their electrical level.
+const CLK: char = '!';
+const ALE: char = '"';
+const IO_M: char = '#';
+const S1: char = '$';
+const S0: char = '%';
+const RD: char = '&';
+const WR: char = '\'';
+const INTA: char = '(';
+const AD: char = ')';
+const A: char = '*';
+
+// Level of every pin during one half of a T-state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pins {
+    clk: bool,
+    ale: bool,
+    io_m: bool,
+    s1: bool,
+    s0: bool,
+    rd: bool,
+    wr: bool,
+    inta: bool,
+    ad: Option<u8>, // None while AD0-7 float
+    a: Option<u8>,
+}
+
+impl Pins {
+    fn idle() -> Pins {
+        Pins {
+            clk: false, ale: false, io_m: false, s1: false, s0: false,
+            rd: true, wr: true, inta: true, ad: None, a: None,
+        }
+    }
+
+    // Pins during T-state `t` of a machine cycle, first or second half
+    fn during(cycle: &BusCycle, t: u8, first_half: bool) -> Pins {
+        let (io_m, s1, s0) = cycle.kind.status();
+        let mut pins = Pins { clk: first_half, io_m, s1, s0, ..Pins::idle() };
+        if cycle.kind == MachineCycle::BusIdle {
+            return pins;
+        }
+
+        pins.a = Some((cycle.addr >> 8) as u8);
+        match t {
+            // T1: the low address byte is latched on the falling edge of ALE
+            0 => {
+                pins.ale = first_half;
+                pins.ad = Some(cycle.addr as u8);
+            }
+            // T2, T3: the transfer, data is valid for reads from T3
+            1 | 2 => {
+                match cycle.kind {
+                    MachineCycle::Inta => pins.inta = false,
+                    kind if kind.is_read() => pins.rd = false,
+                    _ => pins.wr = false,
+                }
+                if cycle.kind.is_write() || t == 2 {
+                    pins.ad = Some(cycle.data);
+                }
+            }
+            // T4-T6 of an opcode fetch: decoding, the bus floats
+            _ => {}
+        }
+        pins
+    }
+}
+
+fn bits(value: Option<u8>) -> String {
+    match value {
+        Some(value) => format!("b{value:08b}"),
+        None => "bzzzzzzzz".to_string(),
+    }
+}
+
+fn write_changes<W: Write>(out: &mut W, old: Option<Pins>, new: Pins) -> io::Result<()> {
+    let scalars = [
+        (CLK, new.clk, old.map(|p| p.clk)),
+        (ALE, new.ale, old.map(|p| p.ale)),
+        (IO_M, new.io_m, old.map(|p| p.io_m)),
+        (S1, new.s1, old.map(|p| p.s1)),
+        (S0, new.s0, old.map(|p| p.s0)),
+        (RD, new.rd, old.map(|p| p.rd)),
+        (WR, new.wr, old.map(|p| p.wr)),
+        (INTA, new.inta, old.map(|p| p.inta)),
+    ];
+    for (id, level, before) in scalars {
+        if before != Some(level) {
+            writeln!(out, "{}{id}", level as u8)?;
+        }
+    }
+    if old.map(|p| p.ad) != Some(new.ad) {
+        writeln!(out, "{} {AD}", bits(new.ad))?;
+    }
+    if old.map(|p| p.a) != Some(new.a) {
+        writeln!(out, "{} {A}", bits(new.a))?;
+    }
+    Ok(())
+}
+
+// Writes the machine cycles as a VCD waveform of the multiplexed bus and
+// control pins, timed for a CPU clock of `clock_hz`
+pub fn write_vcd<W: Write>(mut out: W, cycles: &[BusCycle], clock_hz: u64) -> io::Result<()> {
+    // Half a clock period in picoseconds
+    let half_period = 500_000_000_000 / clock_hz.max(1);
+
+    writeln!(out, "$version bobs8085 $end")?;
+    writeln!(out, "$timescale 1ps $end")?;
+    writeln!(out, "$scope module i8085 $end")?;
+    for (id, name) in [
+        (CLK, "CLK"), (ALE, "ALE"), (IO_M, "IO_M"), (S1, "S1"), (S0, "S0"),
+        (RD, "RD_n"), (WR, "WR_n"), (INTA, "INTA_n"),
+    ] {
+        writeln!(out, "$var wire 1 {id} {name} $end")?;
+    }
+    writeln!(out, "$var wire 8 {AD} AD[7:0] $end")?;
+    writeln!(out, "$var wire 8 {A} A[15:8] $end")?;
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$enddefinitions $end")?;
+
+    let origin = cycles.first().map_or(0, |c| c.start);
+    let mut last: Option<Pins> = None;
+    for cycle in cycles {
+        for t in 0..cycle.t_states {
+            let t_state = cycle.start - origin + t as u64;
+            for (half, first_half) in [(0, true), (1, false)] {
+                let pins = Pins::during(cycle, t, first_half);
+                writeln!(out, "#{}", (t_state * 2 + half) * half_period)?;
+                if last.is_none() {
+                    writeln!(out, "$dumpvars")?;
+                    write_changes(&mut out, None, pins)?;
+                    writeln!(out, "$end")?;
+                } else {
+                    write_changes(&mut out, last, pins)?;
+                }
+                last = Some(pins);
+            }
+        }
+    }
+
+    // Leave the bus released after the last cycle
+    if let (Some(last), Some(cycle)) = (last, cycles.last()) {
+        let end = cycle.start - origin + cycle.t_states as u64;
+        writeln!(out, "#{}", end * 2 * half_period)?;
+        write_changes(&mut out, Some(last), Pins { clk: true, ..Pins::idle() })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+// A subroutine called 16K times, saving registers on the stack
+    LXI SP,FFF0h
+    LXI D,4000h     // Calls left
+LOOP:
+    CALL WORK
+    DCX D
+    MOV A,D
+    ORA E
+    JNZ LOOP
+    HLT
+WORK:
+    PUSH B
+    PUSH H
+    MOV A,E
+    ADD D
+    MOV B,A
+    MOV H,B
+    POP H
+    POP B
+    RET
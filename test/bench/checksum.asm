@@ -0,0 +1,17 @@
+// 16-bit sum of the bytes from 0000 to 7FFF, in BC
+    LXI H,0000h     // Pointer
+    LXI D,8000h     // Bytes left
+    LXI B,0000h     // Sum
+SUM:
+    MOV A,C
+    ADD M
+    MOV C,A
+    MOV A,B
+    ACI 00h
+    MOV B,A
+    INX H
+    DCX D
+    MOV A,D
+    ORA E
+    JNZ SUM
+    HLT
@@ -0,0 +1,10 @@
+// Nested countdown loops, the tightest code there is
+    MVI B,00h       // 256 outer passes
+OUTER:
+    MVI C,00h       // 256 inner passes
+INNER:
+    DCR C
+    JNZ INNER
+    DCR B
+    JNZ OUTER
+    HLT
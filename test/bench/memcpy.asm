@@ -0,0 +1,21 @@
+// Copies 4K from D000 to E000, 16 times
+    MVI A,10h
+    STA F000h       // Passes left
+AGAIN:
+    LXI B,D000h     // Source
+    LXI H,E000h     // Destination
+    LXI D,1000h     // Bytes left
+COPY:
+    LDAX B
+    MOV M,A
+    INX B
+    INX H
+    DCX D
+    MOV A,D
+    ORA E
+    JNZ COPY
+    LDA F000h
+    SUI 01h
+    STA F000h
+    JNZ AGAIN
+    HLT
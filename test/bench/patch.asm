@@ -0,0 +1,14 @@
+// Rewrites an instruction before running it on every pass, the worst case
+// for the block cache
+    LXI D,2000h     // Passes left
+    MVI B,00h
+LOOP:
+    MVI A,04h       // INR B
+    STA SLOT
+SLOT:
+    NOP
+    DCX D
+    MOV A,D
+    ORA E
+    JNZ LOOP
+    HLT
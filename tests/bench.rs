@@ -0,0 +1,18 @@
+// Checks that the cached mode ends every benchmark in the same state as the
+// interpreter, the reference it has to match.
+
+use std::time::Duration;
+
+use bobs8085::bench::BENCHMARKS;
+use bobs8085::cpu::ExecMode;
+
+#[test]
+fn cached_mode_matches_the_interpreter() {
+    for benchmark in BENCHMARKS {
+        let reference = benchmark.run(ExecMode::Interpreter, Duration::ZERO).unwrap();
+        let cached = benchmark.run(ExecMode::Cached, Duration::ZERO).unwrap();
+        assert_eq!(cached.steps, reference.steps, "steps of {}", benchmark.name);
+        assert_eq!(cached.cycles, reference.cycles, "cycles of {}", benchmark.name);
+        assert_eq!(cached.digest, reference.digest, "final state of {}", benchmark.name);
+    }
+}
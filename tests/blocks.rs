@@ -0,0 +1,51 @@
+// Checks that the cached mode sees code written while it runs: a changed
+// operand is read as the instruction runs, a changed opcode drops the blocks
+// that hold it.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::cpu::ExecMode;
+use bobs8085::{Simulator, StepOutcome};
+
+// Each pass writes the operand of MVI C at C007h, and the second one turns
+// the NOP at C00Ah into INR A, in the middle of the running block
+const PATCH_PROGRAM: &str = "
+    MVI B, 03h
+    MVI A, 00h
+    loop: STA C008h
+    MVI C, 00h
+    INR A
+    NOP
+    MOV D, A
+    MVI A, 3Ch
+    STA C00Ah
+    MOV A, D
+    DCR B
+    JNZ loop
+    HLT
+";
+
+fn run(mode: ExecMode) -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_exec_mode(mode);
+    sim.set_history_depth(0);
+    sim.load_program(assemble_text(PATCH_PROGRAM).unwrap());
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+    sim
+}
+
+#[test]
+fn written_code_is_run_as_written() {
+    let reference = run(ExecMode::Interpreter);
+    let cached = run(ExecMode::Cached);
+
+    // A counts 1, then 3 and 5 with the patched INR A, and MVI C loads what
+    // A was at the start of the last pass
+    assert_eq!(reference.cpu_get_reg(7), 0x05);
+    assert_eq!(reference.cpu_get_reg(1), 0x03);
+    for reg in 0..8 {
+        assert_eq!(cached.cpu_get_reg(reg), reference.cpu_get_reg(reg), "register {reg}");
+    }
+    assert_eq!(cached.mem_get8(0xC008), reference.mem_get8(0xC008));
+    assert_eq!(cached.get_step_count(), reference.get_step_count());
+    assert_eq!(cached.get_cycles(), reference.get_cycles());
+}
@@ -0,0 +1,71 @@
+// Checks that the CPU runs against a bus other than the simulator's own and
+// makes the same accesses through it.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::bus::Bus8085;
+use bobs8085::cpu::CPU;
+
+// Flat memory and ports, with every access logged
+#[derive(Debug)]
+struct Fixture {
+    mem: Vec<u8>,
+    ports: [u8; 256],
+    log: Vec<(char, u16, u8)>,
+}
+
+impl Bus8085 for Fixture {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.mem[addr as usize];
+        self.log.push(('R', addr, value));
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.mem[addr as usize] = value;
+        self.log.push(('W', addr, value));
+    }
+
+    fn io_read(&mut self, port: u8) -> u8 {
+        self.log.push(('I', port as u16, self.ports[port as usize]));
+        self.ports[port as usize]
+    }
+
+    fn io_write(&mut self, port: u8, value: u8) {
+        self.ports[port as usize] = value;
+        self.log.push(('O', port as u16, value));
+    }
+
+    fn inta(&mut self) -> u8 {
+        0xFF
+    }
+}
+
+#[test]
+fn runs_against_a_custom_bus() {
+    let program = assemble_text("
+        LXI SP, D000h
+        IN 10h
+        INR A
+        PUSH PSW
+        STA C800h
+        SHLD FFFFh
+        OUT 11h
+        HLT
+    ").unwrap();
+    let mut bus = Fixture { mem: vec![0; 0x10000], ports: [0; 256], log: Vec::new() };
+    bus.mem[program.origin as usize..][..program.bytes.len()].copy_from_slice(&program.bytes);
+    bus.ports[0x10] = 0x41;
+
+    let mut cpu = CPU::default();
+    cpu.set_pc(program.origin);
+    while !cpu.is_halted() {
+        cpu.execute(&mut bus);
+    }
+
+    assert_eq!(bus.ports[0x11], 0x42);
+    assert_eq!(bus.mem[0xC800], 0x42);
+    assert_eq!(bus.mem[0xCFFF], 0x42, "A pushed");
+    let writes = bus.log.iter().filter(|(kind, ..)| *kind == 'W').map(|(_, addr, _)| *addr).collect::<Vec<_>>();
+    assert_eq!(writes, [0xCFFF, 0xCFFE, 0xC800, 0xFFFF, 0x0000]);
+    assert!(bus.log.contains(&('I', 0x10, 0x41)));
+}
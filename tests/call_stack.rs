@@ -0,0 +1,94 @@
+// Checks how the call stack follows returns: a RET after an unbalanced PUSH,
+// one that skips a frame, one with no call, and the cap on kept frames.
+
+mod common;
+
+use bobs8085::cpu::{ExecMode, Frame, StackWarning};
+
+use common::machine;
+
+const MODES: [ExecMode; 2] = [ExecMode::Interpreter, ExecMode::Cached];
+
+#[test]
+fn ret_after_an_unbalanced_push() {
+    for mode in MODES {
+        let mut sim = machine(mode, "
+            LXI SP, 3000h
+            LXI B, C009h
+            CALL func
+            HLT
+            func: PUSH B
+            RET
+        ");
+        sim.run(4);
+        let frame = Frame { caller: 0xC006, target: 0xC00A, return_addr: 0xC009, sp: 0x2FFE, interrupt: None };
+        assert_eq!(sim.get_call_stack(), [frame], "{mode:?}");
+        assert_eq!(sim.get_stack_warning(), None, "{mode:?}");
+
+        // Returns to the pushed BC, which is where the call would have gone
+        sim.run(1);
+        let warning = StackWarning { pc: 0xC00B, sp: 0x2FFC, expected: Some(frame) };
+        assert_eq!(sim.get_stack_warning(), Some(warning), "{mode:?}");
+        assert_eq!(warning.to_string(), "RET at C00B with SP=2FFC, but the call to C00A left SP=2FFE (unbalanced PUSH/POP?)");
+        assert!(sim.get_call_stack().is_empty(), "{mode:?}");
+        assert_eq!(sim.get_pc(), 0xC009, "{mode:?}");
+
+        sim.run(1);
+        assert_eq!(sim.get_stack_warning(), None, "{mode:?}: cleared by the next step");
+    }
+}
+
+#[test]
+fn ret_past_an_inner_frame() {
+    // inner drops its return address and returns for outer
+    for mode in MODES {
+        let mut sim = machine(mode, "
+            LXI SP, 3000h
+            CALL outer
+            HLT
+            outer: CALL inner
+            inner: POP H
+            RET
+        ");
+        sim.run(4);
+        assert_eq!(sim.get_call_stack().len(), 2, "{mode:?}");
+        let inner = sim.get_call_stack()[1];
+
+        sim.run(1);
+        assert_eq!(sim.get_stack_warning(), Some(StackWarning { pc: 0xC00B, sp: 0x2FFE, expected: Some(inner) }), "{mode:?}");
+        assert!(sim.get_call_stack().is_empty(), "{mode:?}");
+        assert_eq!(sim.get_pc(), 0xC006, "{mode:?}");
+    }
+}
+
+#[test]
+fn ret_without_a_call() {
+    let mut sim = machine(ExecMode::Interpreter, "
+        LXI SP, 3000h
+        LXI B, C008h
+        PUSH B
+        RET
+        HLT
+    ");
+    sim.run(4);
+    let warning = sim.get_stack_warning().unwrap();
+    assert_eq!(warning, StackWarning { pc: 0xC007, sp: 0x2FFE, expected: None });
+    assert_eq!(warning.to_string(), "RET at C007 with no call to return from");
+}
+
+#[test]
+fn oldest_frames_are_dropped() {
+    for mode in MODES {
+        let mut sim = machine(mode, "
+            LXI SP, 3000h
+            func: CALL func
+        ");
+        sim.run(301);
+        let frames = sim.get_call_stack();
+        assert_eq!(frames.len(), 256, "{mode:?}");
+        // The 45th call is the oldest left
+        assert_eq!(frames[0].sp, 0x3000 - 2 * 45, "{mode:?}");
+        assert_eq!(frames[255].sp, 0x3000 - 2 * 300, "{mode:?}");
+        assert!(frames.iter().all(|frame| frame.target == 0xC003 && frame.return_addr == 0xC006), "{mode:?}");
+    }
+}
@@ -0,0 +1,81 @@
+// Checks that writes to the assembled program and to addresses run as
+// opcodes are reported to the hook, and stop the program when asked to.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use bobs8085::bus::code::{CodeHook, CodeOrigin, CodeWatch, CodeWrite};
+use bobs8085::cpu::ExecMode;
+use bobs8085::{Simulator, StepOutcome};
+
+use common::machine;
+
+const MODES: [ExecMode; 2] = [ExecMode::Interpreter, ExecMode::Cached];
+
+// Writes INR A, RET to 2000h and calls it, then overwrites the INR and the
+// operand of the first instruction
+const PROGRAM: &str = "
+    MVI A, 3Ch
+    STA 2000h
+    MVI A, C9h
+    STA 2001h
+    LXI SP, 3000h
+    CALL 2000h
+    MVI A, 00h
+    STA 2000h
+    STA C001h
+    HLT
+";
+
+const WRITES: [CodeWrite; 2] = [
+    CodeWrite { pc: 0xC012, addr: 0x2000, old: 0x3C, value: 0x00, origin: CodeOrigin::Fetched },
+    CodeWrite { pc: 0xC015, addr: 0xC001, old: 0x3C, value: 0x00, origin: CodeOrigin::Assembled },
+];
+
+fn watched(mode: ExecMode, watch: CodeWatch) -> (Simulator, Rc<RefCell<Vec<CodeWrite>>>) {
+    let mut sim = machine(mode, PROGRAM);
+    sim.set_code_watch(Some(watch));
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let hooked = writes.clone();
+    sim.set_code_hook(Some(CodeHook(Box::new(move |write| hooked.borrow_mut().push(*write)))));
+    (sim, writes)
+}
+
+#[test]
+fn hook_gets_every_write_to_code() {
+    for mode in MODES {
+        let (mut sim, writes) = watched(mode, CodeWatch::new());
+        // Runs pause after each write for the caller to report it
+        for write in WRITES {
+            assert_eq!(sim.run(u64::MAX), StepOutcome::Running, "{mode:?}");
+            assert_eq!(sim.get_code_writes(), [write], "{mode:?}");
+        }
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(*writes.borrow(), WRITES, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7), 0x00, "{mode:?}");
+
+        // Only writes made by steps count
+        sim.mem_set8(0xC000, 0x00);
+        assert_eq!(writes.borrow().len(), 2, "{mode:?}");
+    }
+}
+
+#[test]
+fn stop_on_each_write_to_code() {
+    for mode in MODES {
+        let (mut sim, writes) = watched(mode, CodeWatch::new().with_stop(true));
+        assert!(sim.stops_on_code_writes());
+
+        for write in WRITES {
+            assert_eq!(sim.run(u64::MAX), StepOutcome::CodeWrite(write), "{mode:?}");
+            assert_eq!(sim.get_code_writes(), [write], "{mode:?}");
+            assert_eq!(sim.get_pc(), write.pc + 3, "{mode:?}: stopped after the write");
+        }
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert!(sim.get_code_writes().is_empty(), "{mode:?}");
+        assert_eq!(*writes.borrow(), WRITES, "{mode:?}: hook called when stopping too");
+    }
+
+    assert_eq!(WRITES[0].to_string(), "Write to code: instruction at C012 changed 2000 from 3C to 00 (executed as an opcode)");
+}
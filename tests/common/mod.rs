@@ -0,0 +1,16 @@
+// Fixtures shared by the tests that run a program in both execution modes
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::cpu::ExecMode;
+use bobs8085::Simulator;
+
+// Loads a program to run in a mode, the cached one without step-back history
+pub fn machine(mode: ExecMode, program: &str) -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_exec_mode(mode);
+    if mode == ExecMode::Cached {
+        sim.set_history_depth(0);
+    }
+    sim.load_program(assemble_text(program).unwrap());
+    sim
+}
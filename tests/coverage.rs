@@ -0,0 +1,70 @@
+// Checks the execution and branch counts coverage records for each source
+// line and the lcov tracefile written from them.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::coverage::{BranchCount, Coverage, LineCoverage};
+use bobs8085::Simulator;
+
+// Line 1 is empty, the loop runs three times and the last line never runs
+const PROGRAM: &str = "
+    MVI B, 03h
+    loop: DCR B
+    JNZ loop
+    CZ done
+    HLT
+    done: RET
+    RNZ
+";
+
+fn covered() -> Simulator {
+    let mut sim = Simulator::new();
+    sim.load_program(assemble_text(PROGRAM).unwrap());
+    sim.set_coverage(Some(Coverage::new()));
+    sim.run(u64::MAX);
+    sim
+}
+
+#[test]
+fn branch_directions_are_counted_per_line() {
+    let sim = covered();
+    let lines = sim.coverage().unwrap().lines(sim.get_program().unwrap());
+    let line = |count, branch| LineCoverage { count, branch };
+
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[&2], line(1, None));
+    assert_eq!(lines[&3], line(3, None));
+    assert_eq!(lines[&4], line(3, Some(BranchCount { taken: 2, not_taken: 1 })));
+    assert_eq!(lines[&5], line(1, Some(BranchCount { taken: 1, not_taken: 0 })));
+    assert_eq!(lines[&6], line(1, None), "HLT counted once");
+    assert_eq!(lines[&7], line(1, None));
+    assert_eq!(lines[&8], line(0, Some(BranchCount::default())));
+    assert_eq!(sim.coverage().unwrap().count_at(0xC003), 3);
+}
+
+#[test]
+fn lcov_tracefile() {
+    let sim = covered();
+    let mut out = Vec::new();
+    sim.coverage().unwrap().write_lcov(&mut out, sim.get_program().unwrap(), "loop.asm").unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+        TN:\n\
+        SF:loop.asm\n\
+        BRDA:4,0,0,2\n\
+        BRDA:4,0,1,1\n\
+        BRDA:5,0,0,1\n\
+        BRDA:5,0,1,0\n\
+        BRDA:8,0,0,-\n\
+        BRDA:8,0,1,-\n\
+        BRF:6\n\
+        BRH:3\n\
+        DA:2,1\n\
+        DA:3,3\n\
+        DA:4,3\n\
+        DA:5,1\n\
+        DA:6,1\n\
+        DA:7,1\n\
+        DA:8,0\n\
+        LF:7\n\
+        LH:6\n\
+        end_of_record\n");
+}
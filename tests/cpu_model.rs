@@ -0,0 +1,208 @@
+// Checks where the 8080 model differs from the 8085 on the same program:
+// RIM and SIM, the flags PUSH PSW stores, AC after AND, the interrupt inputs
+// and the T-states of each instruction.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::cpu::{CpuModel, Interrupts};
+use bobs8085::{opcodes, Simulator};
+
+const MODELS: [CpuModel; 2] = [CpuModel::I8085, CpuModel::I8080];
+
+// Register indexes of cpu_get_reg and the AC flag of get_flag
+const A: u8 = 7;
+const AC: u8 = 2;
+
+fn machine(model: CpuModel, program: &str) -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_model(model);
+    sim.load_program(assemble_text(program).unwrap());
+    sim
+}
+
+#[test]
+fn rim_and_sim_are_nops_on_the_8080() {
+    for model in MODELS {
+        let mut sim = machine(model, "
+            MVI A, 0C9h
+            SIM
+            MVI A, 55h
+            RIM
+            HLT
+        ");
+        sim.run(2);
+        // SOD enabled and set, RST 5.5 masked
+        match model {
+            CpuModel::I8085 => {
+                assert!(sim.get_sod());
+                assert!(sim.get_masked_int().rst5_5);
+            }
+            CpuModel::I8080 => {
+                assert!(!sim.get_sod());
+                let masks = sim.get_masked_int();
+                assert!(!masks.rst7_5 && !masks.rst6_5 && !masks.rst5_5);
+            }
+        }
+
+        sim.run(2);
+        let a = sim.cpu_get_reg(A);
+        match model {
+            // The masks and the interrupt enable, nothing pending
+            CpuModel::I8085 => assert_eq!(a, 0x01),
+            CpuModel::I8080 => assert_eq!(a, 0x55),
+        }
+    }
+}
+
+#[test]
+fn push_psw_stores_the_fixed_flag_bits_of_the_8080() {
+    for model in MODELS {
+        // Every flag set through POP PSW, then pushed back
+        let mut sim = machine(model, "
+            LXI SP, 3000h
+            LXI B, 00FFh
+            PUSH B
+            POP PSW
+            PUSH PSW
+            HLT
+        ");
+        sim.run(5);
+        let flags = sim.mem_get8(0x2FFE);
+        assert_eq!(flags & 0xD5, 0xD5, "{model:?}: S, Z, AC, P and CY");
+        assert_eq!(flags & 0x28, 0x00, "{model:?}: bits 3 and 5");
+        match model {
+            CpuModel::I8085 => assert_eq!(flags & 0x02, 0x00),
+            CpuModel::I8080 => assert_eq!(flags & 0x02, 0x02),
+        }
+    }
+}
+
+#[test]
+fn and_sets_ac_from_bit_3_on_the_8080() {
+    // A and the operand of ANA B or ANI, and AC on the 8080
+    let cases = [
+        (0xF0, 0x30, false),
+        (0xF8, 0x30, true),
+        (0x01, 0x08, true),
+        (0x0F, 0xF0, true),
+        (0x00, 0x00, false),
+    ];
+    for model in MODELS {
+        for (a, value, ac_8080) in cases {
+            for and in ["ANA B", &format!("ANI {value:02X}h")] {
+                let mut sim = machine(model, &format!("
+                    MVI A, {a:02X}h
+                    MVI B, {value:02X}h
+                    {and}
+                    HLT
+                "));
+                sim.run(3);
+                assert_eq!(sim.cpu_get_reg(A), a & value);
+                let ac = match model {
+                    CpuModel::I8085 => true,
+                    CpuModel::I8080 => ac_8080,
+                };
+                assert_eq!(sim.get_flag(AC), ac, "{model:?}: {a:02X} {and} ({value:02X})");
+            }
+        }
+    }
+}
+
+#[test]
+fn the_8080_only_takes_intr() {
+    let lines = [
+        ("TRAP", Interrupts { trap: true, ..Default::default() }, 0x0024),
+        ("RST 7.5", Interrupts { rst7_5: true, ..Default::default() }, 0x003C),
+        ("RST 6.5", Interrupts { rst6_5: true, ..Default::default() }, 0x0034),
+        ("RST 5.5", Interrupts { rst5_5: true, ..Default::default() }, 0x002C),
+        ("INTR", Interrupts { intr: true, ..Default::default() }, 0x0038),
+    ];
+    for model in MODELS {
+        for (name, pins, vector) in lines {
+            // Unmask everything and wait, with a HLT at every vector
+            let mut sim = machine(model, "
+                LXI SP, 3000h
+                MVI A, 08h
+                SIM
+                EI
+                loop: JMP loop
+            ");
+            for (_, _, vector) in lines {
+                sim.mem_set8(vector, 0x76);
+            }
+            sim.run(5);
+            sim.set_pins(pins);
+            sim.run(3);
+
+            // INTR gets RST 7 from the default controller
+            let taken = model == CpuModel::I8085 || name == "INTR";
+            if taken {
+                assert_eq!(sim.get_pc(), vector + 1, "{model:?}: {name}");
+                assert!(sim.is_halted(), "{model:?}: {name}");
+            } else {
+                assert_eq!(sim.get_pc(), 0xC007, "{model:?}: {name}");
+                assert!(!sim.get_pending_int().any(), "{model:?}: {name}");
+                // The pin is still high for a switch back to the 8085
+                assert!(sim.get_pins().any(), "{model:?}: {name}");
+                sim.set_model(CpuModel::I8085);
+                sim.run(3);
+                assert_eq!(sim.get_pc(), vector + 1, "{name} after switching to the 8085");
+            }
+        }
+    }
+}
+
+#[test]
+fn t_states_follow_the_model() {
+    // Each instruction with its T-states on the 8085 and the 8080
+    let steps = [
+        ("XRA A", 4, 4),
+        ("MOV B, A", 4, 5),
+        ("JNZ far", 7, 10),
+        ("JZ near", 10, 10),
+        ("INX H", 6, 5),
+        ("DCX H", 6, 5),
+        ("PUSH B", 12, 11),
+        ("POP B", 10, 10),
+        ("CNZ far", 9, 11),
+        ("HLT", 5, 7),
+    ];
+    let program = "
+        XRA A
+        MOV B, A
+        JNZ far
+        JZ near
+        near: INX H
+        DCX H
+        PUSH B
+        POP B
+        CNZ far
+        HLT
+        far: HLT
+    ";
+    for model in MODELS {
+        let mut sim = machine(model, &format!("LXI SP, 3000h\n{program}"));
+        sim.step();
+        let mut total = 0;
+        for (text, t_8085, t_8080) in steps {
+            let pc = sim.get_pc();
+            let before = sim.get_cycles();
+            sim.step();
+            let taken = sim.get_cycles() - before;
+            let expected = match model {
+                CpuModel::I8085 => t_8085,
+                CpuModel::I8080 => t_8080,
+            };
+            assert_eq!(taken, expected, "{model:?}: {text}");
+
+            // The same as the table, for the branch as it went
+            let (base, branch) = opcodes::get(sim.mem_get8(pc)).unwrap().timing(model);
+            assert!(taken == base as u64 || taken == branch as u64, "{model:?}: {text}");
+            total += taken;
+        }
+        let expected = match model {
+            CpuModel::I8085 => 73,
+            CpuModel::I8080 => 78,
+        };
+        assert_eq!(total, expected, "{model:?}");
+    }
+}
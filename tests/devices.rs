@@ -0,0 +1,164 @@
+// Checks that devices are reached by IN and OUT or by memory accesses,
+// interrupt the CPU from their ticks, are left alone by views, and are kept
+// by the step-back history and snapshots.
+
+mod common;
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::bus::device::IoDevice;
+use bobs8085::cpu::{ExecMode, Interrupts};
+use bobs8085::snapshot::{Reader, Snapshot, SnapshotError, Writer};
+use bobs8085::{Simulator, StepOutcome};
+
+const PORT: u8 = 0x40;
+
+// One-shot timer: OUT starts it for 10 T-states per unit, it raises RST 7.5
+// when it runs out, and IN reads and clears the flag
+#[derive(Debug, Default)]
+struct Timer {
+    left: u64,
+    fired: bool,
+}
+
+impl IoDevice for Timer {
+    fn read(&mut self, port: u16) -> u8 {
+        let value = self.peek(port);
+        self.fired = false;
+        value
+    }
+
+    fn peek(&self, _port: u16) -> u8 {
+        self.fired as u8
+    }
+
+    fn write(&mut self, _port: u16, value: u8) {
+        self.left = value as u64 * 10;
+        self.fired = false;
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if self.left > 0 {
+            self.left = self.left.saturating_sub(cycles);
+            self.fired = self.left == 0;
+        }
+    }
+
+    fn irq(&self) -> Interrupts {
+        Interrupts { rst7_5: self.fired, ..Default::default() }
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        w.u64(self.left);
+        w.bool(self.fired);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.left = r.u64()?;
+        self.fired = r.bool()?;
+        Ok(())
+    }
+}
+
+// Starts the timer and spins until its interrupt, whose handler reads the
+// flag into A and halts
+const PROGRAM: &str = "
+    MVI A, 08h
+    SIM
+    EI
+    MVI A, 20h
+    OUT 40h
+    loop: JMP loop
+";
+
+fn machine(mode: ExecMode) -> Simulator {
+    let mut sim = common::machine(mode, PROGRAM);
+    sim.add_io_device(PORT, PORT, Box::new(Timer::default()));
+    // RST 7.5 handler: IN 40h, HLT
+    for (i, byte) in [0xDB, PORT, 0x76].into_iter().enumerate() {
+        sim.mem_set8(0x003C + i as u16, byte);
+    }
+    sim
+}
+
+#[test]
+fn timer_interrupts_the_program() {
+    let mut steps = Vec::new();
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode);
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.get_pc(), 0x003F, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7), 1, "{mode:?}: flag read by the handler");
+        assert_eq!(sim.io_get8(PORT), 0, "{mode:?}: flag left set");
+        steps.push((sim.get_step_count(), sim.get_cycles()));
+    }
+    assert_eq!(steps[0], steps[1], "steps and cycles of both modes");
+}
+
+#[test]
+fn history_and_snapshots_keep_the_device() {
+    let mut sim = machine(ExecMode::Interpreter);
+    sim.run(u64::MAX);
+    let snapshot = Snapshot::from_bytes(&sim.snapshot().to_bytes()).unwrap();
+
+    // Undo HLT and IN, which cleared the flag
+    assert!(sim.step_back().unwrap() && sim.step_back().unwrap());
+    assert_eq!(sim.io_get8(PORT), 1);
+
+    let mut other = Simulator::new();
+    assert!(other.restore_snapshot(&snapshot).is_err(), "restored without the device");
+    other.add_io_device(PORT, PORT, Box::new(Timer { left: 5, fired: true }));
+    other.restore_snapshot(&snapshot).unwrap();
+    assert_eq!(other.io_get8(PORT), 0);
+}
+
+// Display with a register per digit. Reading a digit counts the read.
+#[derive(Debug, Default)]
+struct Display {
+    digits: [u8; 4],
+    reads: u8,
+}
+
+impl IoDevice for Display {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.reads += 1;
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            4 => self.reads,
+            digit => self.digits[digit as usize],
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if let Some(digit) = self.digits.get_mut(addr as usize) {
+            *digit = value;
+        }
+    }
+}
+
+#[test]
+fn memory_mapped_device() {
+    let program = "
+        MVI A, 12h
+        STA 1800h
+        LXI H, 1801h
+        MVI M, 34h
+        LDA 1801h
+        MOV B, M
+        HLT
+    ";
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = Simulator::new();
+        sim.set_exec_mode(mode);
+        sim.add_mem_device(0x1800, 0x1804, Box::new(Display::default()));
+        sim.load_program(assemble_text(program).unwrap());
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+
+        assert_eq!((sim.cpu_get_reg(7), sim.cpu_get_reg(0)), (0x34, 0x34), "{mode:?}");
+        assert_eq!(sim.mem_get8(0x1800), 0x12, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(6), 0x34, "{mode:?}: M through the device");
+        assert_eq!(sim.mem_get8(0x1804), 2, "{mode:?}: reads by the program only");
+    }
+}
@@ -0,0 +1,87 @@
+// Checks the end conditions, the step limit that stops a program which
+// never meets its end condition, and running into an undefined opcode.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::cpu::{ExecMode, UndefinedOpcode};
+use bobs8085::{EndCondition, Simulator, StepOutcome, opcodes};
+
+const SPIN_PROGRAM: &str = "
+    loop: INR A
+    JMP loop
+";
+
+#[test]
+fn end_conditions_are_parsed() {
+    assert_eq!("halt".parse(), Ok(EndCondition::Halt));
+    assert_eq!("addr=C003".parse(), Ok(EndCondition::Address(0xC003)));
+    assert_eq!("ADDR=0x10".parse(), Ok(EndCondition::Address(0x0010)));
+    assert_eq!("cycles=1000".parse(), Ok(EndCondition::Cycles(1000)));
+    assert!("addr=12345".parse::<EndCondition>().is_err());
+    assert!("cycles=-1".parse::<EndCondition>().is_err());
+    assert!("forever".parse::<EndCondition>().is_err());
+
+    for condition in [EndCondition::Halt, EndCondition::Address(0xC003), EndCondition::Cycles(7)] {
+        assert_eq!(condition.to_string().parse(), Ok(condition));
+    }
+}
+
+#[test]
+fn program_without_hlt_stops_at_the_step_limit() {
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = Simulator::new();
+        sim.set_exec_mode(mode);
+        sim.set_max_steps(Some(1001));
+        sim.load_program(assemble_text(SPIN_PROGRAM).unwrap());
+
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.get_step_count(), 1001, "{mode:?}");
+        assert!(sim.is_out_of_steps(), "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7), 0xF5, "{mode:?}: 501 INR");
+    }
+}
+
+#[test]
+fn end_condition_is_met_before_the_step_limit() {
+    for (condition, steps) in [(EndCondition::Address(0xC001), 1), (EndCondition::Cycles(60), 9)] {
+        for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+            let mut sim = Simulator::new();
+            sim.set_exec_mode(mode);
+            sim.set_max_steps(Some(1000));
+            sim.set_end_condition(condition);
+            sim.load_program(assemble_text(SPIN_PROGRAM).unwrap());
+
+            assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{condition} {mode:?}");
+            assert_eq!(sim.get_step_count(), steps, "{condition} {mode:?}");
+            assert!(!sim.is_out_of_steps(), "{condition} {mode:?}");
+        }
+    }
+}
+
+#[test]
+fn undefined_opcodes_stop_the_program() {
+    let undefined = (0..=0xFFu8).filter(|op| opcodes::get(*op).is_none()).collect::<Vec<_>>();
+    assert_eq!(undefined, [0x08, 0x10, 0x18, 0x28, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD]);
+
+    for opcode in undefined {
+        for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+            let mut sim = Simulator::new();
+            sim.set_exec_mode(mode);
+            sim.load_program(assemble_text("
+                MVI A, 01h
+                JMP D000h
+            ").unwrap());
+            sim.mem_set8(0xD000, opcode);
+
+            let fault = UndefinedOpcode { pc: 0xD000, opcode };
+            assert_eq!(sim.run(u64::MAX), StepOutcome::UndefinedOpcode(fault), "{opcode:02X} {mode:?}");
+            assert_eq!(fault.to_string(), format!("Undefined opcode {opcode:02X} at D000"));
+            assert_eq!(sim.get_pc(), 0xD000, "{opcode:02X} {mode:?}");
+            assert_eq!(sim.get_step_count(), 3, "{opcode:02X} {mode:?}");
+            assert_eq!(sim.get_cycles(), 7 + 10 + 4, "{opcode:02X} {mode:?}: MVI, JMP and the fetch");
+
+            // It stays there
+            assert_eq!(sim.step(), StepOutcome::UndefinedOpcode(fault), "{opcode:02X} {mode:?}");
+            assert_eq!(sim.get_pc(), 0xD000, "{opcode:02X} {mode:?}");
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// Checks travelling through the history: seeking, stepping back and running
+// backwards, past the step-back history through the checkpoints.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::coverage::Coverage;
+use bobs8085::history::{MAX_CHECKPOINTS, Watch};
+use bobs8085::Simulator;
+
+// Counts in A and in memory, one INR every three steps
+const COUNTER_PROGRAM: &str = "
+    MVI A, 00h
+    loop: INR A
+    STA 2000h
+    JMP loop
+";
+
+// A short step-back history, so going back more than a few steps replays
+// from a checkpoint
+fn machine() -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_history_depth(4);
+    sim.set_checkpoint_interval(10);
+    sim.load_program(assemble_text(COUNTER_PROGRAM).unwrap());
+    sim
+}
+
+// PC, A and the counter in memory
+fn state(sim: &Simulator) -> (u16, u8, u8) {
+    (sim.get_pc(), sim.cpu_get_reg(7), sim.mem_get8(0x2000))
+}
+
+// State before each step, and after the last
+fn run_recording(sim: &mut Simulator, steps: u64) -> Vec<(u16, u8, u8)> {
+    let mut states = vec![state(sim)];
+    for _ in 0..steps {
+        sim.step();
+        states.push(state(sim));
+    }
+    states
+}
+
+#[test]
+fn seek_goes_back_and_forward() {
+    let mut sim = machine();
+    let states = run_recording(&mut sim, 60);
+
+    for step in [58, 37, 3, 0, 25, 60, 41] {
+        assert_eq!(sim.seek(step).unwrap(), step);
+        assert_eq!(state(&sim), states[step as usize], "step {step}");
+    }
+    assert_eq!(sim.seek(80).unwrap(), 80, "past the recorded steps");
+}
+
+#[test]
+fn reverse_step_replays_across_checkpoints() {
+    let mut sim = machine();
+    let states = run_recording(&mut sim, 45);
+
+    for step in (0..45).rev() {
+        assert!(sim.reverse_step().unwrap(), "step {step}");
+        assert_eq!(sim.get_step_count(), step);
+        assert_eq!(state(&sim), states[step as usize], "step {step}");
+    }
+    assert!(!sim.reverse_step().unwrap(), "before the first step");
+}
+
+#[test]
+fn reverse_continue_finds_the_latest_match() {
+    let mut sim = machine();
+    let states = run_recording(&mut sim, 50);
+
+    // A counts up to 16, so the latest steps with A = 12 are before the
+    // history and the last checkpoint
+    let latest = |sim: &Simulator, value: u8| {
+        (0..sim.get_step_count()).rev().find(|step| states[*step as usize].1 == value)
+    };
+    let expected = latest(&sim, 12);
+    assert!(expected.is_some_and(|step| step < 40));
+    assert_eq!(sim.reverse_continue(|sim| sim.cpu_get_reg(7) == 12).unwrap(), expected);
+    assert_eq!(state(&sim), states[expected.unwrap() as usize]);
+
+    // From there, the previous value
+    let expected = latest(&sim, 5);
+    assert_eq!(sim.reverse_continue(|sim| sim.cpu_get_reg(7) == 5).unwrap(), expected);
+
+    assert_eq!(sim.reverse_continue(|sim| sim.cpu_get_reg(7) == 0xFF).unwrap(), None);
+}
+
+#[test]
+fn replay_stops_when_the_program_is_finished() {
+    let mut sim = machine();
+    sim.run(50);
+
+    // Every step replayed from a checkpoint is past the new limit, so the
+    // replays can't make any progress
+    sim.set_max_steps(Some(20));
+    assert_eq!(sim.reverse_continue(|sim| sim.cpu_get_reg(7) == 0xFF).unwrap(), None);
+    assert_eq!(sim.seek(30).unwrap(), 20);
+}
+
+#[test]
+fn oldest_checkpoints_are_dropped() {
+    let mut sim = Simulator::new();
+    sim.set_history_depth(0);
+    sim.set_checkpoint_interval(1);
+    sim.load_program(assemble_text(COUNTER_PROGRAM).unwrap());
+    let steps = MAX_CHECKPOINTS as u64 + 50;
+    sim.run(steps);
+
+    let oldest = steps - MAX_CHECKPOINTS as u64;
+    assert_eq!(sim.seek(oldest + 10).unwrap(), oldest + 10);
+    assert_ne!(sim.seek(oldest - 10).unwrap(), oldest - 10, "checkpoint kept");
+}
+
+#[test]
+fn replayed_steps_are_not_recorded() {
+    let mut sim = machine();
+    sim.set_coverage(Some(Coverage::new()));
+    sim.run(30);
+    let count = sim.coverage().unwrap().count_at(0xC002);
+
+    sim.seek(5).unwrap();
+    sim.seek(30).unwrap();
+    sim.reverse_continue(|sim| sim.cpu_get_reg(7) == 2).unwrap();
+    sim.value_history(Watch::Reg(7), 0, 30).unwrap();
+    assert_eq!(sim.coverage().unwrap().count_at(0xC002), count);
+
+    sim.run(10);
+    assert!(sim.coverage().unwrap().count_at(0xC002) > count, "steps run after the replays");
+}
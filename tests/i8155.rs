@@ -0,0 +1,138 @@
+// Checks the 8155 as wired on an SDK-85: ports from 20h, RAM from 2000h and
+// TIMER OUT on RST 7.5.
+
+mod common;
+
+use bobs8085::cpu::{ExecMode, InterruptSource};
+use bobs8085::devices::I8155;
+use bobs8085::snapshot::Snapshot;
+use bobs8085::{Simulator, StepOutcome};
+
+use common::machine;
+
+// Starts a single pulse of 100 counts and spins until its interrupt, whose
+// handler reads the status into A and halts
+const TIMER_PROGRAM: &str = "
+    MVI A, 08h
+    SIM
+    EI
+    MVI A, 64h
+    OUT 24h
+    MVI A, 80h
+    OUT 25h
+    MVI A, C0h
+    OUT 20h
+    loop: JMP loop
+";
+
+#[test]
+fn timer_interrupts_the_program() {
+    let mut steps = Vec::new();
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode, TIMER_PROGRAM);
+        let chip = I8155::new().with_timer_irq(InterruptSource::Rst7_5).attach(&mut sim, 0x20, 0x2000);
+        // RST 7.5 handler: IN 20h, HLT
+        for (i, byte) in [0xDB, 0x20, 0x76].into_iter().enumerate() {
+            sim.mem_set8(0x003C + i as u16, byte);
+        }
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+
+        assert_eq!(sim.get_pc(), 0x003F, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7) & 0x40, 0x40, "{mode:?}: TC flag read by the handler");
+        assert_eq!(sim.io_get8(0x20) & 0x40, 0, "{mode:?}: TC flag left set");
+        assert!(!chip.borrow().is_timer_running(), "{mode:?}: single pulse kept running");
+        assert!(chip.borrow().timer_out(), "{mode:?}");
+        steps.push((sim.get_step_count(), sim.get_cycles()));
+    }
+    assert_eq!(steps[0], steps[1], "steps and cycles of both modes");
+}
+
+#[test]
+fn square_wave_follows_the_cycles() {
+    // Continuous square wave of 10 counts
+    let program = "
+        MVI A, 0Ah
+        OUT 24h
+        MVI A, 40h
+        OUT 25h
+        MVI A, C0h
+        OUT 20h
+        loop: JMP loop
+    ";
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let chip = I8155::new().attach(&mut sim, 0x20, 0x2000);
+    sim.run(6);
+    assert!(chip.borrow().is_timer_running());
+    assert!(chip.borrow().timer_out(), "high for the first half");
+
+    // JMP takes 10 T-states, so every jump ends at the same point of the wave
+    let start = sim.io_get8(0x24);
+    sim.run(1);
+    assert_eq!(sim.io_get8(0x24), start);
+    assert_eq!(sim.io_get8(0x25), 0x40, "mode bits");
+}
+
+#[test]
+fn strobed_input_interrupts_the_program() {
+    // Port A strobed input with its interrupt enabled, INTR A on RST 5.5
+    let program = "
+        MVI A, 14h
+        OUT 20h
+        MVI A, 08h
+        SIM
+        EI
+        loop: JMP loop
+    ";
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode, program);
+        let chip = I8155::new().with_port_a_irq(InterruptSource::Rst5_5).attach(&mut sim, 0x20, 0x2000);
+        // RST 5.5 handler: IN 21h, HLT
+        for (i, byte) in [0xDB, 0x21, 0x76].into_iter().enumerate() {
+            sim.mem_set8(0x002C + i as u16, byte);
+        }
+        assert_eq!(sim.run(20), StepOutcome::Running, "{mode:?}");
+
+        chip.borrow_mut().set_port_a(0x5A);
+        assert_eq!(sim.io_get8(0x20) & 0x03, 0, "{mode:?}: empty before STB");
+        chip.borrow_mut().strobe_a();
+        chip.borrow_mut().set_port_a(0x00);
+        assert_eq!(sim.io_get8(0x20) & 0x07, 0x07, "{mode:?}: INTR, BF and INTE");
+        assert_eq!(chip.borrow().port_c() & 0x03, 0x03, "{mode:?}: INTR and BF on PC0-1");
+
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7), 0x5A, "{mode:?}: latched input");
+        assert_eq!(sim.io_get8(0x20) & 0x03, 0, "{mode:?}: INTR and BF left set");
+    }
+}
+
+#[test]
+fn ram_and_state_are_kept() {
+    let program = "
+        MVI A, 12h
+        STA 2000h
+        STA 20FFh
+        MVI A, 0Eh
+        OUT 20h
+        MVI A, 99h
+        OUT 22h
+        HLT
+    ";
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let chip = I8155::new().attach(&mut sim, 0x20, 0x2000);
+    sim.mem_set8(0x2100, 0x77);
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+    assert_eq!((sim.mem_get8(0x2000), sim.mem_get8(0x20FF)), (0x12, 0x12));
+    assert_eq!(sim.mem_get8(0x2100), 0x77, "RAM past the chip");
+    assert_eq!(chip.borrow().port_b(), 0x99);
+    let snapshot = Snapshot::from_bytes(&sim.snapshot().to_bytes()).unwrap();
+
+    // Undo HLT and OUT 22h
+    assert!(sim.step_back().unwrap() && sim.step_back().unwrap());
+    assert_eq!(chip.borrow().port_b(), 0x00, "output latch kept");
+
+    let mut other = Simulator::new();
+    let copy = I8155::new().attach(&mut other, 0x20, 0x2000);
+    other.restore_snapshot(&snapshot).unwrap();
+    assert_eq!(other.mem_get8(0x20FF), 0x12);
+    assert_eq!(copy.borrow().port_b(), 0x99);
+}
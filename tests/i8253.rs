@@ -0,0 +1,216 @@
+// Checks the 8253/8254 with its ports at 40h: the six modes, BCD counting,
+// the latch and readback commands, clocks from the CPU or outside, and OUT
+// driving the CPU and other counters.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod common;
+
+use bobs8085::cpu::{ExecMode, InterruptSource};
+use bobs8085::devices::I8253;
+use bobs8085::devices::i8253::{Clock, PitModel};
+use bobs8085::snapshot::Snapshot;
+use bobs8085::{Simulator, StepOutcome};
+
+use common::machine;
+
+// Runs a program that sets up counters clocked from outside
+fn external(program: &str) -> (Simulator, Rc<RefCell<I8253>>) {
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let mut chip = I8253::new().with_model(PitModel::I8254);
+    for counter in 0..3 {
+        chip = chip.with_clock(counter, Clock::External);
+    }
+    let chip = chip.attach(&mut sim, 0x40);
+    (sim, chip)
+}
+
+// OUT of `counter` after each of a number of CLK pulses
+fn wave(chip: &Rc<RefCell<I8253>>, counter: usize, pulses: usize) -> String {
+    (0..pulses)
+        .map(|_| {
+            chip.borrow_mut().clock(counter, 1);
+            if chip.borrow().out(counter) { 'H' } else { 'L' }
+        })
+        .collect()
+}
+
+#[test]
+fn terminal_count_interrupts_the_program() {
+    // Counter 0 in mode 0 with a count of 100, counting every other T-state
+    let program = "
+        MVI A, 08h
+        SIM
+        EI
+        MVI A, 30h
+        OUT 43h
+        MVI A, 64h
+        OUT 40h
+        MVI A, 00h
+        OUT 40h
+        loop: JMP loop
+    ";
+    let mut steps = Vec::new();
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode, program);
+        let chip = I8253::new().with_divider(2).with_out_irq(0, InterruptSource::Rst7_5).attach(&mut sim, 0x40);
+        // RST 7.5 handler: HLT
+        sim.mem_set8(0x003C, 0x76);
+        assert_eq!(sim.run(20), StepOutcome::Running, "{mode:?}");
+        assert!(!chip.borrow().out(0), "{mode:?}: OUT high before the count");
+
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.get_pc(), 0x003D, "{mode:?}");
+        assert!(chip.borrow().out(0), "{mode:?}");
+        steps.push((sim.get_step_count(), sim.get_cycles()));
+    }
+    assert_eq!(steps[0], steps[1], "steps and cycles of both modes");
+}
+
+#[test]
+fn periodic_waveforms() {
+    // Counter 0 in mode 2 with 4, counter 1 in mode 3 with 5 and counter 2
+    // in mode 4 with 2
+    let (mut sim, chip) = external(
+        "
+        MVI A, 14h
+        OUT 43h
+        MVI A, 04h
+        OUT 40h
+        MVI A, 56h
+        OUT 43h
+        MVI A, 05h
+        OUT 41h
+        MVI A, 98h
+        OUT 43h
+        MVI A, 02h
+        OUT 42h
+        HLT
+    ",
+    );
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+
+    assert_eq!(wave(&chip, 0, 9), "HHHLHHHLH", "rate generator");
+    assert_eq!(wave(&chip, 1, 11), "HHHLLHHHLLH", "square wave, odd count");
+    assert_eq!(wave(&chip, 2, 6), "HHLHHH", "software strobe");
+}
+
+#[test]
+fn gate_triggers_and_suspends() {
+    // Counter 0 in mode 1 with 3, counter 1 in mode 5 with 2 and counter 2
+    // in mode 0 with 3
+    let (mut sim, chip) = external(
+        "
+        MVI A, 12h
+        OUT 43h
+        MVI A, 03h
+        OUT 40h
+        MVI A, 5Ah
+        OUT 43h
+        MVI A, 02h
+        OUT 41h
+        MVI A, 90h
+        OUT 43h
+        MVI A, 03h
+        OUT 42h
+        HLT
+    ",
+    );
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+
+    assert_eq!(wave(&chip, 0, 2), "HH", "one-shot before its trigger");
+    chip.borrow_mut().set_gate(0, false);
+    chip.borrow_mut().set_gate(0, true);
+    assert_eq!(wave(&chip, 0, 5), "LLLHH", "one-shot");
+    chip.borrow_mut().set_gate(0, false);
+    chip.borrow_mut().set_gate(0, true);
+    assert_eq!(wave(&chip, 0, 2), "LL", "retriggered one-shot");
+
+    chip.borrow_mut().set_gate(1, false);
+    chip.borrow_mut().set_gate(1, true);
+    chip.borrow_mut().set_gate(1, false);
+    assert_eq!(wave(&chip, 1, 5), "HHLHH", "hardware strobe");
+
+    assert_eq!(wave(&chip, 2, 1), "L", "count loaded");
+    chip.borrow_mut().set_gate(2, false);
+    assert_eq!(wave(&chip, 2, 4), "LLLL", "suspended by GATE");
+    chip.borrow_mut().set_gate(2, true);
+    assert_eq!(wave(&chip, 2, 4), "LLHH", "terminal count");
+}
+
+#[test]
+fn bcd_latch_and_readback() {
+    // Counter 0 in mode 2 counting 1000 in BCD, latched after 4 pulses and
+    // read after 14, then its status read back
+    let (mut sim, chip) = external(
+        "
+        MVI A, 35h
+        OUT 43h
+        MVI A, 00h
+        OUT 40h
+        MVI A, 10h
+        OUT 40h
+        MVI A, 00h
+        OUT 43h
+        IN 40h
+        MOV B, A
+        IN 40h
+        MOV C, A
+        MVI A, E2h
+        OUT 43h
+        IN 40h
+        HLT
+    ",
+    );
+    assert_eq!(sim.run(6), StepOutcome::Running);
+    chip.borrow_mut().clock(0, 4);
+    assert_eq!(sim.run(2), StepOutcome::Running);
+    chip.borrow_mut().clock(0, 10);
+    assert_eq!(sim.io_get8(0x40), 0x97, "latch seen by a view");
+
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+    assert_eq!((sim.cpu_get_reg(0), sim.cpu_get_reg(1)), (0x97, 0x09), "latched 0997");
+    assert_eq!(sim.cpu_get_reg(7), 0xB5, "status: OUT high, LSB and MSB, mode 2, BCD");
+    assert_eq!(sim.io_get8(0x40), 0x87, "count of 0987 after the latch");
+}
+
+#[test]
+fn cascaded_counters_and_snapshot() {
+    // Counter 0 in mode 2 with 2 clocks counter 1, in mode 0 with 2
+    let program = "
+        MVI A, 14h
+        OUT 43h
+        MVI A, 02h
+        OUT 40h
+        MVI A, 50h
+        OUT 43h
+        MVI A, 02h
+        OUT 41h
+        HLT
+    ";
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let chip = I8253::new()
+        .with_clock(0, Clock::External)
+        .with_clock(1, Clock::Counter(0))
+        .with_out_irq(1, InterruptSource::Rst6_5)
+        .attach(&mut sim, 0x40);
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+
+    // OUT 0 rises on pulses 3, 5 and 7, which load counter 1 and count it down
+    assert_eq!(wave(&chip, 0, 6), "HLHLHL");
+    assert!(!chip.borrow().out(1));
+    let snapshot = Snapshot::from_bytes(&sim.snapshot().to_bytes()).unwrap();
+    chip.borrow_mut().clock(0, 1);
+    assert!(chip.borrow().out(1), "terminal count of counter 1");
+
+    let mut other = Simulator::new();
+    let copy = I8253::new()
+        .with_clock(0, Clock::External)
+        .with_clock(1, Clock::Counter(0))
+        .attach(&mut other, 0x40);
+    other.restore_snapshot(&snapshot).unwrap();
+    assert!(!copy.borrow().out(1));
+    copy.borrow_mut().clock(0, 1);
+    assert!(copy.borrow().out(1));
+}
@@ -0,0 +1,143 @@
+// Checks the 8255 with its ports at 80h: mode 0 ports and bit set/reset,
+// the mode 1 and 2 handshakes, and INTR driving the CPU.
+
+mod common;
+
+use bobs8085::cpu::{ExecMode, InterruptSource};
+use bobs8085::devices::I8255;
+use bobs8085::snapshot::Snapshot;
+use bobs8085::{Simulator, StepOutcome};
+
+use common::machine;
+
+// Handler of `vector`: IN port, HLT
+fn handler(sim: &mut Simulator, vector: u16, port: u8) {
+    for (i, byte) in [0xDB, port, 0x76].into_iter().enumerate() {
+        sim.mem_set8(vector + i as u16, byte);
+    }
+}
+
+#[test]
+fn switches_to_leds_and_bit_set_reset() {
+    // A in, B and C out; copies the switches to the LEDs and sets PC7 and PC2
+    let program = "
+        MVI A, 90h
+        OUT 83h
+        IN 80h
+        OUT 81h
+        MVI A, 0Fh
+        OUT 83h
+        MVI A, 05h
+        OUT 83h
+        MVI A, 0Ah
+        OUT 83h
+        HLT
+    ";
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let chip = I8255::new().attach(&mut sim, 0x80);
+    chip.borrow_mut().set_port_a(0x3C);
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+
+    assert_eq!(chip.borrow().port_b(), 0x3C);
+    assert_eq!(chip.borrow().port_c(), 0x84, "PC7 and PC2 set, PC5 cleared");
+    assert_eq!(sim.io_get8(0x82), 0x84);
+    let snapshot = Snapshot::from_bytes(&sim.snapshot().to_bytes()).unwrap();
+
+    // Undo everything after OUT 81h
+    for _ in 0..7 {
+        assert!(sim.step_back().unwrap());
+    }
+    assert_eq!((chip.borrow().port_b(), chip.borrow().port_c()), (0x3C, 0x00));
+
+    let mut other = Simulator::new();
+    let copy = I8255::new().attach(&mut other, 0x80);
+    other.restore_snapshot(&snapshot).unwrap();
+    assert_eq!((copy.borrow().port_b(), copy.borrow().port_c()), (0x3C, 0x84));
+}
+
+#[test]
+fn strobed_input_interrupts_the_program() {
+    // A as a mode 1 input with INTE A (PC4) set, INTR A on RST 6.5
+    let program = "
+        MVI A, B0h
+        OUT 83h
+        MVI A, 09h
+        OUT 83h
+        MVI A, 08h
+        SIM
+        EI
+        loop: JMP loop
+    ";
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode, program);
+        let chip = I8255::new().with_port_a_irq(InterruptSource::Rst6_5).attach(&mut sim, 0x80);
+        handler(&mut sim, 0x0034, 0x80);
+        assert_eq!(sim.run(20), StepOutcome::Running, "{mode:?}");
+        assert!(!chip.borrow().intr_a(), "{mode:?}");
+
+        chip.borrow_mut().set_port_a(0xA5);
+        chip.borrow_mut().strobe_a();
+        chip.borrow_mut().set_port_a(0x00);
+        assert!(chip.borrow().intr_a(), "{mode:?}");
+        assert_eq!(sim.io_get8(0x82) & 0x38, 0x38, "{mode:?}: INTR, INTE and IBF");
+
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7), 0xA5, "{mode:?}: latched input");
+        assert_eq!(sim.io_get8(0x82) & 0x38, 0x10, "{mode:?}: INTR and IBF left set");
+    }
+}
+
+#[test]
+fn acknowledged_output_interrupts_the_program() {
+    // B as a mode 1 output; writes a byte, then sets INTE B (PC2) with INTR B
+    // on RST 5.5, and the handler reads port C
+    let program = "
+        MVI A, 84h
+        OUT 83h
+        MVI A, 55h
+        OUT 81h
+        MVI A, 05h
+        OUT 83h
+        MVI A, 08h
+        SIM
+        EI
+        loop: JMP loop
+    ";
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode, program);
+        let chip = I8255::new().with_port_b_irq(InterruptSource::Rst5_5).attach(&mut sim, 0x80);
+        handler(&mut sim, 0x002C, 0x82);
+        assert_eq!(sim.run(20), StepOutcome::Running, "{mode:?}");
+        assert_eq!(chip.borrow().port_b(), 0x55, "{mode:?}");
+        assert_eq!(chip.borrow().port_c() & 0x07, 0x04, "{mode:?}: OBF and INTR low, ACK idle");
+
+        chip.borrow_mut().ack_b();
+        assert_eq!(sim.run(u64::MAX), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.cpu_get_reg(7) & 0x07, 0x07, "{mode:?}: INTR, OBF and INTE");
+    }
+}
+
+#[test]
+fn bidirectional_port() {
+    let program = "
+        MVI A, C0h
+        OUT 83h
+        MVI A, 42h
+        OUT 80h
+        IN 80h
+        HLT
+    ";
+    let mut sim = machine(ExecMode::Interpreter, program);
+    let chip = I8255::new().attach(&mut sim, 0x80);
+    assert_eq!(sim.run(4), StepOutcome::Running);
+    assert_eq!(chip.borrow().port_a(), 0x42);
+    assert_eq!(chip.borrow().port_c() & 0x88, 0x00, "OBF low, INTR A low");
+
+    chip.borrow_mut().ack_a();
+    chip.borrow_mut().set_port_a(0x17);
+    chip.borrow_mut().strobe_a();
+    assert_eq!(chip.borrow().port_c() & 0xA0, 0xA0, "OBF high, IBF");
+    assert_eq!(sim.run(u64::MAX), StepOutcome::Finished);
+    assert_eq!(sim.cpu_get_reg(7), 0x17);
+    assert_eq!(sim.io_get8(0x82) & 0xA8, 0x80, "IBF cleared, INTEs off");
+}
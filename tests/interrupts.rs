@@ -0,0 +1,128 @@
+// Checks the timing of the interrupt inputs: the instruction EI lets run
+// first, the state TRAP keeps for RIM, the RST 7.5 edge latch, and what the
+// CPU does with the byte an interrupt controller gives on INTA.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::bus::intc::FixedOpcode;
+use bobs8085::cpu::Interrupts;
+use bobs8085::{Simulator, StepOutcome};
+
+fn machine(program: &str) -> Simulator {
+    let mut sim = Simulator::new();
+    sim.load_program(assemble_text(program).unwrap());
+    sim
+}
+
+// Puts a handler at an interrupt vector
+fn handler(sim: &mut Simulator, vector: u16, bytes: &[u8]) {
+    for (i, byte) in bytes.iter().enumerate() {
+        sim.mem_set8(vector + i as u16, *byte);
+    }
+}
+
+#[test]
+fn ei_lets_one_instruction_run_first() {
+    let mut sim = machine("
+        LXI SP, 3000h
+        EI
+        NOP
+        NOP
+        HLT
+    ");
+    // RST 7 from the default controller, handler: HLT
+    handler(&mut sim, 0x0038, &[0x76]);
+    sim.set_pins(Interrupts { intr: true, ..Default::default() });
+
+    sim.run(2);
+    assert_eq!(sim.get_pc(), 0xC004);
+    sim.step();
+    assert_eq!(sim.get_pc(), 0xC005, "taken right after EI");
+    sim.step();
+    assert_eq!(sim.get_pc(), 0x0038);
+    assert_eq!(sim.mem_get8(0x2FFE), 0x05, "return to the second NOP");
+    assert_eq!(sim.mem_get8(0x2FFF), 0xC0);
+}
+
+#[test]
+fn trap_keeps_the_interrupt_enable_for_rim() {
+    // TRAP handler: RIM, MOV B, A, RIM, HLT
+    let rim_twice = [0x20, 0x47, 0x20, 0x76];
+    for (enable, ie) in [("EI", 0x08), ("DI", 0x00)] {
+        let mut sim = machine(&format!("
+            LXI SP, 3000h
+            {enable}
+            NOP
+            loop: JMP loop
+        "));
+        handler(&mut sim, 0x0024, &rim_twice);
+        sim.run(3);
+        // The pin stays high, TRAP fires once on its rising edge
+        sim.set_pins(Interrupts { trap: true, ..Default::default() });
+
+        assert_eq!(sim.run(100), StepOutcome::Finished, "{enable}");
+        assert_eq!(sim.cpu_get_reg(0) & 0x08, ie, "{enable}: first RIM");
+        assert_eq!(sim.cpu_get_reg(7) & 0x08, 0x00, "{enable}: second RIM");
+    }
+}
+
+#[test]
+fn rst7_5_latches_its_rising_edge() {
+    let mut sim = machine("
+        LXI SP, 3000h
+        NOP
+        RIM
+        MOV B, A
+        MVI A, 10h
+        SIM
+        RIM
+        MOV C, A
+        EI
+        NOP
+        loop: JMP loop
+    ");
+    // RST 7.5 handler: HLT
+    handler(&mut sim, 0x003C, &[0x76]);
+    let pulse = |sim: &mut Simulator| {
+        sim.set_pins(Interrupts { rst7_5: true, ..Default::default() });
+        sim.step();
+        sim.set_pins(Interrupts::default());
+    };
+
+    // A pulse while interrupts are disabled stays latched after the pin drops
+    sim.step();
+    pulse(&mut sim);
+    sim.run(2);
+    assert_eq!(sim.cpu_get_reg(0) & 0x40, 0x40, "pending before SIM");
+    sim.run(4);
+    assert_eq!(sim.cpu_get_reg(1) & 0x40, 0x00, "pending after SIM R7.5");
+
+    // A held pin is a single edge, and the latch outlives it
+    sim.set_pins(Interrupts { rst7_5: true, ..Default::default() });
+    sim.run(3);
+    sim.set_pins(Interrupts::default());
+    assert_eq!(sim.run(3), StepOutcome::Finished);
+    assert_eq!(sim.get_pc(), 0x003D);
+    assert!(!sim.get_pending_int().rst7_5, "latch left set");
+}
+
+#[test]
+fn other_opcodes_on_inta_run_in_place() {
+    // INR A jammed in on INTA, then MVI A, which has no operand to take
+    for (opcode, a) in [(0x3C, 0x01), (0x3E, 0x00)] {
+        let mut sim = machine("
+            LXI SP, 3000h
+            MVI A, 00h
+            EI
+            NOP
+            NOP
+            HLT
+        ");
+        sim.set_interrupt_controller(Box::new(FixedOpcode(opcode)));
+        sim.set_pins(Interrupts { intr: true, ..Default::default() });
+
+        assert_eq!(sim.run(100), StepOutcome::Finished, "{opcode:02X}");
+        assert_eq!(sim.cpu_get_reg(7), a, "{opcode:02X}");
+        assert_eq!(sim.get_sp(), 0x3000, "{opcode:02X}: pushed PC");
+        assert_eq!(sim.get_pc(), 0xC009, "{opcode:02X}");
+    }
+}
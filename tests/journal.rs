@@ -0,0 +1,144 @@
+// Checks that stepping back undoes what a step wrote to memory, to IO ports
+// and to devices, that stepping forward again redoes it, and that a step only
+// saves the devices it reaches.
+
+use std::{cell::Cell, rc::Rc};
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::bus::device::IoDevice;
+use bobs8085::devices::I8155;
+use bobs8085::snapshot::{Reader, SnapshotError, Writer};
+use bobs8085::Simulator;
+
+const PORT: u8 = 0x50;
+
+// Output latch on a port, counting how often its state is saved
+#[derive(Debug)]
+struct Latch {
+    value: u8,
+    saves: Rc<Cell<u32>>,
+}
+
+impl IoDevice for Latch {
+    fn read(&mut self, _port: u16) -> u8 {
+        self.value
+    }
+
+    fn peek(&self, _port: u16) -> u8 {
+        self.value
+    }
+
+    fn write(&mut self, _port: u16, value: u8) {
+        self.value = value;
+    }
+
+    fn is_idle(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        self.saves.set(self.saves.get() + 1);
+        w.u8(self.value);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), SnapshotError> {
+        self.value = r.u8()?;
+        Ok(())
+    }
+}
+
+fn machine(program: &str) -> Simulator {
+    let mut sim = Simulator::new();
+    // Checkpoints save every device, which would hide what the steps save
+    sim.set_checkpoint_interval(0);
+    sim.load_program(assemble_text(program).unwrap());
+    sim
+}
+
+// Memory, the stack, a plain port and A
+fn state(sim: &Simulator) -> (u8, u8, u8, u8, u16) {
+    (sim.mem_get8(0x2000), sim.mem_get8(0x2FFF), sim.io_get8(0x10), sim.cpu_get_reg(7), sim.get_sp())
+}
+
+#[test]
+fn memory_and_io_are_undone_and_redone() {
+    let mut sim = machine("
+        LXI SP, 3000h
+        MVI A, 11h
+        STA 2000h
+        OUT 10h
+        PUSH PSW
+        MVI A, 22h
+        STA 2000h
+        OUT 10h
+        HLT
+    ");
+    let mut states = vec![state(&sim)];
+    for _ in 0..9 {
+        sim.step();
+        states.push(state(&sim));
+    }
+    assert_eq!(states[9], (0x22, 0x11, 0x22, 0x22, 0x2FFE));
+
+    for step in (0..9).rev() {
+        assert!(sim.step_back().unwrap());
+        assert_eq!(state(&sim), states[step], "undo of step {}", step + 1);
+    }
+    assert!(!sim.step_back().unwrap());
+
+    for (step, expected) in states.iter().enumerate().skip(1) {
+        sim.step();
+        assert_eq!(state(&sim), *expected, "redo of step {step}");
+    }
+}
+
+#[test]
+fn only_the_devices_a_step_reaches_are_saved() {
+    let mut sim = machine("
+        MVI A, 05h
+        OUT 50h
+        MVI A, 07h
+        NOP
+        NOP
+        OUT 50h
+        HLT
+    ");
+    let saves = Rc::new(Cell::new(0));
+    sim.add_io_device(PORT, PORT, Box::new(Latch { value: 0, saves: saves.clone() }));
+    sim.run(u64::MAX);
+    assert_eq!(sim.io_get8(PORT), 0x07);
+    assert_eq!(saves.get(), 2, "saved by the steps without OUT");
+
+    // Undo HLT and the second OUT, then redo it
+    assert!(sim.step_back().unwrap() && sim.step_back().unwrap());
+    assert_eq!(sim.io_get8(PORT), 0x05);
+    sim.step();
+    assert_eq!(sim.io_get8(PORT), 0x07);
+
+    while sim.step_back().unwrap() {}
+    assert_eq!(sim.io_get8(PORT), 0x00);
+}
+
+#[test]
+fn device_memory_is_undone() {
+    // The 8155 RAM answers in memory space, away from the chip's ports
+    let mut sim = machine("
+        MVI A, 12h
+        STA 2000h
+        MVI A, 34h
+        STA 2000h
+        HLT
+    ");
+    let chip = I8155::new().attach(&mut sim, 0x20, 0x2000);
+    sim.run(u64::MAX);
+    assert_eq!(sim.mem_get8(0x2000), 0x34);
+
+    assert!(sim.step_back().unwrap() && sim.step_back().unwrap());
+    assert_eq!(sim.mem_get8(0x2000), 0x12);
+    sim.step();
+    assert_eq!(sim.mem_get8(0x2000), 0x34);
+    assert!(!chip.borrow().is_timer_running());
+
+    while sim.step_back().unwrap() {}
+    assert_eq!(sim.mem_get8(0x2000), 0x00);
+}
@@ -0,0 +1,43 @@
+// Checks that the memory map decodes ROM, mirrors and 16-bit accesses at the
+// top of memory as a board would.
+
+mod common;
+
+use bobs8085::bus::map::{MemFaultKind, MemoryMap};
+use bobs8085::cpu::ExecMode;
+use bobs8085::{Simulator, StepOutcome};
+
+const PROGRAM: &str = "
+    LXI H, 1234h
+    SHLD FFFFh
+    MVI A, 55h
+    STA 2105h
+    LDA 2005h
+    STA 1000h
+    HLT
+";
+
+fn machine(mode: ExecMode) -> Simulator {
+    let map: MemoryMap = "rom 1000-10FF fault\nmirror 2100-21FF 2000-20FF".parse().unwrap();
+    let mut sim = common::machine(mode, PROGRAM);
+    sim.set_history_depth(0);
+    sim.set_memory_map(Some(map));
+    sim
+}
+
+#[test]
+fn rom_mirrors_and_wrap_around() {
+    for mode in [ExecMode::Interpreter, ExecMode::Cached] {
+        let mut sim = machine(mode);
+
+        let outcome = sim.run(u64::MAX);
+        let StepOutcome::MemFault(fault) = outcome else { panic!("{mode:?}: expected a fault, got {outcome:?}") };
+        assert_eq!(fault.pc, 0xC00E, "{mode:?}");
+        assert_eq!(fault.addr, 0x1000, "{mode:?}");
+        assert_eq!(fault.kind, MemFaultKind::RomWrite { value: 0x55 }, "{mode:?}");
+        assert_eq!(sim.mem_get8(0x1000), 0x00, "{mode:?}: ROM written");
+
+        assert_eq!(sim.mem_get8(0x2005), 0x55, "{mode:?}: mirror not written through");
+        assert_eq!((sim.mem_get8(0xFFFF), sim.mem_get8(0x0000)), (0x34, 0x12), "{mode:?}: SHLD at FFFF");
+    }
+}
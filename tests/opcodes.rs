@@ -0,0 +1,96 @@
+// Checks that the assembler, the CPU dispatcher and the disassembler agree
+// with the shared opcode table.
+
+use bobs8085::assembler::{lexer::tokenize, parser::parse};
+use bobs8085::bus::{Bus, cycles::MachineCycle};
+use bobs8085::cpu::{CPU, CpuModel};
+use bobs8085::disassembler::disassemble_one;
+use bobs8085::opcodes::{self, Opcode};
+
+// Operand bytes used for every instruction, chosen to stay clear of the
+// program so nothing overwrites it
+const OPERANDS: [u8; 2] = [0x34, 0x12];
+
+fn encoding(op: u8, info: &Opcode) -> Vec<u8> {
+    let mut bytes = vec![op];
+    bytes.extend_from_slice(&OPERANDS[..info.length as usize - 1]);
+    bytes
+}
+
+fn is_control_transfer(info: &Opcode) -> bool {
+    matches!(
+        info.mnemonic,
+        "JMP" | "JNZ" | "JZ" | "JNC" | "JC" | "JPO" | "JPE" | "JP" | "JM"
+            | "CALL" | "CNZ" | "CZ" | "CNC" | "CC" | "CPO" | "CPE" | "CP" | "CM"
+            | "RET" | "RNZ" | "RZ" | "RNC" | "RC" | "RPO" | "RPE" | "RP" | "RM"
+            | "RST" | "PCHL" | "HLT"
+    )
+}
+
+#[test]
+fn assembler_and_disassembler_round_trip() {
+    for op in 0..=0xFFu8 {
+        let Some(info) = opcodes::get(op) else { continue };
+        let bytes = encoding(op, info);
+        let (text, length) = disassemble_one(&bytes);
+        assert_eq!(length, info.length, "length of {op:02X} ({text})");
+
+        let tokens = tokenize(&format!("{text}\n")).unwrap();
+        let assembled = parse(&tokens).unwrap_or_else(|e| panic!("{op:02X} ({text}): {e}"));
+        assert_eq!(assembled, bytes, "assembling \"{text}\"");
+    }
+}
+
+fn check_dispatcher(model: CpuModel) {
+    for op in 0..=0xFFu8 {
+        let Some(info) = opcodes::get(op) else { continue };
+        let mut bus = Bus::new();
+        for (i, byte) in encoding(op, info).iter().enumerate() {
+            bus.mem_set8(0xC000 + i as u16, *byte);
+        }
+        let mut cpu = CPU::with_model(model);
+        cpu.set_pc(0xC000);
+        bus.record_cycles(true);
+        cpu.execute(&mut bus);
+
+        let cycles = cpu.get_cycles();
+        let (t_states, t_states_taken) = info.timing(model);
+        assert!(
+            cycles == t_states as u64 || cycles == t_states_taken as u64,
+            "{model:?}: {op:02X} ({}) took {cycles} T-states",
+            info.mnemonic
+        );
+
+        let bus_cycles = bus.take_cycles();
+        assert_eq!(bus_cycles[0].kind, MachineCycle::OpcodeFetch, "{op:02X} ({})", info.mnemonic);
+        assert_eq!(
+            bus_cycles.iter().map(|c| c.t_states as u64).sum::<u64>(),
+            cycles,
+            "{model:?}: machine cycles of {op:02X} ({})",
+            info.mnemonic
+        );
+        if !is_control_transfer(info) {
+            assert_eq!(cpu.get_pc(), 0xC000 + info.length as u16, "{op:02X} ({})", info.mnemonic);
+        }
+    }
+}
+
+#[test]
+fn dispatcher_matches_table() {
+    check_dispatcher(CpuModel::I8085);
+}
+
+#[test]
+fn dispatcher_matches_8080_table() {
+    check_dispatcher(CpuModel::I8080);
+}
+
+#[test]
+fn mnemonics_resolve_to_their_base_opcode() {
+    for op in 0..=0xFFu8 {
+        let Some(info) = opcodes::get(op) else { continue };
+        let (base, found) = opcodes::find(info.mnemonic).unwrap();
+        assert_eq!(found.operands, info.operands, "{}", info.mnemonic);
+        assert!(base <= op, "{}", info.mnemonic);
+    }
+}
@@ -0,0 +1,110 @@
+// Checks how the profiler follows calls, restarts and returns, recursion
+// included, and the cycles it gives each address, routine and call stack.
+
+use bobs8085::profile::{Profiler, RoutineStats, Stats, StepRecord};
+
+// Feeds steps to a profiler, keeping the T-state count
+struct Steps {
+    profiler: Profiler,
+    cycles: u64,
+}
+
+impl Steps {
+    fn new() -> Steps {
+        Steps { profiler: Profiler::new(), cycles: 0 }
+    }
+
+    fn step(&mut self, pc: u16, opcode: u8, next_pc: u16, cycles: u64) {
+        self.cycles += cycles;
+        self.profiler.record(&StepRecord {
+            pc,
+            opcode,
+            next_pc,
+            cycles,
+            end_cycles: self.cycles,
+            executed: true,
+            interrupted: false,
+        });
+    }
+
+    fn routine(&self, addr: u16) -> RoutineStats {
+        self.profiler.routine_stats().into_iter().find(|(routine, _)| *routine == addr).unwrap().1
+    }
+}
+
+const NOP: u8 = 0x00;
+const CALL: u8 = 0xCD;
+const CZ: u8 = 0xCC;
+const RET: u8 = 0xC9;
+const RST1: u8 = 0xCF;
+const RST7: u8 = 0xFF;
+const HLT: u8 = 0x76;
+
+// Root at 0100 calls 0200, which calls itself once, then restarts to 0008
+// and to 0038 where it halts
+fn recursion_and_restarts() -> Steps {
+    let mut steps = Steps::new();
+    steps.step(0x0100, NOP, 0x0101, 4);
+    steps.step(0x0101, CALL, 0x0200, 18);
+    steps.step(0x0200, NOP, 0x0201, 4);
+    steps.step(0x0201, CZ, 0x0200, 18);
+    steps.step(0x0200, NOP, 0x0201, 4);
+    steps.step(0x0201, CZ, 0x0204, 9);
+    steps.step(0x0204, RET, 0x0204, 10);
+    steps.step(0x0204, RET, 0x0104, 10);
+    steps.step(0x0104, RST1, 0x0008, 12);
+    steps.step(0x0008, RET, 0x0105, 10);
+    steps.step(0x0105, RST7, 0x0038, 12);
+    steps.step(0x0038, HLT, 0x0038, 5);
+    steps
+}
+
+#[test]
+fn recursion_is_counted_once_in_inclusive_cycles() {
+    let steps = recursion_and_restarts();
+    assert_eq!(steps.cycles, 116);
+
+    // The root and 0038 are still running, counted up to now
+    assert_eq!(steps.routine(0x0100), RoutineStats { calls: 1, inclusive: 116, exclusive: 46 });
+    assert_eq!(steps.routine(0x0200), RoutineStats { calls: 2, inclusive: 55, exclusive: 55 });
+    assert_eq!(steps.routine(0x0008), RoutineStats { calls: 1, inclusive: 10, exclusive: 10 });
+    assert_eq!(steps.routine(0x0038), RoutineStats { calls: 1, inclusive: 5, exclusive: 5 });
+    let routines = steps.profiler.routine_stats();
+    assert_eq!(routines.len(), 4);
+    assert_eq!(routines.iter().map(|(_, stats)| stats.exclusive).sum::<u64>(), 116);
+    assert_eq!(routines[0].0, 0x0100, "sorted by inclusive cycles");
+
+    let spots = steps.profiler.hot_spots();
+    assert_eq!(spots[0], (0x0201, Stats { count: 2, cycles: 27 }));
+    assert!(spots.contains(&(0x0204, Stats { count: 2, cycles: 20 })));
+}
+
+#[test]
+fn cycles_per_call_stack_are_folded() {
+    let steps = recursion_and_restarts();
+    let mut out = Vec::new();
+    steps.profiler.write_folded(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+        0100 46\n\
+        0100;0008 10\n\
+        0100;0038 5\n\
+        0100;0200 32\n\
+        0100;0200;0200 23\n");
+}
+
+#[test]
+fn unmatched_returns_leave_the_innermost_frame() {
+    let mut steps = Steps::new();
+    // A return at the root has nothing to leave
+    steps.step(0x0100, RET, 0x0300, 10);
+    steps.step(0x0300, CALL, 0x0400, 18);
+    steps.step(0x0400, CALL, 0x0500, 18);
+    // Lands on no return address, so only the innermost frame is left
+    steps.step(0x0500, RET, 0x0600, 10);
+    steps.step(0x0600, RET, 0x0303, 10);
+    steps.step(0x0303, HLT, 0x0303, 5);
+
+    assert_eq!(steps.routine(0x0500), RoutineStats { calls: 1, inclusive: 10, exclusive: 10 });
+    assert_eq!(steps.routine(0x0400), RoutineStats { calls: 1, inclusive: 38, exclusive: 28 });
+    assert_eq!(steps.routine(0x0100), RoutineStats { calls: 1, inclusive: 71, exclusive: 33 });
+}
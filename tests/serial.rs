@@ -0,0 +1,144 @@
+// Checks the software UART with SOD looped back to SID at the default clock
+// and baud rate: the frames it sends, their timing, and what it decodes.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::serial::{DEFAULT_BAUD, DEFAULT_CLOCK_HZ, SoftUart};
+use bobs8085::Simulator;
+
+const BIT: u64 = DEFAULT_CLOCK_HZ / DEFAULT_BAUD as u64;
+
+// Runs the UART until a cycle, updating it every `step` cycles with SOD
+// wired to SID, and returns the cycles at which SID changed
+fn loopback(uart: &mut SoftUart, until: u64, step: usize) -> Vec<u64> {
+    let mut sid = true;
+    let mut edges = Vec::new();
+    for cycles in (0..until).step_by(step) {
+        let level = uart.update(cycles, sid);
+        if level != sid {
+            edges.push(cycles);
+        }
+        sid = level;
+    }
+    edges
+}
+
+// Holds SOD at each level for a bit time, from a cycle on
+fn drive(uart: &mut SoftUart, start: u64, levels: &[bool]) {
+    for (bit, level) in levels.iter().enumerate() {
+        for cycles in 0..BIT {
+            uart.update(start + bit as u64 * BIT + cycles, *level);
+        }
+    }
+}
+
+// Start bit, data LSB first and the stop bit
+fn frame(byte: u8, stop: bool) -> Vec<bool> {
+    let mut levels = vec![false];
+    levels.extend((0..8).map(|bit| byte & (1 << bit) != 0));
+    levels.push(stop);
+    levels
+}
+
+#[test]
+fn frames_are_sent_at_the_baud_rate() {
+    let mut uart = SoftUart::default();
+    assert_eq!(uart.get_bit_cycles(), 320);
+    uart.send(&[0x55, 0x0F]);
+    let edges = loopback(&mut uart, 25 * BIT, 1);
+
+    // 0x55 changes level on every bit, then 0x0F starts right after its stop
+    // bit and changes on its first and fifth data bits and its stop bit
+    let mut expected = (0..=9).map(|bit| bit * BIT).collect::<Vec<_>>();
+    expected.extend([10 * BIT, 11 * BIT, 15 * BIT, 19 * BIT]);
+    assert_eq!(edges, expected);
+    assert!(uart.is_idle());
+}
+
+#[test]
+fn looped_back_bytes_are_received() {
+    let bytes = [0x00, 0xFF, 0xA3, 0x5C, 0x80, 0x01];
+    // Every cycle, and at instruction-sized steps that don't divide a bit
+    for step in [1, 7, 18] {
+        let mut uart = SoftUart::default();
+        uart.send(&bytes);
+        loopback(&mut uart, (bytes.len() as u64 + 1) * 10 * BIT, step);
+        let received = std::iter::from_fn(|| uart.receive()).collect::<Vec<_>>();
+        assert_eq!(received, bytes, "step {step}");
+        assert!(uart.is_idle(), "step {step}");
+    }
+}
+
+#[test]
+fn low_stop_bit_drops_the_byte() {
+    let mut uart = SoftUart::default();
+    drive(&mut uart, 0, &[true]);
+    drive(&mut uart, BIT, &frame(0x41, false));
+    // Back to idle, then a good frame
+    drive(&mut uart, 11 * BIT, &[true, true]);
+    drive(&mut uart, 13 * BIT, &frame(0x42, true));
+    drive(&mut uart, 23 * BIT, &[true]);
+
+    assert_eq!(uart.receive(), Some(0x42));
+    assert_eq!(uart.receive(), None);
+}
+
+// Sends 'A' on SOD at 15 T-states a bit: MVI and SIM take 11, a NOP the rest
+fn sending_machine() -> Simulator {
+    let levels = [true, true].into_iter().chain(frame(b'A', true)).chain([true]);
+    let program = levels
+        .map(|level| format!("MVI A, {}\nSIM\nNOP\n", if level { "C0h" } else { "40h" }))
+        .collect::<String>() + "HLT";
+    let mut sim = Simulator::new();
+    sim.set_serial(Some(SoftUart::new(1, 15)));
+    sim.load_program(assemble_text(&program).unwrap());
+    sim
+}
+
+#[test]
+fn stepping_back_rewinds_the_line() {
+    let mut sim = Simulator::new();
+    sim.set_serial(Some(SoftUart::default()));
+    sim.load_program(assemble_text("loop: RIM\nJMP loop").unwrap());
+    sim.run(5);
+    sim.serial().unwrap().send(b"A");
+    let mut sid = Vec::new();
+    for _ in 0..1000 {
+        sim.step();
+        sid.push(sim.get_sid());
+    }
+
+    // Back into the frame, then the same levels again
+    for _ in 0..990 {
+        assert!(sim.step_back().unwrap());
+    }
+    for (step, level) in sid.iter().enumerate().skip(10) {
+        sim.step();
+        assert_eq!(sim.get_sid(), *level, "step {step}");
+    }
+
+    // Back to before the byte was taken, which sends it again
+    while sim.step_back().unwrap() {}
+    sim.run(6);
+    assert!(!sim.get_sid(), "start bit");
+}
+
+#[test]
+fn output_is_delivered_once() {
+    let mut sim = sending_machine();
+    sim.run(u64::MAX);
+    let steps = sim.get_step_count();
+    assert_eq!(sim.serial().unwrap().receive(), Some(b'A'));
+
+    // Running the same steps again, stepping or replaying, sends nothing new
+    while sim.step_back().unwrap() {}
+    sim.run(u64::MAX);
+    assert_eq!(sim.serial().unwrap().receive(), None);
+    sim.set_history_depth(3);
+    sim.set_checkpoint_interval(10);
+    sim.seek(0).unwrap();
+    sim.seek(steps).unwrap();
+    assert_eq!(sim.seek(4).unwrap(), 4);
+    sim.run(u64::MAX);
+    assert_eq!(sim.serial().unwrap().receive(), None);
+    assert_eq!(sim.get_step_count(), steps);
+}
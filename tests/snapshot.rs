@@ -0,0 +1,142 @@
+// Checks that a machine saved to a file and loaded back carries on exactly as
+// the original, and that damaged files are rejected instead of loaded.
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::serial::{DEFAULT_CLOCK_HZ, SoftUart, UartLine};
+use bobs8085::snapshot::{Snapshot, SnapshotError};
+use bobs8085::Simulator;
+
+const PROGRAM: &str = "
+    LXI SP, 3000h
+    MVI A, 01h
+    loop: OUT 10h
+    STA 2000h
+    PUSH PSW
+    POP PSW
+    RLC
+    JMP loop
+";
+
+fn machine() -> Simulator {
+    let mut sim = Simulator::new();
+    sim.set_serial(Some(SoftUart::default()));
+    sim.load_program(assemble_text(PROGRAM).unwrap());
+    sim
+}
+
+// Registers, cycles, memory and the last port written
+fn state(sim: &Simulator) -> (Vec<u8>, u16, u16, u64, u8, u8) {
+    let regs = (0..8).filter(|idx| *idx != 6).map(|idx| sim.cpu_get_reg(idx)).collect();
+    (regs, sim.get_pc(), sim.get_sp(), sim.get_cycles(), sim.mem_get8(0x2000), sim.io_get8(0x10))
+}
+
+// SID level after each of a number of steps
+fn sid_levels(sim: &mut Simulator, steps: usize) -> Vec<bool> {
+    (0..steps).map(|_| { sim.step(); sim.get_sid() }).collect()
+}
+
+// Replaces the payload of a section, keeping the other sections as they are
+fn replace_section(bytes: &[u8], tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut pos = 10;
+    loop {
+        let len = u32::from_le_bytes(bytes[pos+4..pos+8].try_into().unwrap()) as usize;
+        if &bytes[pos..pos+4] == tag {
+            let mut out = bytes[..pos+4].to_vec();
+            out.extend((payload.len() as u32).to_le_bytes());
+            out.extend(payload);
+            out.extend(&bytes[pos+8+len..]);
+            return out;
+        }
+        pos += 8 + len;
+    }
+}
+
+// Length-prefixed, as the snapshot writes byte strings
+fn prefixed(bytes: &[u8]) -> Vec<u8> {
+    let mut out = (bytes.len() as u32).to_le_bytes().to_vec();
+    out.extend(bytes);
+    out
+}
+
+#[test]
+fn saved_machine_carries_on_after_loading() {
+    let mut sim = machine();
+    sim.run(25);
+    let path = std::env::temp_dir().join(format!("bobs8085-snapshot-{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+    sim.snapshot().save(path).unwrap();
+    let loaded = Snapshot::load(path);
+    std::fs::remove_file(path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded.to_bytes(), sim.snapshot().to_bytes());
+
+    let mut other = Simulator::new();
+    other.restore_snapshot(&loaded).unwrap();
+    assert_eq!(state(&other), state(&sim));
+    assert!(other.serial().is_some());
+    let program = other.get_program().unwrap();
+    assert_eq!(program.labels.get("loop"), Some(&0xC005));
+    assert_eq!(program.lines, sim.get_program().unwrap().lines);
+
+    sim.run(40);
+    other.run(40);
+    assert_eq!(state(&other), state(&sim));
+}
+
+#[test]
+fn connected_uart_stays_after_loading() {
+    // Saved without serial, the UART connected before loading is kept idle
+    let mut sim = machine();
+    sim.set_serial(None);
+    sim.run(10);
+    let mut other = Simulator::new();
+    other.set_serial(Some(SoftUart::new(1200, DEFAULT_CLOCK_HZ)));
+    other.restore_snapshot(&sim.snapshot()).unwrap();
+    let uart = other.serial().unwrap();
+    assert_eq!(uart.get_bit_cycles(), DEFAULT_CLOCK_HZ / 1200);
+    assert_eq!(uart.get_line(), UartLine::default());
+
+    // Saved while sending "A" on SID, the frame carries on from the saved bit
+    // and then the connected UART sends what it has queued
+    let mut sim = machine();
+    sim.serial().unwrap().send(b"A");
+    sim.run(100);
+    let mut other = Simulator::new();
+    other.set_serial(Some(SoftUart::default()));
+    other.serial().unwrap().send(b"\x00");
+    other.restore_snapshot(&sim.snapshot()).unwrap();
+
+    let rest = sid_levels(&mut sim, 300);
+    assert!(rest.contains(&false) && rest.ends_with(&[true]), "the frame ends");
+    let end = rest.iter().rposition(|level| !level).unwrap() + 1;
+    let resumed = sid_levels(&mut other, 300);
+    assert_eq!(resumed[..end], rest[..end]);
+    assert!(resumed[end..].contains(&false), "the queued byte follows");
+}
+
+#[test]
+fn damaged_files_are_rejected() {
+    let bytes = machine().snapshot().to_bytes();
+    let invalid = |bytes: &[u8]| matches!(Snapshot::from_bytes(bytes), Err(SnapshotError::Invalid(_)));
+
+    // Memory and IO sections cut short
+    assert!(invalid(&replace_section(&bytes, b"MEM ", &prefixed(&[0; 0x100]))));
+    assert!(invalid(&replace_section(&bytes, b"IO  ", &prefixed(&[0; 0x10]))));
+    assert!(invalid(&replace_section(&bytes, b"IO  ", &prefixed(&[0; 0x101]))));
+
+    // A line past the end of the program's bytes: origin, one byte, one line
+    let mut program = 0xC000u16.to_le_bytes().to_vec();
+    program.extend(prefixed(&[0x00]));
+    program.extend(1u32.to_le_bytes());
+    program.extend(0xC001u16.to_le_bytes());
+    program.extend(1u32.to_le_bytes());
+    program.extend(0u32.to_le_bytes());
+    assert!(invalid(&replace_section(&bytes, b"PROG", &program)));
+
+    // The same line on the byte that is there loads
+    program[11..13].copy_from_slice(&0xC000u16.to_le_bytes());
+    let loaded = Snapshot::from_bytes(&replace_section(&bytes, b"PROG", &program)).unwrap();
+    assert_eq!(loaded.program.unwrap().code_addresses().collect::<Vec<_>>(), [0xC000]);
+
+    assert!(matches!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+}
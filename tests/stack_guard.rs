@@ -0,0 +1,122 @@
+// Checks the stack guard: pushes below the floor, pops above the initial SP,
+// stack writes onto the program, and pushes before the program loads SP.
+
+mod common;
+
+use bobs8085::cpu::ExecMode;
+use bobs8085::stack::{StackAccess, StackFault, StackFaultKind, StackGuard};
+use bobs8085::{Simulator, StepOutcome};
+
+use common::machine;
+
+const MODES: [ExecMode; 2] = [ExecMode::Interpreter, ExecMode::Cached];
+
+fn guarded(mode: ExecMode, program: &str, guard: StackGuard) -> Simulator {
+    let mut sim = machine(mode, program);
+    sim.set_stack_guard(Some(guard.with_fault(true)));
+    sim
+}
+
+fn fault(outcome: StepOutcome) -> StackFault {
+    let StepOutcome::StackFault(fault) = outcome else { panic!("expected a stack fault, got {outcome:?}") };
+    fault
+}
+
+#[test]
+fn push_below_the_floor() {
+    for mode in MODES {
+        let mut sim = guarded(mode, "
+            LXI SP, 3000h
+            loop: PUSH B
+            JMP loop
+        ", StackGuard::new().with_floor(0x2FF0));
+
+        let fault = fault(sim.run(1000));
+        assert_eq!(fault.kind, StackFaultKind::Overflow { floor: 0x2FF0 }, "{mode:?}");
+        assert_eq!((fault.pc, fault.opcode, fault.sp), (0xC003, Some(0xC5), 0x2FF0), "{mode:?}");
+        assert_eq!(fault.to_string(), "Stack overflow: PUSH at C003 with SP=2FF0 pushes below the floor 2FF0");
+        assert_eq!(sim.stack_guard().unwrap().get_top(), Some(0x3000), "{mode:?}");
+    }
+}
+
+#[test]
+fn pop_above_the_initial_sp() {
+    for mode in MODES {
+        let mut sim = guarded(mode, "
+            LXI SP, 3000h
+            PUSH B
+            POP B
+            POP B
+            HLT
+        ", StackGuard::new());
+
+        let fault = fault(sim.run(1000));
+        assert_eq!(fault.kind, StackFaultKind::Underflow { top: 0x3000 }, "{mode:?}");
+        assert_eq!((fault.pc, fault.sp), (0xC005, 0x3000), "{mode:?}");
+    }
+}
+
+#[test]
+fn stack_writes_onto_the_program() {
+    for mode in MODES {
+        // SP lands just above the program, which ends at C009
+        let mut sim = guarded(mode, "
+            LXI SP, C00Bh
+            CALL func
+            HLT
+            func: PUSH B
+            NOP
+        ", StackGuard::new());
+
+        let fault = fault(sim.run(1000));
+        assert_eq!(fault.kind, StackFaultKind::Collision { addr: 0xC008 }, "{mode:?}");
+        assert_eq!((fault.pc, fault.sp), (0xC007, 0xC009), "{mode:?}");
+    }
+
+    // Extra regions are protected too
+    let mut guard = StackGuard::new().with_top(0x2000).protect(0x1FF0, 0x1FF8);
+    let push = |guard: &mut StackGuard, sp| guard.check(StackAccess::Push, 0, Some(0xC5), sp, None).map(|fault| fault.kind);
+    assert_eq!(push(&mut guard, 0x2000), None);
+    assert_eq!(push(&mut guard, 0x1FFA), Some(StackFaultKind::Collision { addr: 0x1FF8 }));
+}
+
+#[test]
+fn push_before_sp_is_loaded() {
+    for mode in MODES {
+        // No LXI SP, so the stack starts at the reset value
+        let program = "
+            CALL func
+            HLT
+            func: PUSH B
+            POP B
+            RET
+        ";
+        let mut sim = guarded(mode, program, StackGuard::new().with_floor(0xFF00));
+        let fault = fault(sim.run(1000));
+        assert_eq!(fault.kind, StackFaultKind::Unloaded, "{mode:?}");
+        assert_eq!((fault.pc, fault.opcode, fault.sp), (0xC000, Some(0xCD), 0x0000), "{mode:?}");
+        assert_eq!(fault.to_string(), "Stack overflow: CALL at C000 with SP=0000 pushes before SP was loaded by LXI SP or SPHL");
+
+        // As a warning the stack carries on from the top of memory
+        let mut sim = machine(mode, program);
+        sim.set_stack_guard(Some(StackGuard::new().with_floor(0xFF00)));
+        sim.step();
+        assert_eq!(sim.get_stack_fault().map(|fault| fault.kind), Some(StackFaultKind::Unloaded), "{mode:?}");
+        assert_eq!(sim.run(1000), StepOutcome::Finished, "{mode:?}");
+        assert_eq!(sim.stack_guard().unwrap().get_top(), Some(0x0000), "{mode:?}");
+        assert_eq!((sim.mem_get8(0xFFFF), sim.mem_get8(0xFFFE)), (0xC0, 0x03), "{mode:?}");
+        assert_eq!(sim.get_sp(), 0x0000, "{mode:?}");
+    }
+
+    // Once reported, popping past it is still an underflow
+    let mut guard = StackGuard::new();
+    let push = guard.check(StackAccess::Push, 0xC000, Some(0xC5), 0x0000, None);
+    assert_eq!(push.map(|fault| fault.kind), Some(StackFaultKind::Unloaded));
+    assert_eq!(guard.check(StackAccess::Push, 0xC001, Some(0xC5), 0xFFFE, None), None);
+    let fault = guard.check(StackAccess::Pop, 0xC002, Some(0xC1), 0x0000, None);
+    assert_eq!(fault.map(|fault| fault.kind), Some(StackFaultKind::Underflow { top: 0x0000 }));
+
+    // A configured top needs no LXI SP
+    let mut guard = StackGuard::new().with_top(0x0000);
+    assert_eq!(guard.check(StackAccess::Push, 0xC000, Some(0xC5), 0x0000, None), None);
+}
@@ -0,0 +1,63 @@
+// Checks the schedule the throttle keeps, at set points in time instead of
+// sleeping, and that out-of-range speeds can't break it.
+
+use std::time::Duration;
+
+use bobs8085::serial::DEFAULT_CLOCK_HZ;
+use bobs8085::throttle::{MAX_SPEED, MIN_SPEED, Throttle, is_valid_speed};
+use bobs8085::Simulator;
+
+#[test]
+fn cycles_are_due_at_the_clock_rate() {
+    for speed in [1.0, 0.5, 4.0] {
+        let throttle = Throttle::new(DEFAULT_CLOCK_HZ).with_speed(speed);
+        let origin = throttle.due(0);
+        let second = Duration::from_secs_f64(1.0 / speed);
+        assert_eq!(throttle.due(DEFAULT_CLOCK_HZ) - origin, second, "{speed}");
+
+        // Behind when the cycles come late, ahead when they come early
+        let now = origin + second;
+        assert_eq!(throttle.drift_at(DEFAULT_CLOCK_HZ, now), 0.0, "{speed}");
+        let late = throttle.drift_at(DEFAULT_CLOCK_HZ / 2, now);
+        assert!((late - 0.5 / speed).abs() < 1e-6, "{speed}: {late}");
+        let early = throttle.drift_at(DEFAULT_CLOCK_HZ * 2, now);
+        assert!((early + 1.0 / speed).abs() < 1e-6, "{speed}: {early}");
+    }
+}
+
+#[test]
+fn bursts_catch_up_and_give_up_when_too_far_behind() {
+    let mut throttle = Throttle::new(1000);
+    let origin = throttle.due(0);
+
+    // 10 ms in at 1 kHz, 10 cycles are due whatever ran so far
+    let now = origin + Duration::from_millis(10);
+    assert_eq!(throttle.due_cycles_at(0, now), 10);
+    assert_eq!(throttle.due_cycles_at(10, now), 10);
+    assert_eq!(throttle.get_lost(), Duration::ZERO);
+
+    // A second without running starts the schedule over from there
+    let now = origin + Duration::from_secs(1);
+    assert_eq!(throttle.due_cycles_at(10, now), 10);
+    assert_eq!(throttle.get_lost(), Duration::from_millis(990));
+    assert_eq!(throttle.due(10), now);
+    assert_eq!(throttle.due_cycles_at(10, now + Duration::from_millis(5)), 15);
+}
+
+#[test]
+fn speeds_are_kept_in_range() {
+    for (speed, expected) in [(0.0, MIN_SPEED), (1e-300, MIN_SPEED), (-2.0, MIN_SPEED), (1e300, MAX_SPEED), (f64::NAN, 1.0)] {
+        let throttle = Throttle::new(1).with_speed(speed);
+        assert_eq!(throttle.get_speed(), expected, "{speed}");
+        assert!(!is_valid_speed(speed), "{speed}");
+        // Far enough ahead to overflow without the limit
+        assert!(throttle.due(u64::MAX) > throttle.due(0), "{speed}");
+        throttle.drift(u64::MAX);
+    }
+    assert!(is_valid_speed(1.0) && is_valid_speed(MIN_SPEED) && is_valid_speed(MAX_SPEED));
+
+    let mut sim = Simulator::new();
+    sim.set_speed(Some(1e-300));
+    assert_eq!(sim.throttle().unwrap().get_speed(), MIN_SPEED);
+    assert!(sim.get_drift().is_some());
+}
@@ -0,0 +1,122 @@
+// Checks the trace in each format, limited to an address range or a number
+// of lines, and that write errors come out of `finish`.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::trace::{CSV_HEADER, TraceFormat, Tracer};
+use bobs8085::Simulator;
+
+const PROGRAM: &str = "
+    LXI SP, 3000h
+    MVI A, 12h
+    STA 2000h
+    OUT 10h
+    PUSH PSW
+    HLT
+";
+
+// Output shared with the test, as the tracer owns its writer
+#[derive(Debug, Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Takes a number of bytes, then fails
+struct Failing(usize);
+
+impl Write for Failing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0 == 0 {
+            return Err(io::Error::other("disk full"));
+        }
+        let len = buf.len().min(self.0);
+        self.0 -= len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs the program with a tracer and returns the trace lines and what
+// `finish` gave
+fn trace(configure: impl FnOnce(Tracer) -> Tracer, format: TraceFormat) -> (Vec<String>, u64) {
+    let out = Shared::default();
+    let mut sim = Simulator::new();
+    sim.load_program(assemble_text(PROGRAM).unwrap());
+    sim.set_tracer(Some(configure(Tracer::new(Box::new(out.clone()), format))));
+    sim.run(u64::MAX);
+    let lines = sim.take_tracer().unwrap().finish().unwrap();
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    (text.lines().map(str::to_string).collect(), lines)
+}
+
+#[test]
+fn text_trace_of_an_address_range() {
+    let (lines, count) = trace(|tracer| tracer.with_range(0xC008, 0xC003), TraceFormat::Text);
+    assert_eq!(count, 3);
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].contains("C003  3E12"), "{}", lines[0]);
+    assert!(lines[0].contains("A=00"), "state before the instruction: {}", lines[0]);
+    assert!(lines[1].ends_with(" [2000]=12"), "{}", lines[1]);
+    assert!(lines[2].ends_with(" (10)=12"), "{}", lines[2]);
+}
+
+#[test]
+fn csv_trace_up_to_a_number_of_lines() {
+    let (lines, count) = trace(|tracer| tracer.with_max_lines(5), TraceFormat::Csv);
+    assert_eq!(count, 5);
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], CSV_HEADER);
+    // The mnemonic is quoted, it can have a comma
+    for line in &lines[1..] {
+        let (_, rest) = line.rsplit_once("\",").unwrap();
+        assert_eq!(rest.split(',').count(), 11, "{line}");
+    }
+    assert!(lines[1].starts_with("0,C000,310030,"), "{}", lines[1]);
+    assert!(lines[3].ends_with(",2000=12,"), "{}", lines[3]);
+    // PUSH PSW writes A, then the flags
+    assert!(lines[5].ends_with(",2FFF=12;2FFE=00,"), "{}", lines[5]);
+}
+
+#[test]
+fn json_trace_of_every_instruction() {
+    let (lines, count) = trace(|tracer| tracer, TraceFormat::Json);
+    assert_eq!(count, 6);
+    assert_eq!(lines.len(), 6);
+    assert!(lines.iter().all(|line| line.starts_with("{\"cycles\":") && line.ends_with('}')));
+    assert!(lines[1].contains("\"pc\":49155,\"bytes\":[62,18],"), "{}", lines[1]);
+    assert!(lines[2].contains("\"memory\":[[8192,18]],\"io\":[]"), "{}", lines[2]);
+    assert!(lines[3].contains("\"memory\":[],\"io\":[[16,18]]"), "{}", lines[3]);
+}
+
+#[test]
+fn write_errors_stop_the_trace_and_are_reported() {
+    for format in [TraceFormat::Text, TraceFormat::Csv, TraceFormat::Json] {
+        // Fails once the buffer is flushed past the first few lines
+        let mut tracer = Tracer::new(Box::new(Failing(200)), format);
+        let mut sim = Simulator::new();
+        sim.load_program(assemble_text("loop: JMP loop").unwrap());
+        sim.set_tracer(Some(tracer));
+        sim.run(1000);
+        tracer = sim.take_tracer().unwrap();
+        assert!(!tracer.wants(0xC000), "{format:?}: still tracing");
+        assert!(tracer.get_lines() < 1000, "{format:?}");
+        let err = tracer.finish().unwrap_err();
+        assert_eq!(err.to_string(), "disk full", "{format:?}");
+    }
+}
@@ -0,0 +1,155 @@
+// Checks reading logs from other tools by header names or column indexes, in
+// either radix and with each form of flags, and comparing them against the
+// simulator's own traces.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::trace::diff::{ColumnMap, Field, TraceDiff, diff, load_trace, parse_delimited, parse_json_lines, parse_text};
+use bobs8085::trace::{TraceFormat, Tracer};
+use bobs8085::Simulator;
+
+// Output shared with the test, as the tracer owns its writer
+#[derive(Debug, Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Trace of a short program written by the tracer
+fn own_trace(format: TraceFormat) -> String {
+    let out = Shared::default();
+    let mut sim = Simulator::new();
+    sim.load_program(assemble_text("
+        LXI SP, 3000h
+        MVI A, 80h
+        ADD A
+        STA 2000h
+        HLT
+    ").unwrap());
+    sim.set_tracer(Some(Tracer::new(Box::new(out.clone()), format)));
+    sim.run(u64::MAX);
+    sim.take_tracer().unwrap().finish().unwrap();
+    String::from_utf8(out.0.borrow().clone()).unwrap()
+}
+
+#[test]
+fn header_names_and_column_indexes_agree() {
+    let log = "\
+        # exported by another simulator\n\
+        PC;A;F;SP\n\
+        C000;00;00;0000\n\
+        \n\
+        C003;3E;C4;FFFF\n";
+    let by_name = ColumnMap::default().with_delimiter(';').with_skip(1).with_columns("pc=pc,a=A,flags=F,sp=Sp").unwrap();
+    let by_index = ColumnMap::default().with_delimiter(';').with_skip(2).with_columns("pc=0,a=1,flags=2,sp=3").unwrap();
+    let named = parse_delimited(log, &by_name).unwrap();
+    let indexed = parse_delimited(log, &by_index).unwrap();
+
+    assert_eq!(named.len(), 2, "blank lines are skipped");
+    assert_eq!(named[1].line, 5);
+    assert_eq!(named[1].values[&Field::Flags], "SZ-P-");
+    assert_eq!(named.iter().map(|step| &step.values).collect::<Vec<_>>(), indexed.iter().map(|step| &step.values).collect::<Vec<_>>());
+
+    let missing = ColumnMap::default().with_delimiter(';').with_skip(1).with_columns("pc=pc,b=B").unwrap();
+    assert_eq!(parse_delimited(log, &missing).unwrap_err(), "Column \"B\" not found in the header");
+    assert!(ColumnMap::default().with_columns("pc").is_err());
+    assert!(ColumnMap::default().with_columns("ix=3").is_err());
+}
+
+#[test]
+fn numbers_are_read_in_the_radix_and_flags_in_each_form() {
+    let columns = "pc=0,a=1,flags=2,memory=3";
+    let hex = ColumnMap::default().with_delimiter(' ').with_columns(columns).unwrap();
+    let decimal = ColumnMap::default().with_delimiter(' ').with_columns(columns).unwrap().with_radix(10).unwrap();
+
+    // The flag byte, SZAPC letters and the 8-position form of the same flags
+    let hex_log = "0xC000 3Eh C5 2000=3E\nC001 0F SZ-PC 2000=3E;2001=0F\nC002 FF SZ---P-C 2002=FF";
+    let decimal_log = "49152 62 197 8192=62\n49153 15 SZ-PC 8192=62;8193=15\n49154 255 SZ---P-C 8194=255";
+    let hex = parse_delimited(hex_log, &hex).unwrap();
+    let decimal = parse_delimited(decimal_log, &decimal).unwrap();
+    assert!(matches!(diff(&hex, &decimal), TraceDiff::Same { steps: 3 }));
+    for step in &hex {
+        assert_eq!(step.values[&Field::Flags], "SZ-PC", "line {}", step.line);
+    }
+    assert_eq!(hex[0].values[&Field::Pc], "C000");
+    assert_eq!(hex[1].values[&Field::Memory], "2000=3E;2001=0F");
+
+    let bad = ColumnMap::default().with_columns("a=0").unwrap();
+    assert_eq!(parse_delimited("12\nGG", &bad).unwrap_err(), "line 2: Not a number: \"GG\"");
+
+    for radix in [0, 1, 37] {
+        assert_eq!(ColumnMap::default().with_radix(radix).unwrap_err(), format!("Not a valid radix: {radix} (expected 2 to 36)"));
+    }
+    assert!(ColumnMap::default().with_radix(2).is_ok());
+    assert!(ColumnMap::default().with_radix(36).is_ok());
+}
+
+#[test]
+fn own_traces_match_and_divergence_is_reported() {
+    let csv = parse_delimited(&own_trace(TraceFormat::Csv), &ColumnMap::default()).unwrap();
+    let json = parse_json_lines(&own_trace(TraceFormat::Json)).unwrap();
+    assert_eq!(csv.len(), 5);
+    assert!(matches!(diff(&csv, &json), TraceDiff::Same { steps: 5 }));
+
+    // A log of another simulator, with a wrong carry after ADD A
+    let other = "\
+        pc,a,f\n\
+        C000,00,00\n\
+        C003,00,00\n\
+        C005,80,00\n\
+        C006,00,44\n";
+    let map = ColumnMap::default().with_columns("pc=pc,a=a,flags=f").unwrap();
+    let other = parse_delimited(other, &map).unwrap();
+    let TraceDiff::Diverged(divergence) = diff(&csv, &other) else { panic!("no divergence") };
+    assert_eq!(divergence.step, 3);
+    assert_eq!(divergence.field, Field::Flags);
+    assert_eq!(divergence.left.values[&Field::Flags], "-Z-PC");
+    assert_eq!(divergence.right.values[&Field::Flags], "-Z-P-");
+    assert_eq!(divergence.right.line, 5);
+
+    // Agreeing as far as the shorter one goes
+    assert!(matches!(diff(&csv[..3], &other), TraceDiff::Truncated { steps: 3, left: 3, right: 4 }));
+}
+
+#[test]
+fn text_traces_are_compared() {
+    let text = own_trace(TraceFormat::Text);
+    let steps = parse_text(&text).unwrap();
+    let csv = parse_delimited(&own_trace(TraceFormat::Csv), &ColumnMap::default()).unwrap();
+    assert_eq!(steps.len(), 5);
+    assert!(matches!(diff(&steps, &csv), TraceDiff::Same { steps: 5 }));
+    assert_eq!(steps[3].values[&Field::Pc], "C006");
+    assert_eq!(steps[3].values[&Field::Flags], "-Z-PC");
+    assert_eq!(steps[3].values[&Field::Memory], "2000=00");
+    assert_eq!(steps[4].values[&Field::Memory], "");
+
+    // The default output of "run --trace", read back by its extension
+    let path = std::env::temp_dir().join(format!("bobs8085-trace-diff-{}.log", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(path, &text).unwrap();
+    let loaded = load_trace(path, TraceFormat::from_path(path), &ColumnMap::default()).unwrap();
+    assert!(matches!(diff(&loaded, &steps), TraceDiff::Same { steps: 5 }));
+
+    // A second text trace where STA wrote elsewhere
+    let other = text.replace("[2000]=00", "[2001]=00");
+    std::fs::write(path, &other).unwrap();
+    let other = load_trace(path, TraceFormat::Text, &ColumnMap::default()).unwrap();
+    std::fs::remove_file(path).unwrap();
+    let TraceDiff::Diverged(divergence) = diff(&steps, &other) else { panic!("no divergence") };
+    assert_eq!((divergence.step, divergence.field), (3, Field::Memory));
+    assert_eq!(divergence.right.values[&Field::Memory], "2001=00");
+
+    assert_eq!(parse_text("12  C0G0  00  NOP A=00").unwrap_err(), "line 1: Not a number: \"C0G0\"");
+}
@@ -0,0 +1,263 @@
+// Checks the VCD waveform of a short program: the header, the timing taken
+// from the clock, and the pins during each kind of machine cycle.
+
+use std::collections::HashMap;
+
+use bobs8085::assembler::assemble_text;
+use bobs8085::bus::cycles::{BusCycle, MachineCycle};
+use bobs8085::cpu::Interrupts;
+use bobs8085::vcd::write_vcd;
+use bobs8085::Simulator;
+
+// One T-state at 1 MHz, in the picoseconds of the timescale
+const T: u64 = 1_000_000;
+
+// Fetch, read, write, IO, six T-state fetch (PUSH), bus idle (DAD) and the
+// INTA cycle of the RST 7 the default controller gives after EI
+fn cycles() -> Vec<BusCycle> {
+    let mut sim = Simulator::new();
+    sim.load_program(assemble_text("
+        LXI SP, 3000h
+        MVI A, 5Ah
+        STA 2000h
+        IN 10h
+        OUT 20h
+        PUSH B
+        DAD B
+        EI
+        NOP
+        HLT
+    ").unwrap());
+    sim.mem_set8(0x0038, 0x76);
+    sim.record_bus_cycles(true);
+    sim.run(8);
+    sim.set_pins(Interrupts { intr: true, ..Default::default() });
+    sim.run(2);
+    sim.take_bus_cycles()
+}
+
+fn vcd(cycles: &[BusCycle], clock_hz: u64) -> String {
+    let mut out = Vec::new();
+    write_vcd(&mut out, cycles, clock_hz).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+// The value of every signal by name after each timestamp
+struct Wave {
+    states: Vec<(u64, HashMap<String, String>)>,
+}
+
+impl Wave {
+    fn parse(text: &str) -> Wave {
+        let mut names = HashMap::new();
+        let mut values = HashMap::new();
+        let mut states: Vec<(u64, HashMap<String, String>)> = Vec::new();
+        for line in text.lines() {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                ["$var", "wire", _, id, name, "$end"] => { names.insert(id.to_string(), name.to_string()); }
+                [time] if time.starts_with('#') => {
+                    if let Some((_, last)) = states.last_mut() {
+                        *last = values.clone();
+                    }
+                    states.push((time[1..].parse().unwrap(), HashMap::new()));
+                }
+                [vector, id] if vector.starts_with('b') => { values.insert(names[id].clone(), vector.to_string()); }
+                [scalar] if scalar.starts_with(['0', '1']) => {
+                    let (level, id) = scalar.split_at(1);
+                    values.insert(names[id].clone(), level.to_string());
+                }
+                _ => {}
+            }
+        }
+        if let Some((_, last)) = states.last_mut() {
+            *last = values;
+        }
+        Wave { states }
+    }
+
+    // The signal during the first or second half of a T-state
+    fn at(&self, t_state: u64, first_half: bool, name: &str) -> &str {
+        let time = t_state * T + if first_half { 0 } else { T / 2 };
+        let (_, values) = self.states.iter().rev().find(|(at, _)| *at <= time).unwrap();
+        &values[name]
+    }
+
+    // IO/M, S1 and S0 during a T-state
+    fn status(&self, t_state: u64) -> String {
+        ["IO_M", "S1", "S0"].map(|name| self.at(t_state, true, name)).concat()
+    }
+}
+
+fn bits(value: u8) -> String {
+    format!("b{value:08b}")
+}
+
+fn find(cycles: &[BusCycle], kind: MachineCycle) -> BusCycle {
+    *cycles.iter().find(|c| c.kind == kind).unwrap()
+}
+
+#[test]
+fn header_declares_every_pin() {
+    let text = vcd(&cycles(), 1_000_000);
+    let header = text.split("$enddefinitions $end").next().unwrap();
+    assert!(header.contains("$timescale 1ps $end"));
+    for (width, name) in [
+        (1, "CLK"), (1, "ALE"), (1, "IO_M"), (1, "S1"), (1, "S0"),
+        (1, "RD_n"), (1, "WR_n"), (1, "INTA_n"), (8, "AD[7:0]"), (8, "A[15:8]"),
+    ] {
+        let declared = header.lines()
+            .any(|line| line.starts_with(&format!("$var wire {width} ")) && line.ends_with(&format!(" {name} $end")));
+        assert!(declared, "{name}");
+    }
+    let body = text.split("$enddefinitions $end\n").nth(1).unwrap();
+    assert!(body.starts_with("#0\n$dumpvars\n"));
+}
+
+#[test]
+fn timestamps_follow_the_clock() {
+    let cycles = cycles();
+    let total = cycles.iter().map(|c| c.t_states as u64).sum::<u64>();
+    for (clock_hz, half_period) in [(1_000_000, 500_000), (2_000_000, 250_000), (3_072_000, 162_760)] {
+        let text = vcd(&cycles, clock_hz);
+        let times = text.lines()
+            .filter_map(|line| line.strip_prefix('#'))
+            .map(|time| time.parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(times[1], half_period, "{clock_hz} Hz");
+        // Two halves per T-state, and the bus released at the end
+        assert_eq!(times.len() as u64, total * 2 + 1, "{clock_hz} Hz");
+        assert_eq!(*times.last().unwrap(), total * 2 * half_period, "{clock_hz} Hz");
+    }
+}
+
+#[test]
+fn opcode_fetch_reads_then_floats_while_decoding() {
+    let cycles = cycles();
+    let wave = Wave::parse(&vcd(&cycles, 1_000_000));
+    // PUSH B, the fetch with six T-states
+    let fetch = *cycles.iter().find(|c| c.kind == MachineCycle::OpcodeFetch && c.t_states == 6).unwrap();
+    assert_eq!(fetch.data, 0xC5);
+    let t1 = fetch.start;
+
+    assert_eq!(wave.status(t1), "011");
+    assert_eq!(wave.at(t1, true, "CLK"), "1");
+    assert_eq!(wave.at(t1, false, "CLK"), "0");
+    // ALE only in the first half of T1, with the low address byte on AD
+    assert_eq!(wave.at(t1, true, "ALE"), "1");
+    assert_eq!(wave.at(t1, false, "ALE"), "0");
+    assert_eq!(wave.at(t1, true, "AD[7:0]"), bits(fetch.addr as u8));
+    assert_eq!(wave.at(t1, true, "A[15:8]"), bits((fetch.addr >> 8) as u8));
+    assert_eq!(wave.at(t1, true, "RD_n"), "1");
+    // RD low in T2 and T3, the opcode valid in T3
+    for t in [t1 + 1, t1 + 2] {
+        assert_eq!(wave.at(t, true, "RD_n"), "0");
+        assert_eq!(wave.at(t, false, "RD_n"), "0");
+        assert_eq!(wave.at(t, true, "WR_n"), "1");
+        assert_eq!(wave.at(t, true, "ALE"), "0");
+    }
+    assert_eq!(wave.at(t1 + 1, true, "AD[7:0]"), "bzzzzzzzz");
+    assert_eq!(wave.at(t1 + 2, true, "AD[7:0]"), bits(0xC5));
+    // T4-T6: the bus floats with the status kept
+    for t in t1 + 3..t1 + 6 {
+        assert_eq!(wave.at(t, true, "AD[7:0]"), "bzzzzzzzz", "T{}", t - t1 + 1);
+        assert_eq!(wave.at(t, true, "RD_n"), "1", "T{}", t - t1 + 1);
+        assert_eq!(wave.at(t, true, "A[15:8]"), bits((fetch.addr >> 8) as u8), "T{}", t - t1 + 1);
+        assert_eq!(wave.status(t), "011", "T{}", t - t1 + 1);
+    }
+}
+
+#[test]
+fn reads_and_writes_strobe_in_t2_and_t3() {
+    let cycles = cycles();
+    let wave = Wave::parse(&vcd(&cycles, 1_000_000));
+    let cases = [
+        (MachineCycle::MemRead, "010", "RD_n"),
+        (MachineCycle::MemWrite, "001", "WR_n"),
+        (MachineCycle::IoRead, "110", "RD_n"),
+        (MachineCycle::IoWrite, "101", "WR_n"),
+    ];
+    for (kind, status, strobe) in cases {
+        let cycle = find(&cycles, kind);
+        let t1 = cycle.start;
+        let other = if strobe == "RD_n" { "WR_n" } else { "RD_n" };
+        for t in t1..t1 + 3 {
+            assert_eq!(wave.status(t), status, "{kind:?}");
+            assert_eq!(wave.at(t, true, "INTA_n"), "1", "{kind:?}");
+            assert_eq!(wave.at(t, true, other), "1", "{kind:?}");
+        }
+        assert_eq!(wave.at(t1, true, strobe), "1", "{kind:?}");
+        assert_eq!(wave.at(t1 + 1, true, strobe), "0", "{kind:?}");
+        assert_eq!(wave.at(t1 + 2, false, strobe), "0", "{kind:?}");
+        assert_eq!(wave.at(t1, true, "AD[7:0]"), bits(cycle.addr as u8), "{kind:?}");
+        // Writes drive the data from T2, reads get it in T3
+        let t2 = if kind.is_write() { bits(cycle.data) } else { "bzzzzzzzz".to_string() };
+        assert_eq!(wave.at(t1 + 1, true, "AD[7:0]"), t2, "{kind:?}");
+        assert_eq!(wave.at(t1 + 2, true, "AD[7:0]"), bits(cycle.data), "{kind:?}");
+    }
+
+    // STA 2000h writes A, IO cycles carry the port on both address halves
+    let write = find(&cycles, MachineCycle::MemWrite);
+    assert_eq!((write.addr, write.data), (0x2000, 0x5A));
+    let out = find(&cycles, MachineCycle::IoWrite);
+    assert_eq!(wave.at(out.start, true, "A[15:8]"), bits(0x20));
+    assert_eq!(wave.at(out.start, true, "AD[7:0]"), bits(0x20));
+}
+
+#[test]
+fn bus_idle_drives_nothing() {
+    let cycles = cycles();
+    let wave = Wave::parse(&vcd(&cycles, 1_000_000));
+    let idle = cycles.iter().filter(|c| c.kind == MachineCycle::BusIdle).collect::<Vec<_>>();
+    // DAD: a four T-state fetch and six more T-states of internal work
+    assert_eq!(idle.len(), 2);
+    for cycle in idle {
+        for t in cycle.start..cycle.start + cycle.t_states as u64 {
+            assert_eq!(wave.status(t), "000");
+            for strobe in ["ALE", "RD_n", "WR_n", "INTA_n"] {
+                let level = if strobe == "ALE" { "0" } else { "1" };
+                assert_eq!(wave.at(t, true, strobe), level, "{strobe}");
+            }
+            assert_eq!(wave.at(t, true, "AD[7:0]"), "bzzzzzzzz");
+            assert_eq!(wave.at(t, true, "A[15:8]"), "bzzzzzzzz");
+        }
+    }
+}
+
+#[test]
+fn inta_takes_the_opcode_without_rd() {
+    let cycles = cycles();
+    let wave = Wave::parse(&vcd(&cycles, 1_000_000));
+    let inta = find(&cycles, MachineCycle::Inta);
+    assert_eq!((inta.data, inta.t_states), (0xFF, 6));
+    let t1 = inta.start;
+
+    assert_eq!(wave.status(t1), "111");
+    assert_eq!(wave.at(t1, true, "ALE"), "1");
+    for t in [t1 + 1, t1 + 2] {
+        assert_eq!(wave.at(t, true, "INTA_n"), "0");
+        assert_eq!(wave.at(t, false, "INTA_n"), "0");
+        assert_eq!(wave.at(t, true, "RD_n"), "1");
+    }
+    assert_eq!(wave.at(t1 + 2, true, "AD[7:0]"), bits(0xFF));
+    assert_eq!(wave.at(t1 + 3, true, "INTA_n"), "1");
+    assert_eq!(wave.at(t1 + 3, true, "AD[7:0]"), "bzzzzzzzz");
+
+    // The return address pushed by RST 7 follows
+    let pushes = cycles.iter().skip_while(|c| c.kind != MachineCycle::Inta).skip(1).collect::<Vec<_>>();
+    assert_eq!(pushes.iter().map(|c| c.kind).collect::<Vec<_>>(), [MachineCycle::MemWrite; 2]);
+    assert_eq!(wave.at(pushes[0].start + 1, true, "WR_n"), "0");
+}
+
+#[test]
+fn bus_is_released_after_the_last_cycle() {
+    let cycles = cycles();
+    let wave = Wave::parse(&vcd(&cycles, 1_000_000));
+    let last = cycles.last().unwrap();
+    let end = last.start + last.t_states as u64;
+    assert_eq!(wave.at(end, true, "CLK"), "1");
+    assert_eq!(wave.status(end), "000");
+    assert_eq!(wave.at(end, true, "WR_n"), "1");
+    assert_eq!(wave.at(end, true, "AD[7:0]"), "bzzzzzzzz");
+    assert_eq!(wave.at(end, true, "A[15:8]"), "bzzzzzzzz");
+}